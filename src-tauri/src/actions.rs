@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+
+/// what happens with the answer of an action
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionTarget {
+    /// send the prompt to the main chat window
+    Chat,
+    /// answer in a small window next to the selection
+    InlineAnswer,
+    /// overwrite the selection with the answer
+    Replace,
+    /// copy the selection without asking the model
+    Copy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectAction {
+    pub id: String,
+    pub label: String,
+    /// name of the icon the popup renders
+    pub icon: String,
+    pub prompt: String,
+    pub target: ActionTarget,
+    pub enabled: bool,
+    /// always ask the model, even when the response cache is on
+    #[serde(default)]
+    pub bypass_cache: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSelectAction {
+    pub label: String,
+    pub icon: String,
+    pub prompt: String,
+    pub target: ActionTarget,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub bypass_cache: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+pub fn default_actions() -> Vec<SelectAction> {
+    fn action(
+        id: &str,
+        label: &str,
+        icon: &str,
+        prompt: &str,
+        target: ActionTarget,
+    ) -> SelectAction {
+        SelectAction {
+            id: id.to_string(),
+            label: label.to_string(),
+            icon: icon.to_string(),
+            prompt: prompt.to_string(),
+            target,
+            enabled: true,
+            bypass_cache: false,
+        }
+    }
+    vec![
+        action("ask", "提问", "EmojiPeople", "", ActionTarget::Chat),
+        action(
+            "explain",
+            "解释",
+            "Comment",
+            "请帮我解释这段文字：",
+            ActionTarget::Chat,
+        ),
+        action(
+            "translate",
+            "翻译",
+            "Compare",
+            "请帮我翻译这段文字：",
+            ActionTarget::Chat,
+        ),
+        action(
+            "polish",
+            "润色",
+            "Brush",
+            "帮我美化或者优化这段文字：",
+            ActionTarget::Chat,
+        ),
+        action("copy", "复制", "FileCopy", "", ActionTarget::Copy),
+    ]
+}
+
+fn actions_path() -> Option<std::path::PathBuf> {
+    crate::app_config::app_config_dir().map(|dir| dir.join("actions.json"))
+}
+
+/// actions saved next to `config.json`, or the built-in ones on first start
+pub fn load_actions() -> Vec<SelectAction> {
+    let content = match actions_path() {
+        Some(path) if path.exists() => std::fs::read_to_string(path),
+        _ => return default_actions(),
+    };
+    match content.map(|content| serde_json::from_str::<Vec<SelectAction>>(&content)) {
+        Ok(Ok(actions)) => actions,
+        Ok(Err(err)) => {
+            tracing::warn!(parse_actions_error = ?err);
+            default_actions()
+        }
+        Err(err) => {
+            tracing::warn!(read_actions_error = ?err);
+            default_actions()
+        }
+    }
+}
+
+pub fn save_actions(actions: &[SelectAction]) -> Result<(), String> {
+    let path = actions_path().ok_or("not found app config directory")?;
+    let content = serde_json::to_string_pretty(actions)
+        .map_err(|err| format!("serialize actions error {}", err))?;
+    std::fs::write(path, content).map_err(|err| format!("failed to write actions {}", err))
+}
+
+fn validate(label: &str, prompt: &str) -> Result<(), String> {
+    if label.trim().is_empty() {
+        return Err("action label can't be empty".to_string());
+    }
+    crate::prompt_template::Template::parse(prompt)
+        .map_err(|err| format!("invalid prompt template: {}", err))?;
+    Ok(())
+}
+
+fn new_action_id(actions: &[SelectAction]) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let mut id = format!("action-{:x}", nanos);
+    while actions.iter().any(|action| action.id == id) {
+        id.push('0');
+    }
+    id
+}
+
+pub fn create_action(
+    actions: &mut Vec<SelectAction>,
+    new_action: NewSelectAction,
+) -> Result<SelectAction, String> {
+    validate(&new_action.label, &new_action.prompt)?;
+    let action = SelectAction {
+        id: new_action_id(actions),
+        label: new_action.label,
+        icon: new_action.icon,
+        prompt: new_action.prompt,
+        target: new_action.target,
+        enabled: new_action.enabled,
+        bypass_cache: new_action.bypass_cache,
+    };
+    actions.push(action.clone());
+    Ok(action)
+}
+
+pub fn update_action(actions: &mut [SelectAction], action: SelectAction) -> Result<(), String> {
+    validate(&action.label, &action.prompt)?;
+    let existing = actions
+        .iter_mut()
+        .find(|existing| existing.id == action.id)
+        .ok_or_else(|| format!("not found action {}", action.id))?;
+    *existing = action;
+    Ok(())
+}
+
+pub fn delete_action(actions: &mut Vec<SelectAction>, id: &str) -> Result<(), String> {
+    let index = actions
+        .iter()
+        .position(|action| action.id == id)
+        .ok_or_else(|| format!("not found action {}", id))?;
+    actions.remove(index);
+    Ok(())
+}
+
+/// `ids` must list every action exactly once
+pub fn reorder_actions(actions: &mut Vec<SelectAction>, ids: &[String]) -> Result<(), String> {
+    if ids.len() != actions.len() {
+        return Err("reorder must list every action".to_string());
+    }
+    let mut reordered = Vec::with_capacity(actions.len());
+    for id in ids {
+        let index = actions
+            .iter()
+            .position(|action| &action.id == id)
+            .ok_or_else(|| format!("not found action {}", id))?;
+        reordered.push(actions.remove(index));
+    }
+    *actions = reordered;
+    Ok(())
+}
+
+pub const ACTIONS_CHANGED_EVENT: &str = "actions-changed";
+
+/// apply `change` to the registry, persist it and tell the windows about it
+pub fn modify_actions<T>(
+    handle: &tauri::AppHandle,
+    change: impl FnOnce(&mut Vec<SelectAction>) -> Result<T, String>,
+) -> Result<T, String> {
+    use tauri::Manager;
+    let state: tauri::State<crate::AppState> = handle.state();
+    let mut actions = state.actions.write();
+    let mut changed = actions.clone();
+    let result = change(&mut changed)?;
+    save_actions(&changed)?;
+    *actions = changed;
+    if let Err(err) = handle.emit_all(ACTIONS_CHANGED_EVENT, actions.clone()) {
+        tracing::warn!(emit_actions_changed_error = ?err);
+    }
+    Ok(result)
+}
+
+pub fn find_action(handle: &tauri::AppHandle, id: &str) -> Option<SelectAction> {
+    use tauri::Manager;
+    let state: tauri::State<crate::AppState> = handle.state();
+    let actions = state.actions.read();
+    actions.iter().find(|action| action.id == id).cloned()
+}
@@ -1,6 +1,11 @@
 use serde::{Deserialize, Serialize};
 use tauri::api::path::config_dir;
 
+/// Shift-only defaults of older versions, taken from every app's typing and
+/// now rejected, see `shortcut::normalize_accelerator`
+const OLD_QUICK_ASK_SHORTCUT: &str = "Shift+Q";
+const OLD_CHAT_SHORTCUT: &str = "Shift+C";
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AppConfig {
@@ -11,10 +16,34 @@ pub struct AppConfig {
     pub is_dark_mode: bool,
     pub language: String,
     pub api_key: Option<String>,
+    /// `http://`, `https://` or `socks5://` proxy for every outbound request
+    /// but the updater's, see `http_client::client`
     pub proxy: Option<String>,
+    pub proxy_username: Option<String>,
+    pub proxy_password: Option<String>,
+    /// hosts that bypass the proxy, `NO_PROXY` of the environment when missing
+    pub no_proxy: Option<String>,
+    /// PEM files with extra root certificates
+    pub ca_certs: Option<Vec<String>>,
     pub use_chat_context: bool,
     pub enable_select: Option<bool>,
     pub message_context_count: Option<i32>,
+    /// hotkeys that run an action on the selection
+    pub shortcut_bindings: Option<Vec<crate::shortcut::ShortcutBinding>>,
+    /// takes back the text auto input typed last
+    pub undo_insertion_shortcut: Option<String>,
+    /// model apis to choose from, see `llm::profile`
+    pub llm_profiles: Option<Vec<crate::llm::profile::LlmProfile>>,
+    pub default_llm_profile: Option<String>,
+    /// how failed model calls are retried
+    pub llm_retry: Option<crate::llm::retry::RetryConfig>,
+    /// dollars per million tokens by model, list prices when missing
+    pub llm_pricing: Option<Vec<crate::llm::usage::ModelPrice>>,
+    pub llm_budget: Option<crate::llm::usage::BudgetConfig>,
+    /// answers of selection actions kept on disk, off by default
+    pub response_cache: Option<crate::llm::cache::CacheConfig>,
+    /// how auto input types into other apps
+    pub typing: Option<crate::easy_thing::input::TypingConfig>,
 }
 
 impl Default for AppConfig {
@@ -25,21 +54,60 @@ impl Default for AppConfig {
         let enable_select = true;
 
         Self {
-            quick_ask_shortcut: Some("Shift+Q".to_string()),
+            quick_ask_shortcut: Some("Alt+Shift+Q".to_string()),
             search_shortcut: Some("CommandOrControl+Shift+Space".to_string()),
-            chat_shortcut: Some("Shift+C".to_string()),
+            chat_shortcut: Some("Alt+Shift+C".to_string()),
             mode: Some("快捷提问".to_string()),
             is_dark_mode: true,
             language: "zh-cn".to_string(),
             api_key: None,
             proxy: None,
+            proxy_username: None,
+            proxy_password: None,
+            no_proxy: None,
+            ca_certs: None,
             use_chat_context: true,
             enable_select: Some(enable_select),
             message_context_count: Some(6),
+            shortcut_bindings: None,
+            undo_insertion_shortcut: None,
+            llm_profiles: None,
+            default_llm_profile: None,
+            llm_retry: None,
+            llm_pricing: None,
+            llm_budget: None,
+            response_cache: None,
+            typing: None,
         }
     }
 }
 
+impl AppConfig {
+    /// Move the old default shortcuts to the current ones, true if any was.
+    pub fn migrate(&mut self) -> bool {
+        let defaults = AppConfig::default();
+        let mut migrated = false;
+        for (shortcut, old, new) in [
+            (
+                &mut self.quick_ask_shortcut,
+                OLD_QUICK_ASK_SHORTCUT,
+                defaults.quick_ask_shortcut,
+            ),
+            (
+                &mut self.chat_shortcut,
+                OLD_CHAT_SHORTCUT,
+                defaults.chat_shortcut,
+            ),
+        ] {
+            if shortcut.as_deref() == Some(old) {
+                *shortcut = new;
+                migrated = true;
+            }
+        }
+        migrated
+    }
+}
+
 #[tauri::command]
 pub fn get_app_config_json() -> String {
     if let Some(config_dir) = config_dir() {
@@ -68,7 +136,16 @@ pub fn get_app_config_json() -> String {
 
 pub fn get_app_config() -> Result<AppConfig, Box<dyn std::error::Error>> {
     let config_content = get_app_config_json();
-    let config: AppConfig = serde_json::from_str(&config_content)?;
+    let mut config: AppConfig = serde_json::from_str(&config_content)?;
+    if config.migrate() {
+        tracing::info!(
+            migrated_quick_ask_shortcut =? config.quick_ask_shortcut,
+            migrated_chat_shortcut =? config.chat_shortcut
+        );
+        if let Err(err) = save_app_config(&config) {
+            tracing::warn!(save_migrated_app_config_error = err);
+        }
+    }
     Ok(config)
 }
 
@@ -125,3 +202,48 @@ pub fn get_local_server_port() -> anyhow::Result<Option<u16>> {
         Err(anyhow::anyhow!("not found app config path"))
     }
 }
+
+/// `config.quick-ai` directory, created on first use
+pub fn app_config_dir() -> Option<std::path::PathBuf> {
+    let app_config_dir = config_dir()?.join("config.quick-ai");
+    if !app_config_dir.exists() {
+        std::fs::create_dir_all(&app_config_dir).ok()?;
+    }
+    Some(app_config_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_default_shortcuts_are_migrated() {
+        let mut app_config = AppConfig {
+            quick_ask_shortcut: Some(OLD_QUICK_ASK_SHORTCUT.to_string()),
+            search_shortcut: Some("Shift+S".to_string()),
+            chat_shortcut: Some(OLD_CHAT_SHORTCUT.to_string()),
+            ..AppConfig::default()
+        };
+        assert!(app_config.migrate());
+        assert_eq!(
+            app_config.quick_ask_shortcut.as_deref(),
+            Some("Alt+Shift+Q")
+        );
+        assert_eq!(app_config.chat_shortcut.as_deref(), Some("Alt+Shift+C"));
+        // only the old defaults move, a shortcut the user picked is reported
+        assert_eq!(app_config.search_shortcut.as_deref(), Some("Shift+S"));
+        assert!(!app_config.migrate());
+    }
+
+    #[test]
+    fn own_shortcuts_are_kept() {
+        let mut app_config = AppConfig {
+            quick_ask_shortcut: Some("Ctrl+Q".to_string()),
+            chat_shortcut: None,
+            ..AppConfig::default()
+        };
+        assert!(!app_config.migrate());
+        assert_eq!(app_config.quick_ask_shortcut.as_deref(), Some("Ctrl+Q"));
+        assert_eq!(app_config.chat_shortcut, None);
+    }
+}
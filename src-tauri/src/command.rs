@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, LogicalSize, Manager, Size, State, Window};
-use tokio::sync::mpsc::UnboundedSender;
 
+use crate::easy_thing::auto_input::{AutoInputJob, AutoInputQueue};
+
+use crate::tauri_windows::manager::{WindowKind, WindowManager};
 use crate::AppState;
 
 #[tauri::command]
@@ -38,75 +40,216 @@ pub struct SetSizePayload {
 }
 
 #[tauri::command]
-pub fn set_size(window: Window, payload: SetSizePayload) {
+pub fn set_size(window: Window, payload: SetSizePayload) -> Result<(), String> {
     tracing::info!(payload =? payload);
+    let limits = crate::tauri_windows::geometry::size_limits(window.label())
+        .ok_or(format!("window {} can't be resized", window.label()))?;
+    limits
+        .check(payload.width as f64, payload.height as f64)
+        .map_err(|err| format!("{}", err))?;
     window
         .set_size(Size::Logical(LogicalSize::new(
             payload.width as f64,
             payload.height as f64,
         )))
-        .unwrap();
+        .map_err(|err| format!("set size error {:?}", err))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct AutoInput {
     response: String,
 }
 
+/// the answer so far of a job from `begin_auto_input`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoInputValue {
+    job_id: u64,
+    response: String,
+}
+
 #[tauri::command]
 pub async fn run_auto_input(
     handle: AppHandle,
     window: Window,
     payload: AutoInput,
-) -> Result<(), String> {
+) -> Result<AutoInputJob, String> {
     tracing::info!(payload =? payload);
     window.hide().map_err(|err| format!("{:?}", err))?;
-    crate::easy_thing::send_auto_input_value(&handle, payload.response)?;
-    Ok(())
+    crate::easy_thing::insert_text(&handle, payload.response).await
+}
+
+/// start a job for an answer that is still streaming, typed into the window
+/// of the current selection
+#[tauri::command]
+pub fn begin_auto_input(handle: AppHandle) -> AutoInputJob {
+    crate::easy_thing::begin_auto_input(&handle)
 }
 
 #[tauri::command]
-pub async fn send_auto_input_value(handle: AppHandle, payload: AutoInput) -> Result<(), String> {
+pub async fn send_auto_input_value(
+    handle: AppHandle,
+    payload: AutoInputValue,
+) -> Result<(), String> {
     tracing::info!(payload =? payload);
-    crate::easy_thing::send_auto_input_value(&handle, payload.response)?;
-    Ok(())
+    crate::easy_thing::send_auto_input_value(&handle, payload.job_id, payload.response).await
 }
 
-/// get or init input sender which sends content to other windows
-pub fn get_or_init_auto_input<'a>(
-    state: &'a tauri::State<crate::AppState>,
-) -> &'a UnboundedSender<String> {
-    let answer_sender = state.auto_input_sender.get_or_init(|| {
-        let (answer_sender, mut answer_receiver) = tokio::sync::mpsc::unbounded_channel::<String>();
-        let _ = state.spawn_future(async move {
-            let mut content = String::new();
-            while let Some(answer) = answer_receiver.recv().await {
-                if let Some(suffix) = answer.strip_prefix(&content) {
-                    tracing::info!(split_suffix = true);
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    // crate::easy_thing::input::PlatformInput::send_content_v2(suffix);
-                    if let Err(err) =
-                        crate::easy_thing::input::CrossformInput::auto_input_text_using_copy(suffix)
-                    {
-                        tracing::warn!(err =? err);
-                    }
-                } else {
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    tracing::info!(split_suffix = false);
-                    if let Err(err) =
-                        crate::easy_thing::input::CrossformInput::auto_input_text_using_copy(
-                            &answer,
-                        )
-                    {
-                        tracing::warn!(err =? err);
-                    }
-                }
-                content = answer
-            }
-        });
-        answer_sender
-    });
-    answer_sender
+/// no more values for the job, it is done once everything is typed
+#[tauri::command]
+pub async fn end_auto_input(handle: AppHandle, payload: u64) -> Result<(), String> {
+    crate::easy_thing::end_auto_input(&handle, payload).await
+}
+
+/// get or init the queue of text typed into other windows
+pub fn get_or_init_auto_input<'a>(state: &'a tauri::State<crate::AppState>) -> &'a AutoInputQueue {
+    state.auto_input.get_or_init(|| {
+        let (queue, worker) = AutoInputQueue::new(Box::new(crate::easy_thing::LiveAutoInputHost));
+        // typing sleeps and presses keys between its awaits
+        let _ = state.spawn_blocking_future(worker);
+        queue
+    })
+}
+
+#[tauri::command]
+pub fn list_auto_input_jobs(handle: AppHandle) -> Vec<AutoInputJob> {
+    crate::easy_thing::list_auto_input_jobs(&handle)
+}
+
+#[tauri::command]
+pub fn pause_auto_input_job(handle: AppHandle, payload: u64) -> Result<AutoInputJob, String> {
+    crate::easy_thing::control_auto_input_job(&handle, payload, AutoInputQueue::pause)
+}
+
+#[tauri::command]
+pub fn resume_auto_input_job(handle: AppHandle, payload: u64) -> Result<AutoInputJob, String> {
+    crate::easy_thing::control_auto_input_job(&handle, payload, AutoInputQueue::resume)
+}
+
+#[tauri::command]
+pub fn cancel_auto_input_job(handle: AppHandle, payload: u64) -> Result<AutoInputJob, String> {
+    crate::easy_thing::control_auto_input_job(&handle, payload, AutoInputQueue::cancel)
+}
+
+/// take back the text auto input typed last
+#[tauri::command]
+pub async fn undo_last_insertion(
+    handle: AppHandle,
+) -> Result<crate::easy_thing::auto_input::Insertion, String> {
+    // waits for the window and presses keys, keep it off the async executors
+    tauri::async_runtime::spawn_blocking(move || crate::easy_thing::undo_last_insertion(&handle))
+        .await
+        .map_err(|err| format!("{:?}", err))?
+        .map_err(|err| format!("undo last insertion error {:?}", err))
+}
+
+/// Stream an answer from an OpenAI compatible api. Pieces of it are sent as
+/// `llm-token` events, the whole answer is returned.
+#[tauri::command]
+pub async fn chat_completion(
+    handle: AppHandle,
+    payload: crate::llm::ChatCompletionPayload,
+) -> Result<String, crate::llm::LlmError> {
+    crate::llm::chat_completion(&handle, payload).await
+}
+
+#[tauri::command]
+pub fn count_tokens(payload: crate::llm::CountTokensPayload) -> usize {
+    payload.count()
+}
+
+/// the messages a request would send and what was left out
+#[tauri::command]
+pub fn build_chat_context(
+    payload: crate::llm::BuildContextPayload,
+) -> crate::llm::context::ContextReport {
+    payload.build()
+}
+
+#[tauri::command]
+pub fn list_llm_profiles() -> Vec<crate::llm::profile::LlmProfileSummary> {
+    crate::llm::profile::ProviderRegistry::load().summaries()
+}
+
+#[tauri::command]
+pub fn get_response_cache_stats(state: State<AppState>) -> crate::llm::cache::CacheStats {
+    let config = crate::llm::cache::CacheConfig::load();
+    state.response_cache.stats(&config)
+}
+
+#[tauri::command]
+pub fn list_cached_responses(state: State<AppState>) -> Vec<crate::llm::cache::CacheEntry> {
+    let config = crate::llm::cache::CacheConfig::load();
+    state.response_cache.list(&config)
+}
+
+/// drop the cached answer with this key, or all of them without one
+#[tauri::command]
+pub fn clear_response_cache(state: State<AppState>, payload: Option<String>) -> usize {
+    tracing::info!(clear_response_cache =? payload);
+    state.response_cache.clear(payload.as_deref())
+}
+
+/// tokens and cost grouped by day, model, action or provider
+#[tauri::command]
+pub fn query_llm_usage(
+    state: State<AppState>,
+    payload: crate::llm::usage::UsageQuery,
+) -> Vec<crate::llm::usage::UsageTotal> {
+    state.llm_usage.totals(&payload)
+}
+
+/// spending of the configured budgets, empty without any
+#[tauri::command]
+pub fn get_llm_budget_status(state: State<AppState>) -> Vec<crate::llm::usage::BudgetStatus> {
+    crate::llm::usage::BudgetConfig::load()
+        .map(|budget| state.llm_usage.budget_status(&budget))
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn get_llm_pricing() -> Vec<crate::llm::usage::ModelPrice> {
+    crate::llm::usage::pricing()
+}
+
+#[tauri::command]
+pub async fn check_need_update(payload: crate::remote::NeedUpdatePayload) -> Result<bool, String> {
+    crate::remote::check_need_update(payload)
+        .await
+        .map_err(|err| format!("check need update error {:#}", err))
+}
+
+#[tauri::command]
+pub async fn get_sponsor_ad() -> Result<Option<crate::remote::SponsorAd>, String> {
+    crate::remote::get_sponsor_ad()
+        .await
+        .map_err(|err| format!("get sponsor ad error {:#}", err))
+}
+
+#[tauri::command]
+pub async fn list_sponsor_about_banner() -> Result<Vec<crate::remote::SponsorAboutBanner>, String> {
+    crate::remote::list_sponsor_about_banner()
+        .await
+        .map_err(|err| format!("list sponsor about banner error {:#}", err))
+}
+
+/// stop the request with this id, `chat_completion` fails with `cancelled`
+#[tauri::command]
+pub fn cancel_chat_completion(state: State<AppState>, payload: String) -> bool {
+    state.llm_requests.cancel(&payload)
+}
+
+/// resume every job that was paused
+#[tauri::command]
+pub fn resume_auto_input(handle: AppHandle) {
+    crate::easy_thing::resume_auto_input(&handle);
+}
+
+/// stop typing right away and cancel every job still queued
+#[tauri::command]
+pub fn cancel_auto_input(handle: AppHandle) {
+    crate::easy_thing::cancel_auto_input(&handle);
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -126,25 +269,70 @@ pub async fn run_quick_answer(
     } else {
         Some(payload.question)
     };
-    //crate::tauri_windows::chatgpt::show_quick_answer_window(&handle, question, true);
+    crate::tauri_windows::quick_answer::show_quick_answer_window(&handle, question)
+        .map_err(|err| format!("show quick answer window error {:?}", err))?;
     window.hide().map_err(|err| format!("{:?}", err))?;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn run_chat_mode(window: Window, payload: QuestionPayload) -> Result<(), String> {
+pub fn get_quick_answer_question(
+    handle: AppHandle,
+) -> crate::tauri_windows::quick_answer::QuickAnswerQuestion {
+    let state: State<AppState> = handle.state();
+    let question = state.quick_answer_question.read().clone();
+    question
+}
+
+/// type the quick answer into the app the selection came from
+#[tauri::command]
+pub async fn insert_quick_answer(handle: AppHandle, payload: AutoInput) -> Result<(), String> {
+    tracing::info!(insert_quick_answer =? payload);
+    crate::tauri_windows::quick_answer::hide_quick_answer_window(&handle)
+        .map_err(|err| format!("{:?}", err))?;
+    crate::easy_thing::restore_foreground_window(&handle);
+    crate::easy_thing::insert_text(&handle, payload.response).await?;
+    Ok(())
+}
+
+/// paste the quick answer over the text that is still selected in the source app
+#[tauri::command]
+pub async fn replace_selection(handle: AppHandle, payload: String) -> Result<(), String> {
+    tracing::info!(replace_selection = payload);
+    crate::tauri_windows::quick_answer::hide_quick_answer_window(&handle)
+        .map_err(|err| format!("{:?}", err))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        crate::easy_thing::replace_selection(&handle, payload)
+    })
+    .await
+    .map_err(|err| format!("{:?}", err))?
+    .map_err(|err| format!("replace selection error {:?}", err))
+}
+
+#[tauri::command]
+pub async fn open_quick_answer_in_chat(
+    handle: AppHandle,
+    payload: Vec<crate::tauri_windows::quick_answer::ChatMessage>,
+) -> Result<(), String> {
+    crate::tauri_windows::quick_answer::open_in_chat(&handle, payload)
+        .map_err(|err| format!("open quick answer in chat error {:?}", err))
+}
+
+#[tauri::command]
+pub async fn run_chat_mode(
+    handle: AppHandle,
+    window: Window,
+    payload: QuestionPayload,
+) -> Result<(), String> {
     tracing::info!(payload =? payload);
     let question = if payload.question.is_empty() {
         None
     } else {
         Some(payload.question)
     };
-    let error = std::panic::catch_unwind(|| {
-        //crate::tauri_windows::chat::show_chat_windows(question);
-    });
-    if let Err(error) = error {
-        tracing::warn!(panic =? error);
-    } else {
+    WindowManager::show_chat(&handle, question)
+        .map_err(|err| format!("show chat window error {:?}", err))?;
+    if window.label() != WindowKind::Chat.label() {
         window.hide().map_err(|err| format!("{:?}", err))?;
     }
     Ok(())
@@ -158,8 +346,17 @@ pub async fn close_window(window: Window) {
 }
 
 #[tauri::command]
-pub async fn open_setting_window(handle: AppHandle) {
-    //crate::tauri_windows::settings::build_setting_window(&handle);
+pub async fn open_setting_window(handle: AppHandle) -> Result<(), String> {
+    WindowManager::show(&handle, WindowKind::Settings)
+        .map(|_| ())
+        .map_err(|err| format!("open setting window error {:?}", err))
+}
+
+/// show or hide one of the managed windows by label
+#[tauri::command]
+pub async fn toggle_window(handle: AppHandle, payload: String) -> Result<(), String> {
+    let kind = WindowKind::from_label(&payload).ok_or(format!("unknown window {}", payload))?;
+    WindowManager::toggle(&handle, kind).map_err(|err| format!("toggle window error {:?}", err))
 }
 
 #[cfg(not(target_os = "macos"))]
@@ -175,17 +372,106 @@ pub async fn copy_select_content(payload: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub fn update_shortcut() -> Result<(), String> {
+pub fn update_shortcut() -> Result<crate::shortcut::ShortcutReport, String> {
     let handle = crate::APP.get().ok_or("can't get app handle")?;
-    // crate::shortcut::ShortcutRegister::register_shortcut(handle)
-    //     .map_err(|err| format!("register short cut error :{}", err))?;
+    register_shortcuts(handle)
+}
+
+fn register_shortcuts(handle: &AppHandle) -> Result<crate::shortcut::ShortcutReport, String> {
+    let report = crate::shortcut::ShortcutRegister::register_shortcut(handle)
+        .map_err(|err| format!("register short cut error :{}", err))?;
+    let state: State<AppState> = handle.state();
+    *state.shortcut_report.write() = report.clone();
+    Ok(report)
+}
+
+/// hotkey bindings point at actions, bind them again when those change
+fn rebind_action_shortcuts(handle: &AppHandle) {
+    if let Err(err) = register_shortcuts(handle) {
+        tracing::warn!(rebind_action_shortcuts_error = err);
+    }
+}
+
+#[tauri::command]
+pub fn get_shortcut_report(state: State<AppState>) -> crate::shortcut::ShortcutReport {
+    state.shortcut_report.read().clone()
+}
+
+#[tauri::command]
+pub fn list_actions(handle: AppHandle) -> Vec<crate::actions::SelectAction> {
+    let state: State<AppState> = handle.state();
+    let actions = state.actions.read().clone();
+    actions
+}
+
+#[tauri::command]
+pub fn create_action(
+    handle: AppHandle,
+    payload: crate::actions::NewSelectAction,
+) -> Result<crate::actions::SelectAction, String> {
+    tracing::info!(create_action =? payload);
+    let action = crate::actions::modify_actions(&handle, |actions| {
+        crate::actions::create_action(actions, payload)
+    })?;
+    rebind_action_shortcuts(&handle);
+    Ok(action)
+}
+
+#[tauri::command]
+pub fn update_action(
+    handle: AppHandle,
+    payload: crate::actions::SelectAction,
+) -> Result<(), String> {
+    tracing::info!(update_action =? payload);
+    crate::actions::modify_actions(&handle, |actions| {
+        crate::actions::update_action(actions, payload)
+    })?;
+    rebind_action_shortcuts(&handle);
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_app_config(payload: crate::app_config::AppConfig) -> Result<(), String> {
+pub fn delete_action(handle: AppHandle, payload: String) -> Result<(), String> {
+    tracing::info!(delete_action = payload);
+    crate::actions::modify_actions(&handle, |actions| {
+        crate::actions::delete_action(actions, &payload)
+    })?;
+    rebind_action_shortcuts(&handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn reorder_actions(handle: AppHandle, payload: Vec<String>) -> Result<(), String> {
+    tracing::info!(reorder_actions =? payload);
+    crate::actions::modify_actions(&handle, |actions| {
+        crate::actions::reorder_actions(actions, &payload)
+    })
+}
+
+#[tauri::command]
+pub fn validate_prompt_template(payload: String) -> Result<(), String> {
+    crate::prompt_template::Template::parse(&payload)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn update_app_config(
+    payload: crate::app_config::AppConfig,
+) -> Result<crate::shortcut::ShortcutReport, String> {
     tracing::info!(app_config =? payload);
-    crate::app_config::save_app_config(&payload)
+    crate::app_config::save_app_config(&payload)?;
+    let handle = crate::APP.get().ok_or("can't get app handle")?;
+    if let Some(enable_select) = payload.enable_select {
+        crate::tray::set_enable_select(handle, enable_select);
+    }
+    update_shortcut()
+}
+
+/// keep the latest answer so the tray can copy it
+#[tauri::command]
+pub fn remember_answer(handle: AppHandle, payload: String) {
+    crate::tray::remember_answer(&handle, payload);
 }
 
 #[cfg(not(target_os = "macos"))]
@@ -200,3 +486,13 @@ pub async fn trigger_select_click(
         .map_err(|err| format!("trigger select click error {:?}", err))?;
     Ok(())
 }
+
+/// replay a recorded select trace (see `QUICK_AI_SELECT_TRACE`) through the current selection logic
+#[cfg(not(target_os = "macos"))]
+#[tauri::command]
+pub fn replay_select_trace(path: String) -> Result<crate::trace::ReplayReport, String> {
+    let events = crate::trace::read_trace(&path).map_err(|err| format!("{:?}", err))?;
+    let report = crate::trace::replay_trace(&events).map_err(|err| format!("{:?}", err))?;
+    tracing::info!(replay_trace = path, matches = report.matches());
+    Ok(report)
+}
@@ -0,0 +1,268 @@
+pub struct PlatformAccessibility;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::PlatformAccessibility;
+    use crate::select::{Selection, SelectionRect};
+    use anyhow::{anyhow, Result};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::time::{Duration, Instant};
+    use zbus::blocking::{Connection, ConnectionBuilder};
+    use zbus::zvariant::OwnedObjectPath;
+
+    const REGISTRY_BUS_NAME: &str = "org.a11y.atspi.Registry";
+    const REGISTRY_ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+    const ACCESSIBLE_INTERFACE: &str = "org.a11y.atspi.Accessible";
+    const TEXT_INTERFACE: &str = "org.a11y.atspi.Text";
+
+    // AtspiStateType values
+    const STATE_ACTIVE: u32 = 1;
+    const STATE_FOCUSED: u32 = 12;
+    const STATE_SHOWING: u32 = 25;
+    // ATSPI_COORD_TYPE_SCREEN
+    const COORD_TYPE_SCREEN: u32 = 0;
+
+    // browsers expose very large trees, stop walking after this many nodes
+    const MAX_VISITED_NODES: usize = 3000;
+    const MAX_DEPTH: usize = 40;
+    // past this the clipboard is faster than walking on
+    const WALK_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// connection to the accessibility bus, kept until a call on it fails
+    static CONNECTION: Lazy<Mutex<Option<Connection>>> = Lazy::new(|| Mutex::new(None));
+
+    fn connect() -> Result<Connection> {
+        let session = Connection::session()?;
+        let reply = session.call_method(
+            Some("org.a11y.Bus"),
+            "/org/a11y/bus",
+            Some("org.a11y.Bus"),
+            "GetAddress",
+            &(),
+        )?;
+        let address: String = reply.body()?;
+        Ok(ConnectionBuilder::address(address.as_str())?.build()?)
+    }
+
+    fn connection() -> Result<Connection> {
+        let mut cached = CONNECTION.lock();
+        if let Some(connection) = cached.as_ref() {
+            return Ok(connection.clone());
+        }
+        let connection = connect()?;
+        *cached = Some(connection.clone());
+        Ok(connection)
+    }
+
+    fn has_state(states: &[u32], state: u32) -> bool {
+        states
+            .get((state / 32) as usize)
+            .map(|bits| bits & (1 << (state % 32)) != 0)
+            .unwrap_or(false)
+    }
+
+    #[derive(Clone, Debug)]
+    struct Accessible {
+        bus_name: String,
+        path: OwnedObjectPath,
+    }
+
+    struct AtSpi {
+        connection: Connection,
+        visited: usize,
+        deadline: Instant,
+    }
+
+    impl AtSpi {
+        fn new(connection: Connection) -> Self {
+            Self {
+                connection,
+                visited: 0,
+                deadline: Instant::now() + WALK_TIMEOUT,
+            }
+        }
+
+        /// the walk went on long enough, give up on it
+        fn exhausted(&self) -> bool {
+            self.visited > MAX_VISITED_NODES || Instant::now() >= self.deadline
+        }
+
+        fn call<B, R>(
+            &self,
+            accessible: &Accessible,
+            interface: &str,
+            method: &str,
+            body: &B,
+        ) -> Result<R>
+        where
+            B: serde::Serialize + zbus::zvariant::DynamicType,
+            R: serde::de::DeserializeOwned + zbus::zvariant::Type,
+        {
+            let reply = self.connection.call_method(
+                Some(accessible.bus_name.as_str()),
+                accessible.path.as_str(),
+                Some(interface),
+                method,
+                body,
+            )?;
+            Ok(reply.body()?)
+        }
+
+        fn children(&mut self, accessible: &Accessible) -> Result<Vec<Accessible>> {
+            self.visited += 1;
+            let children: Vec<(String, OwnedObjectPath)> =
+                self.call(accessible, ACCESSIBLE_INTERFACE, "GetChildren", &())?;
+            Ok(children
+                .into_iter()
+                .map(|(bus_name, path)| Accessible { bus_name, path })
+                .collect())
+        }
+
+        /// the state bit set of a node, empty when it can't be read
+        fn states(&self, accessible: &Accessible) -> Vec<u32> {
+            self.call(accessible, ACCESSIBLE_INTERFACE, "GetState", &())
+                .unwrap_or_default()
+        }
+
+        /// depth first search for a node with `state`, only descending into showing nodes
+        fn find_with_state(
+            &mut self,
+            root: &Accessible,
+            state: u32,
+            depth: usize,
+        ) -> Option<Accessible> {
+            if depth > MAX_DEPTH || self.exhausted() {
+                return None;
+            }
+            for child in self.children(root).ok()? {
+                let states = self.states(&child);
+                if has_state(&states, state) {
+                    return Some(child);
+                }
+                if !has_state(&states, STATE_SHOWING) {
+                    continue;
+                }
+                if let Some(found) = self.find_with_state(&child, state, depth + 1) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+
+        fn focused(&mut self) -> Result<Accessible> {
+            let registry = Accessible {
+                bus_name: REGISTRY_BUS_NAME.to_string(),
+                path: OwnedObjectPath::try_from(REGISTRY_ROOT_PATH)?,
+            };
+            let applications = match self.children(&registry) {
+                Ok(applications) => applications,
+                Err(err) => {
+                    // the bus went away, connect again next time
+                    CONNECTION.lock().take();
+                    return Err(err);
+                }
+            };
+            for application in applications {
+                // the active window belongs to the focused application
+                let windows = match self.children(&application) {
+                    Ok(windows) => windows,
+                    Err(_) => continue,
+                };
+                for window in windows {
+                    if self.exhausted() {
+                        return Err(anyhow!("accessibility walk timed out"));
+                    }
+                    if !has_state(&self.states(&window), STATE_ACTIVE) {
+                        continue;
+                    }
+                    if let Some(focused) = self.find_with_state(&window, STATE_FOCUSED, 0) {
+                        return Ok(focused);
+                    }
+                }
+            }
+            Err(anyhow!("no focused accessible object"))
+        }
+
+        fn selection_of(&self, accessible: &Accessible) -> Option<Selection> {
+            let count: i32 = self
+                .call(accessible, TEXT_INTERFACE, "GetNSelections", &())
+                .ok()?;
+            if count <= 0 {
+                return None;
+            }
+            let (start, end): (i32, i32) = self
+                .call(accessible, TEXT_INTERFACE, "GetSelection", &(0i32,))
+                .ok()?;
+            if start >= end {
+                return None;
+            }
+            let text: String = self
+                .call(accessible, TEXT_INTERFACE, "GetText", &(start, end))
+                .ok()?;
+            if text.trim().is_empty() {
+                return None;
+            }
+            let bounds = self
+                .call::<_, (i32, i32, i32, i32)>(
+                    accessible,
+                    TEXT_INTERFACE,
+                    "GetRangeExtents",
+                    &(start, end, COORD_TYPE_SCREEN),
+                )
+                .ok()
+                .filter(|(_, _, width, height)| *width > 0 && *height > 0)
+                .map(|(x, y, width, height)| SelectionRect {
+                    x: x as f64,
+                    y: y as f64,
+                    width: width as f64,
+                    height: height as f64,
+                });
+            Some(Selection { text, bounds })
+        }
+
+        /// the focused node, or for documents the first descendant holding a selection
+        fn find_selection(&mut self, root: &Accessible, depth: usize) -> Option<Selection> {
+            if let Some(selection) = self.selection_of(root) {
+                return Some(selection);
+            }
+            if depth > MAX_DEPTH || self.exhausted() {
+                return None;
+            }
+            for child in self.children(root).ok()? {
+                if !has_state(&self.states(&child), STATE_SHOWING) {
+                    continue;
+                }
+                if let Some(selection) = self.find_selection(&child, depth + 1) {
+                    return Some(selection);
+                }
+            }
+            None
+        }
+    }
+
+    impl PlatformAccessibility {
+        pub fn get_selection() -> Result<Selection> {
+            let mut atspi = AtSpi::new(connection()?);
+            let focused = atspi.focused()?;
+            atspi
+                .find_selection(&focused, 0)
+                .ok_or_else(|| anyhow!("focused accessible object has no selection"))
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported {
+    use super::PlatformAccessibility;
+    use crate::select::Selection;
+    use anyhow::{anyhow, Result};
+
+    impl PlatformAccessibility {
+        pub fn get_selection() -> Result<Selection> {
+            Err(anyhow!(
+                "accessibility selection is not supported on this platform"
+            ))
+        }
+    }
+}
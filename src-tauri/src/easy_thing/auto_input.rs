@@ -0,0 +1,871 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Notify};
+
+use super::input::{InputStrategy, TypingSettings, TypingStrategy};
+use super::markdown::{self, InsertFormat};
+
+/// give the target app a moment between two updates
+const UPDATE_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+/// values waiting for the worker before senders have to wait
+const QUEUE_CAPACITY: usize = 16;
+/// finished jobs kept around for `list_auto_input_jobs`
+const MAX_FINISHED_JOBS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Typing,
+    Paused,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobState::Done | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+
+/// one answer to type, as the frontend sees it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoInputJob {
+    pub id: u64,
+    pub state: JobState,
+    /// characters of the answer already typed
+    pub typed_chars: usize,
+    /// characters of the answer received so far, as inserted in `format`
+    pub total_chars: usize,
+    /// whether the whole answer has been received
+    pub complete: bool,
+    pub app_name: Option<String>,
+    /// window the job types into, 0 when unknown
+    pub window: isize,
+    pub error: Option<String>,
+}
+
+struct Job {
+    info: AutoInputJob,
+    settings: TypingSettings,
+    answer: String,
+    /// text typed so far, kept exact even when interrupted
+    typed: String,
+    /// pastes or lines typed, each one is an undo step in most apps
+    inputs: usize,
+    /// whether typed text was deleted again with backspaces
+    corrected: bool,
+    undone: bool,
+}
+
+/// What a job put into its window, for `undo_last_insertion`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Insertion {
+    pub job_id: u64,
+    pub window: isize,
+    pub app_name: Option<String>,
+    pub strategy: TypingStrategy,
+    /// characters the job left in the window
+    pub chars: usize,
+    #[serde(skip)]
+    pub inputs: usize,
+    #[serde(skip)]
+    pub corrected: bool,
+    #[serde(skip)]
+    pub settings: TypingSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoPlan {
+    /// press the app's undo shortcut this many times
+    Shortcut(usize),
+    Backspaces(usize),
+}
+
+impl Insertion {
+    /// Pastes come back with the app's undo, which also restores anything the
+    /// paste replaced. Typed text and text that was corrected on the way is
+    /// deleted character by character instead, undo steps are unreliable there.
+    pub fn undo_plan(&self) -> UndoPlan {
+        match self.strategy {
+            TypingStrategy::Paste if !self.corrected => UndoPlan::Shortcut(self.inputs),
+            _ => UndoPlan::Backspaces(self.chars),
+        }
+    }
+}
+
+impl Job {
+    /// the answer as it should read in the target app
+    fn text(&self) -> String {
+        match self.settings.format {
+            InsertFormat::Markdown => self.answer.clone(),
+            InsertFormat::Plain | InsertFormat::Rich => markdown::to_plain_text(&self.answer),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum AutoInputMessage {
+    /// the whole answer so far
+    Value { id: u64, text: String },
+    /// no more values for the job
+    End { id: u64 },
+}
+
+/// What the worker needs from the app.
+pub trait AutoInputHost: Send {
+    /// whether the window of `job` is in front, bringing it back if it isn't
+    fn ensure_foreground(&mut self, job: &AutoInputJob) -> bool;
+    /// the job stopped because the target window couldn't be brought back
+    fn paused(&mut self, job: &AutoInputJob);
+    fn progress(&mut self, job: &AutoInputJob);
+
+    /// what types a job with `settings`
+    fn create_input(&mut self, settings: &TypingSettings) -> Box<dyn InputStrategy> {
+        super::input::create_input(settings)
+    }
+}
+
+/// Characters to delete from the end of `typed` and the text to type after
+/// that so it reads `answer`.
+pub fn diff<'a>(typed: &str, answer: &'a str) -> (usize, &'a str) {
+    let mut prefix_bytes = 0;
+    let mut prefix_chars = 0;
+    for (typed_char, (index, answer_char)) in typed.chars().zip(answer.char_indices()) {
+        if typed_char != answer_char {
+            break;
+        }
+        prefix_bytes = index + answer_char.len_utf8();
+        prefix_chars += 1;
+    }
+    let backspaces = typed.chars().count() - prefix_chars;
+    (backspaces, &answer[prefix_bytes..])
+}
+
+/// Jobs are typed one after the other in the order they began. Values go
+/// through a bounded channel, pause, resume and cancel act on the job table
+/// directly so they take effect between two chunks.
+#[derive(Clone)]
+pub struct AutoInputQueue {
+    sender: mpsc::Sender<AutoInputMessage>,
+    jobs: Arc<Mutex<Vec<Job>>>,
+    notify: Arc<Notify>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl AutoInputQueue {
+    /// the queue and the worker future that types its jobs
+    pub fn new(host: Box<dyn AutoInputHost>) -> (Self, impl Future<Output = ()>) {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let queue = Self {
+            sender,
+            jobs: Arc::new(Mutex::new(Vec::new())),
+            notify: Arc::new(Notify::new()),
+            next_id: Arc::new(AtomicU64::new(1)),
+        };
+        let worker = AutoInputWorker {
+            queue: queue.clone(),
+            receiver,
+            host,
+            input: None,
+        };
+        (queue, worker.run())
+    }
+
+    /// Add a job for a new answer. Jobs that began earlier get no more values,
+    /// they finish typing what they have.
+    pub fn begin(
+        &self,
+        settings: TypingSettings,
+        app_name: Option<String>,
+        window: isize,
+    ) -> AutoInputJob {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let info = AutoInputJob {
+            id,
+            state: JobState::Queued,
+            typed_chars: 0,
+            total_chars: 0,
+            complete: false,
+            app_name,
+            window,
+            error: None,
+        };
+        {
+            let mut jobs = self.jobs.lock();
+            for job in jobs.iter_mut() {
+                job.info.complete = true;
+            }
+            let finished = jobs
+                .iter()
+                .filter(|job| job.info.state.is_finished())
+                .count();
+            let mut to_remove = finished.saturating_sub(MAX_FINISHED_JOBS);
+            jobs.retain(|job| {
+                if to_remove > 0 && job.info.state.is_finished() {
+                    to_remove -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+            jobs.push(Job {
+                info: info.clone(),
+                settings,
+                answer: String::new(),
+                typed: String::new(),
+                inputs: 0,
+                corrected: false,
+                undone: false,
+            });
+        }
+        self.notify.notify_one();
+        info
+    }
+
+    /// Set the answer of job `id`, waits while the worker is behind.
+    pub async fn send_value(&self, id: u64, text: String) -> Result<()> {
+        self.sender
+            .send(AutoInputMessage::Value { id, text })
+            .await
+            .map_err(|_| anyhow!("auto input worker stopped"))
+    }
+
+    pub async fn end(&self, id: u64) -> Result<()> {
+        self.sender
+            .send(AutoInputMessage::End { id })
+            .await
+            .map_err(|_| anyhow!("auto input worker stopped"))
+    }
+
+    pub fn job(&self, id: u64) -> Option<AutoInputJob> {
+        self.jobs
+            .lock()
+            .iter()
+            .find(|job| job.info.id == id)
+            .map(|job| job.info.clone())
+    }
+
+    pub fn jobs(&self) -> Vec<AutoInputJob> {
+        self.jobs
+            .lock()
+            .iter()
+            .map(|job| job.info.clone())
+            .collect()
+    }
+
+    /// the latest job that typed something and wasn't undone yet
+    pub fn last_insertion(&self) -> Option<Insertion> {
+        self.jobs
+            .lock()
+            .iter()
+            .rev()
+            .find(|job| !job.undone && !job.typed.is_empty())
+            .map(|job| Insertion {
+                job_id: job.info.id,
+                window: job.info.window,
+                app_name: job.info.app_name.clone(),
+                strategy: job.settings.strategy,
+                chars: job.typed.chars().count(),
+                inputs: job.inputs,
+                corrected: job.corrected,
+                settings: job.settings.clone(),
+            })
+    }
+
+    pub fn mark_undone(&self, id: u64) -> Result<AutoInputJob> {
+        self.update(id, |job| {
+            job.undone = true;
+            Ok(())
+        })
+    }
+
+    pub fn has_paused_jobs(&self) -> bool {
+        self.jobs
+            .lock()
+            .iter()
+            .any(|job| job.info.state == JobState::Paused)
+    }
+
+    /// ids of the jobs that are not finished
+    pub fn active_job_ids(&self) -> Vec<u64> {
+        self.jobs
+            .lock()
+            .iter()
+            .filter(|job| !job.info.state.is_finished())
+            .map(|job| job.info.id)
+            .collect()
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Job) -> Result<()>) -> Result<AutoInputJob> {
+        let info = {
+            let mut jobs = self.jobs.lock();
+            let job = jobs
+                .iter_mut()
+                .find(|job| job.info.id == id)
+                .ok_or_else(|| anyhow!("not found auto input job {}", id))?;
+            update(job)?;
+            job.info.clone()
+        };
+        self.notify.notify_one();
+        Ok(info)
+    }
+
+    pub fn pause(&self, id: u64) -> Result<AutoInputJob> {
+        self.update(id, |job| match job.info.state {
+            JobState::Queued | JobState::Typing => {
+                job.info.state = JobState::Paused;
+                Ok(())
+            }
+            JobState::Paused => Ok(()),
+            state => Err(anyhow!("auto input job {} is {:?}", id, state)),
+        })
+    }
+
+    pub fn resume(&self, id: u64) -> Result<AutoInputJob> {
+        self.update(id, |job| match job.info.state {
+            JobState::Paused => {
+                job.info.state = JobState::Typing;
+                Ok(())
+            }
+            JobState::Queued | JobState::Typing => Ok(()),
+            state => Err(anyhow!("auto input job {} is {:?}", id, state)),
+        })
+    }
+
+    /// stops typing before the next chunk, later values for the job are dropped
+    pub fn cancel(&self, id: u64) -> Result<AutoInputJob> {
+        self.update(id, |job| {
+            if !job.info.state.is_finished() {
+                job.info.state = JobState::Cancelled;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// what the worker should do next
+enum Work {
+    Type {
+        id: u64,
+        typed: String,
+        answer: String,
+    },
+    /// rich text goes in with one paste once the answer is complete
+    Paste {
+        id: u64,
+        html: String,
+        text: String,
+    },
+    Wait,
+}
+
+struct AutoInputWorker {
+    queue: AutoInputQueue,
+    receiver: mpsc::Receiver<AutoInputMessage>,
+    host: Box<dyn AutoInputHost>,
+    /// input of the job being typed
+    input: Option<(u64, Box<dyn InputStrategy>)>,
+}
+
+impl AutoInputWorker {
+    async fn run(mut self) {
+        loop {
+            while let Ok(message) = self.receiver.try_recv() {
+                self.apply(message);
+            }
+            match self.next_work() {
+                Work::Type { id, typed, answer } => {
+                    tokio::time::sleep(UPDATE_DELAY).await;
+                    self.type_answer(id, &typed, &answer);
+                }
+                Work::Paste { id, html, text } => self.paste_answer(id, &html, &text),
+                Work::Wait => {
+                    tokio::select! {
+                        message = self.receiver.recv() => match message {
+                            Some(message) => self.apply(message),
+                            None => break,
+                        },
+                        _ = self.queue.notify.notified() => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, message: AutoInputMessage) {
+        let (id, text) = match message {
+            AutoInputMessage::Value { id, text } => (id, Some(text)),
+            AutoInputMessage::End { id } => (id, None),
+        };
+        let _ = self.queue.update(id, |job| {
+            if job.info.state.is_finished() {
+                return Ok(());
+            }
+            match text {
+                Some(text) => {
+                    job.answer = text;
+                    job.info.total_chars = job.text().chars().count();
+                }
+                None => job.info.complete = true,
+            }
+            Ok(())
+        });
+    }
+
+    /// Look at the first unfinished job, the ones behind it wait for it. Jobs
+    /// with everything typed are finished on the way.
+    fn next_work(&mut self) -> Work {
+        let mut finished = Vec::new();
+        let work = {
+            let mut jobs = self.queue.jobs.lock();
+            let mut work = Work::Wait;
+            for job in jobs.iter_mut() {
+                if job.info.state.is_finished() {
+                    continue;
+                }
+                if job.info.state == JobState::Paused {
+                    break;
+                }
+                let text = job.text();
+                if job.typed == text {
+                    if job.info.complete {
+                        job.info.state = JobState::Done;
+                        finished.push(job.info.clone());
+                        continue;
+                    }
+                    break;
+                }
+                let rich = job.settings.format == InsertFormat::Rich && job.typed.is_empty();
+                if rich && !job.info.complete {
+                    break;
+                }
+                if job.info.state == JobState::Queued {
+                    job.info.state = JobState::Typing;
+                }
+                work = if rich {
+                    Work::Paste {
+                        id: job.info.id,
+                        html: markdown::to_html(&job.answer),
+                        text,
+                    }
+                } else {
+                    Work::Type {
+                        id: job.info.id,
+                        typed: job.typed.clone(),
+                        answer: text,
+                    }
+                };
+                break;
+            }
+            work
+        };
+        for job in finished {
+            self.host.progress(&job);
+        }
+        work
+    }
+
+    /// whether job `id` may type another chunk
+    fn is_typing(&self, id: u64) -> bool {
+        self.queue
+            .jobs
+            .lock()
+            .iter()
+            .any(|job| job.info.id == id && job.info.state == JobState::Typing)
+    }
+
+    /// pause the job instead of typing into another window
+    fn check_foreground(&mut self, id: u64) -> bool {
+        let job = match self.queue.job(id) {
+            Some(job) => job,
+            None => return false,
+        };
+        if self.host.ensure_foreground(&job) {
+            return true;
+        }
+        tracing::warn!(auto_input_paused = id);
+        if let Ok(job) = self.queue.pause(id) {
+            self.host.paused(&job);
+        }
+        false
+    }
+
+    fn record(&mut self, id: u64, update: impl FnOnce(&mut Job)) {
+        let job = self.queue.update(id, |job| {
+            update(job);
+            job.info.typed_chars = job.typed.chars().count();
+            Ok(())
+        });
+        if let Ok(job) = job {
+            self.host.progress(&job);
+        }
+    }
+
+    fn type_answer(&mut self, id: u64, typed: &str, answer: &str) {
+        let (backspaces, rest) = diff(typed, answer);
+        tracing::info!(
+            auto_input_job = id,
+            backspaces = backspaces,
+            append_len = rest.len()
+        );
+        if let Err(err) = self.send_diff(id, backspaces, rest) {
+            self.fail(id, err);
+        }
+    }
+
+    fn paste_answer(&mut self, id: u64, html: &str, text: &str) {
+        tracing::info!(auto_input_job = id, paste_html_len = html.len());
+        if !self.is_typing(id) || !self.check_foreground(id) {
+            return;
+        }
+        let result = self.input(id).paste_html(html, text);
+        match result {
+            Ok(()) => self.record(id, |job| {
+                job.typed = text.to_string();
+                job.inputs += 1;
+            }),
+            Err(err) => self.fail(id, err),
+        }
+    }
+
+    fn fail(&mut self, id: u64, err: anyhow::Error) {
+        tracing::warn!(auto_input_job = id, err =? err);
+        let job = self.queue.update(id, |job| {
+            job.info.state = JobState::Failed;
+            job.info.error = Some(err.to_string());
+            Ok(())
+        });
+        if let Ok(job) = job {
+            self.host.progress(&job);
+        }
+    }
+
+    /// the input of job `id`, created with the job's settings on first use
+    fn input(&mut self, id: u64) -> &mut dyn InputStrategy {
+        if self.input.as_ref().map(|(input_id, _)| *input_id) != Some(id) {
+            self.input = None;
+        }
+        let (queue, host) = (&self.queue, &mut self.host);
+        let (_, input) = self.input.get_or_insert_with(|| {
+            let settings = queue
+                .jobs
+                .lock()
+                .iter()
+                .find(|job| job.info.id == id)
+                .map(|job| job.settings.clone())
+                .unwrap_or_default();
+            (id, host.create_input(&settings))
+        });
+        input.as_mut()
+    }
+
+    fn send_diff(&mut self, id: u64, backspaces: usize, rest: &str) -> Result<()> {
+        if backspaces > 0 && !self.check_foreground(id) {
+            return Ok(());
+        }
+        for _ in 0..backspaces {
+            if !self.is_typing(id) {
+                return Ok(());
+            }
+            self.input(id).backspace()?;
+            self.record(id, |job| {
+                job.typed.pop();
+                job.corrected = true;
+            });
+        }
+        for piece in rest.split_inclusive('\n') {
+            if !self.is_typing(id) || !self.check_foreground(id) {
+                return Ok(());
+            }
+            self.input(id).type_text(piece)?;
+            self.record(id, |job| {
+                job.typed.push_str(piece);
+                job.inputs += 1;
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Op {
+        Type(String),
+        Backspace,
+        PasteHtml(String),
+    }
+
+    /// what the worker did and the window it sees in front
+    #[derive(Clone, Default)]
+    struct Recorded {
+        ops: Arc<Mutex<Vec<Op>>>,
+        background: Arc<Mutex<bool>>,
+        paused: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Recorded {
+        fn take(&self) -> Vec<Op> {
+            std::mem::take(&mut *self.ops.lock())
+        }
+    }
+
+    struct FakeInput(Recorded);
+
+    impl InputStrategy for FakeInput {
+        fn type_text(&mut self, text: &str) -> Result<()> {
+            self.0.ops.lock().push(Op::Type(text.to_string()));
+            Ok(())
+        }
+
+        fn backspace(&mut self) -> Result<()> {
+            self.0.ops.lock().push(Op::Backspace);
+            Ok(())
+        }
+
+        fn paste_html(&mut self, html: &str, _text: &str) -> Result<()> {
+            self.0.ops.lock().push(Op::PasteHtml(html.to_string()));
+            Ok(())
+        }
+    }
+
+    struct FakeHost(Recorded);
+
+    impl AutoInputHost for FakeHost {
+        fn ensure_foreground(&mut self, _job: &AutoInputJob) -> bool {
+            !*self.0.background.lock()
+        }
+
+        fn paused(&mut self, job: &AutoInputJob) {
+            self.0.paused.lock().push(job.id);
+        }
+
+        fn progress(&mut self, _job: &AutoInputJob) {}
+
+        fn create_input(&mut self, _settings: &TypingSettings) -> Box<dyn InputStrategy> {
+            Box::new(FakeInput(self.0.clone()))
+        }
+    }
+
+    fn settings(format: InsertFormat) -> TypingSettings {
+        TypingSettings {
+            format,
+            ..TypingSettings::default()
+        }
+    }
+
+    fn start() -> (AutoInputQueue, Recorded) {
+        let recorded = Recorded::default();
+        let (queue, worker) = AutoInputQueue::new(Box::new(FakeHost(recorded.clone())));
+        tokio::spawn(worker);
+        (queue, recorded)
+    }
+
+    /// lets the worker run until `done` holds for job `id`
+    async fn wait_for(queue: &AutoInputQueue, id: u64, done: impl Fn(&AutoInputJob) -> bool) {
+        for _ in 0..1000 {
+            if queue.job(id).map(|job| done(&job)).unwrap_or(false) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} is {:?}", id, queue.job(id));
+    }
+
+    fn types(text: &str) -> Op {
+        Op::Type(text.to_string())
+    }
+
+    #[test]
+    fn diff_keeps_the_common_prefix() {
+        assert_eq!(diff("", "Hello"), (0, "Hello"));
+        assert_eq!(diff("Hello", "Hello, world"), (0, ", world"));
+        assert_eq!(diff("Hello wrld", "Hello world"), (3, "orld"));
+        assert_eq!(diff("Hello", ""), (5, ""));
+        assert_eq!(diff("Hello", "Hello"), (0, ""));
+        // counted in characters, not bytes
+        assert_eq!(diff("你好吗", "你好呀!"), (1, "呀!"));
+        assert_eq!(diff("café", "cafe"), (1, "e"));
+    }
+
+    #[test]
+    fn pastes_are_undone_with_the_app_undo() {
+        let insertion = |strategy, corrected| Insertion {
+            job_id: 1,
+            window: 0,
+            app_name: None,
+            strategy,
+            chars: 12,
+            inputs: 3,
+            corrected,
+            settings: TypingSettings::default(),
+        };
+        assert_eq!(
+            insertion(TypingStrategy::Paste, false).undo_plan(),
+            UndoPlan::Shortcut(3)
+        );
+        assert_eq!(
+            insertion(TypingStrategy::Paste, true).undo_plan(),
+            UndoPlan::Backspaces(12)
+        );
+        assert_eq!(
+            insertion(TypingStrategy::Keystrokes, false).undo_plan(),
+            UndoPlan::Backspaces(12)
+        );
+        assert_eq!(
+            insertion(TypingStrategy::PasteShiftEnter, false).undo_plan(),
+            UndoPlan::Backspaces(12)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn corrections_are_typed_with_backspaces() {
+        let (queue, recorded) = start();
+        let job = queue.begin(settings(InsertFormat::Markdown), None, 0);
+        queue
+            .send_value(job.id, "Hello wrld\nby".to_string())
+            .await
+            .unwrap();
+        wait_for(&queue, job.id, |job| job.typed_chars == 13).await;
+        assert_eq!(recorded.take(), vec![types("Hello wrld\n"), types("by")]);
+
+        queue
+            .send_value(job.id, "Hello world\nbye".to_string())
+            .await
+            .unwrap();
+        queue.end(job.id).await.unwrap();
+        wait_for(&queue, job.id, |job| job.state == JobState::Done).await;
+        let mut expected = vec![Op::Backspace; 6];
+        expected.extend([types("orld\n"), types("bye")]);
+        assert_eq!(recorded.take(), expected);
+
+        let insertion = queue.last_insertion().unwrap();
+        assert_eq!(insertion.chars, 15);
+        assert!(insertion.corrected);
+        queue.mark_undone(job.id).unwrap();
+        assert!(queue.last_insertion().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rich_answers_are_pasted_once_complete() {
+        let (queue, recorded) = start();
+        let job = queue.begin(settings(InsertFormat::Rich), None, 0);
+        queue
+            .send_value(job.id, "**Hi**".to_string())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(recorded.take().is_empty());
+        queue.end(job.id).await.unwrap();
+        wait_for(&queue, job.id, |job| job.state == JobState::Done).await;
+        assert_eq!(
+            recorded.take(),
+            vec![Op::PasteHtml("<p><strong>Hi</strong></p>\n".to_string())]
+        );
+        assert_eq!(
+            queue.last_insertion().unwrap().undo_plan(),
+            UndoPlan::Shortcut(1)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pause_resume_and_cancel() {
+        let (queue, recorded) = start();
+        let job = queue.begin(settings(InsertFormat::Markdown), None, 0);
+        assert_eq!(queue.pause(job.id).unwrap().state, JobState::Paused);
+        assert_eq!(queue.pause(job.id).unwrap().state, JobState::Paused);
+        assert!(queue.has_paused_jobs());
+        queue.send_value(job.id, "one".to_string()).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(recorded.take().is_empty());
+
+        assert_eq!(queue.resume(job.id).unwrap().state, JobState::Typing);
+        wait_for(&queue, job.id, |job| job.typed_chars == 3).await;
+        assert_eq!(recorded.take(), vec![types("one")]);
+
+        assert_eq!(queue.cancel(job.id).unwrap().state, JobState::Cancelled);
+        queue
+            .send_value(job.id, "one two".to_string())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(recorded.take().is_empty());
+        let cancelled = queue.job(job.id).unwrap();
+        assert_eq!(cancelled.total_chars, 3);
+        assert!(queue.resume(job.id).is_err());
+        assert!(queue.pause(job.id).is_err());
+        assert!(queue.active_job_ids().is_empty());
+        assert!(queue.pause(99).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jobs_pause_when_their_window_is_gone() {
+        let (queue, recorded) = start();
+        *recorded.background.lock() = true;
+        let job = queue.begin(settings(InsertFormat::Markdown), None, 0);
+        queue.send_value(job.id, "one".to_string()).await.unwrap();
+        wait_for(&queue, job.id, |job| job.state == JobState::Paused).await;
+        assert_eq!(*recorded.paused.lock(), vec![job.id]);
+        assert!(recorded.take().is_empty());
+
+        *recorded.background.lock() = false;
+        queue.resume(job.id).unwrap();
+        queue.end(job.id).await.unwrap();
+        wait_for(&queue, job.id, |job| job.state == JobState::Done).await;
+        assert_eq!(recorded.take(), vec![types("one")]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jobs_are_typed_in_order() {
+        let (queue, recorded) = start();
+        let first = queue.begin(settings(InsertFormat::Markdown), None, 0);
+        queue
+            .send_value(first.id, "first".to_string())
+            .await
+            .unwrap();
+        wait_for(&queue, first.id, |job| job.typed_chars == 5).await;
+        // a new answer ends the one before
+        let second = queue.begin(settings(InsertFormat::Markdown), None, 0);
+        assert!(queue.job(first.id).unwrap().complete);
+        queue
+            .send_value(second.id, "second".to_string())
+            .await
+            .unwrap();
+        queue.end(second.id).await.unwrap();
+        wait_for(&queue, second.id, |job| job.state == JobState::Done).await;
+        assert_eq!(queue.job(first.id).unwrap().state, JobState::Done);
+        assert_eq!(recorded.take(), vec![types("first"), types("second")]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn senders_wait_when_the_queue_is_full() {
+        // no worker takes the values
+        let (queue, _worker) = AutoInputQueue::new(Box::new(FakeHost(Recorded::default())));
+        let job = queue.begin(settings(InsertFormat::Markdown), None, 0);
+        for value in 0..QUEUE_CAPACITY {
+            queue.send_value(job.id, value.to_string()).await.unwrap();
+        }
+        let full = tokio::time::timeout(
+            Duration::from_secs(1),
+            queue.send_value(job.id, "one more".to_string()),
+        )
+        .await;
+        assert!(full.is_err());
+    }
+}
@@ -1,10 +1,53 @@
 pub struct PlatformForeground;
 
+/// what we know about a top level window
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowInfo {
+    pub handle: isize,
+    pub title: Option<String>,
+    /// executable or application name, e.g. `WINWORD` or `Slack`
+    pub app_name: Option<String>,
+    pub pid: Option<u32>,
+}
+
 #[cfg(target_os = "windows")]
 mod windows {
-    use super::PlatformForeground;
+    use super::{PlatformForeground, WindowInfo};
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
     use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
-    use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, SetForegroundWindow};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowTextW, SetForegroundWindow,
+    };
+
+    fn process_name(pid: u32) -> Option<String> {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process == 0 {
+                return None;
+            }
+            let mut buffer = [0u16; 1024];
+            let mut size = buffer.len() as u32;
+            let ok = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                buffer.as_mut_ptr(),
+                &mut size as *mut u32,
+            );
+            CloseHandle(process);
+            if ok == 0 {
+                return None;
+            }
+            let path = String::from_utf16_lossy(&buffer[..size as usize]);
+            std::path::Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        }
+    }
 
     impl PlatformForeground {
         pub fn get_foreground_window() -> isize {
@@ -28,12 +71,29 @@ mod windows {
                 }
             }
         }
+
+        pub fn get_window_info(hwnd: isize) -> WindowInfo {
+            let mut title = [0u16; 512];
+            let length = unsafe { GetWindowTextW(hwnd, title.as_mut_ptr(), title.len() as i32) };
+            let mut pid: u32 = 0;
+            let _tid = unsafe { GetWindowThreadProcessId(hwnd, &mut pid as *mut u32) };
+            WindowInfo {
+                handle: hwnd,
+                title: if length > 0 {
+                    Some(String::from_utf16_lossy(&title[..length as usize]))
+                } else {
+                    None
+                },
+                app_name: process_name(pid),
+                pid: if pid != 0 { Some(pid) } else { None },
+            }
+        }
     }
 }
 
 #[cfg(target_os = "macos")]
 mod mac {
-    use super::PlatformForeground;
+    use super::{PlatformForeground, WindowInfo};
     use std::process::{Command, Output};
 
     impl PlatformForeground {
@@ -74,5 +134,383 @@ mod mac {
 
             PlatformForeground::run_script(script).map(|_| ())
         }
+
+        pub fn get_window_info(window_id: isize) -> WindowInfo {
+            let script = r#"tell application "System Events"
+                                set frontApp to first application process whose frontmost is true
+                                set appName to name of frontApp
+                                set winTitle to ""
+                                try
+                                    set winTitle to name of front window of frontApp
+                                end try
+                                return appName & linefeed & winTitle
+                            end tell "#;
+            let output = PlatformForeground::run_script(String::from(script)).unwrap_or_default();
+            let mut lines = output.lines().map(|line| line.trim().to_string());
+            let app_name = lines.next().filter(|line| !line.is_empty());
+            let title = lines.next().filter(|line| !line.is_empty());
+            WindowInfo {
+                handle: window_id,
+                title,
+                app_name,
+                pid: None,
+            }
+        }
+    }
+}
+
+/// X11 through the EWMH hints the window manager maintains. Native Wayland
+/// windows are invisible to X clients, and under XWayland the hints only know
+/// about X windows, so in a Wayland session the active window is unknown (0)
+/// and setting it fails; callers treat that like a missing handle.
+///
+/// To try it without a desktop: `Xvfb :99 &`, `DISPLAY=:99 openbox &`, open a
+/// couple of `xterm`s and run the app with `DISPLAY=:99`. The ignored test
+/// below does the same, `cargo test -- --ignored` with both installed.
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{PlatformForeground, WindowInfo};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, Window,
+    };
+    use x11rb::rust_connection::RustConnection;
+
+    /// the display connection, opened on first use and again after a failure;
+    /// auto input checks the active window before every chunk
+    static CONNECTION: Lazy<Mutex<Option<Arc<X11>>>> = Lazy::new(|| Mutex::new(None));
+
+    /// XWayland sets `DISPLAY` as well, the session type tells them apart
+    fn is_wayland_session() -> bool {
+        std::env::var_os("WAYLAND_DISPLAY").is_some()
+            || std::env::var("XDG_SESSION_TYPE")
+                .map(|session_type| session_type.eq_ignore_ascii_case("wayland"))
+                .unwrap_or(false)
+    }
+
+    struct Atoms {
+        net_active_window: Atom,
+        net_wm_name: Atom,
+        net_wm_pid: Atom,
+        utf8_string: Atom,
+    }
+
+    struct X11 {
+        connection: RustConnection,
+        root: Window,
+        atoms: Atoms,
+    }
+
+    /// run `query` on the cached connection, dropping it when the query fails
+    fn with_x11<T>(query: impl FnOnce(&X11) -> Result<T, String>) -> Result<T, String> {
+        let x11 = {
+            let mut cached = CONNECTION.lock();
+            match cached.as_ref() {
+                Some(x11) => x11.clone(),
+                None => {
+                    let x11 = Arc::new(X11::connect()?);
+                    *cached = Some(x11.clone());
+                    x11
+                }
+            }
+        };
+        let result = query(&x11);
+        if result.is_err() {
+            CONNECTION.lock().take();
+        }
+        result
+    }
+
+    impl X11 {
+        fn connect() -> Result<Self, String> {
+            if is_wayland_session() {
+                return Err("wayland session, X11 doesn't know the active window".to_string());
+            }
+            if std::env::var_os("DISPLAY").is_none() {
+                return Err("no X11 display".to_string());
+            }
+            let (connection, screen_num) =
+                x11rb::connect(None).map_err(|err| format!("connect X11 error {}", err))?;
+            let root = connection.setup().roots[screen_num].root;
+            let atoms = Atoms {
+                net_active_window: Self::atom(&connection, "_NET_ACTIVE_WINDOW")?,
+                net_wm_name: Self::atom(&connection, "_NET_WM_NAME")?,
+                net_wm_pid: Self::atom(&connection, "_NET_WM_PID")?,
+                utf8_string: Self::atom(&connection, "UTF8_STRING")?,
+            };
+            Ok(Self {
+                connection,
+                root,
+                atoms,
+            })
+        }
+
+        fn atom(connection: &RustConnection, name: &str) -> Result<Atom, String> {
+            Ok(connection
+                .intern_atom(false, name.as_bytes())
+                .map_err(|err| err.to_string())?
+                .reply()
+                .map_err(|err| err.to_string())?
+                .atom)
+        }
+
+        fn property(
+            &self,
+            window: Window,
+            property: Atom,
+            kind: impl Into<Atom>,
+        ) -> Result<x11rb::protocol::xproto::GetPropertyReply, String> {
+            self.connection
+                .get_property(false, window, property, kind, 0, u32::MAX / 4)
+                .map_err(|err| err.to_string())?
+                .reply()
+                .map_err(|err| err.to_string())
+        }
+
+        fn property_u32(&self, window: Window, property: Atom, kind: AtomEnum) -> Option<u32> {
+            self.property(window, property, kind)
+                .ok()?
+                .value32()?
+                .next()
+        }
+
+        fn property_string(&self, window: Window, property: Atom, kind: Atom) -> Option<String> {
+            let reply = self.property(window, property, kind).ok()?;
+            if reply.value.is_empty() {
+                return None;
+            }
+            Some(String::from_utf8_lossy(&reply.value).into_owned())
+        }
+
+        fn active_window(&self) -> Result<Window, String> {
+            let reply = self.property(self.root, self.atoms.net_active_window, AtomEnum::WINDOW)?;
+            Ok(reply
+                .value32()
+                .and_then(|mut values| values.next())
+                .unwrap_or(0))
+        }
+
+        fn title(&self, window: Window) -> Option<String> {
+            self.property_string(window, self.atoms.net_wm_name, self.atoms.utf8_string)
+                .or_else(|| {
+                    self.property_string(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())
+                })
+        }
+
+        /// second part of `WM_CLASS`, e.g. `Slack` or `firefox`
+        fn class(&self, window: Window) -> Option<String> {
+            let class =
+                self.property_string(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?;
+            let mut parts = class.split('\0').filter(|part| !part.is_empty());
+            let instance = parts.next().map(String::from);
+            parts.next().map(String::from).or(instance)
+        }
+
+        fn pid(&self, window: Window) -> Option<u32> {
+            self.property_u32(window, self.atoms.net_wm_pid, AtomEnum::CARDINAL)
+        }
+
+        /// ask the window manager to activate `window`, like a pager would
+        fn activate(&self, window: Window) -> Result<(), String> {
+            // source 2: pager, timestamp 0: current time
+            let event =
+                ClientMessageEvent::new(32, window, self.atoms.net_active_window, [2, 0, 0, 0, 0]);
+            self.connection
+                .send_event(
+                    false,
+                    self.root,
+                    EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                    event,
+                )
+                .map_err(|err| err.to_string())?;
+            self.connection.flush().map_err(|err| err.to_string())
+        }
+    }
+
+    impl PlatformForeground {
+        pub fn get_foreground_window() -> isize {
+            match with_x11(X11::active_window) {
+                Ok(window) => window as isize,
+                Err(err) => {
+                    tracing::debug!(get_foreground_window_error = err);
+                    0
+                }
+            }
+        }
+
+        pub fn set_foreground_window(window: isize) -> Result<(), String> {
+            if window <= 0 || window > u32::MAX as isize {
+                return Err(format!("invalid window {}", window));
+            }
+            with_x11(|x11| x11.activate(window as Window))
+        }
+
+        pub fn get_window_info(window: isize) -> WindowInfo {
+            let unknown = WindowInfo {
+                handle: window,
+                ..Default::default()
+            };
+            if window <= 0 || window > u32::MAX as isize {
+                return unknown;
+            }
+            let id = window as Window;
+            with_x11(|x11| {
+                Ok(WindowInfo {
+                    handle: window,
+                    title: x11.title(id),
+                    app_name: x11.class(id),
+                    pid: x11.pid(id),
+                })
+            })
+            .unwrap_or(unknown)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::process::{Child, Command};
+        use std::time::{Duration, Instant};
+        use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+        use x11rb::wrapper::ConnectionExt as _;
+
+        const DISPLAY: &str = ":87";
+
+        /// stops the X server and the window manager when the test ends
+        struct Processes(Vec<Child>);
+
+        impl Drop for Processes {
+            fn drop(&mut self) {
+                for child in self.0.iter_mut().rev() {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+            }
+        }
+
+        fn wait_for(mut check: impl FnMut() -> bool) -> bool {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if check() {
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            false
+        }
+
+        fn create_window(
+            connection: &RustConnection,
+            root: Window,
+            title: &str,
+            class: &str,
+            pid: u32,
+        ) -> Window {
+            let atom = |name: &str| X11::atom(connection, name).unwrap();
+            let window = connection.generate_id().unwrap();
+            connection
+                .create_window(
+                    x11rb::COPY_DEPTH_FROM_PARENT,
+                    window,
+                    root,
+                    0,
+                    0,
+                    200,
+                    100,
+                    0,
+                    WindowClass::INPUT_OUTPUT,
+                    0,
+                    &CreateWindowAux::new(),
+                )
+                .unwrap();
+            connection
+                .change_property8(
+                    PropMode::REPLACE,
+                    window,
+                    atom("_NET_WM_NAME"),
+                    atom("UTF8_STRING"),
+                    title.as_bytes(),
+                )
+                .unwrap();
+            let wm_class = format!("{}\0{}\0", class.to_lowercase(), class);
+            connection
+                .change_property8(
+                    PropMode::REPLACE,
+                    window,
+                    AtomEnum::WM_CLASS,
+                    AtomEnum::STRING,
+                    wm_class.as_bytes(),
+                )
+                .unwrap();
+            connection
+                .change_property32(
+                    PropMode::REPLACE,
+                    window,
+                    atom("_NET_WM_PID"),
+                    AtomEnum::CARDINAL,
+                    &[pid],
+                )
+                .unwrap();
+            connection.map_window(window).unwrap();
+            connection.flush().unwrap();
+            window
+        }
+
+        /// Needs `Xvfb` and an EWMH window manager, `openbox` or the one in
+        /// `X11_TEST_WM`.
+        #[test]
+        #[ignore = "needs Xvfb and a window manager, run with --ignored"]
+        fn active_window_on_xvfb() {
+            std::env::set_var("DISPLAY", DISPLAY);
+            std::env::set_var("XDG_SESSION_TYPE", "x11");
+            std::env::remove_var("WAYLAND_DISPLAY");
+            let xvfb = Command::new("Xvfb")
+                .args([DISPLAY, "-screen", "0", "1024x768x24"])
+                .spawn()
+                .expect("start Xvfb");
+            let mut processes = Processes(vec![xvfb]);
+            assert!(wait_for(|| x11rb::connect(Some(DISPLAY)).is_ok()));
+            let window_manager = std::env::var("X11_TEST_WM").unwrap_or("openbox".to_string());
+            processes.0.push(
+                Command::new(window_manager)
+                    .env("DISPLAY", DISPLAY)
+                    .spawn()
+                    .expect("start window manager"),
+            );
+
+            let (connection, screen_num) = x11rb::connect(Some(DISPLAY)).unwrap();
+            let root = connection.setup().roots[screen_num].root;
+            let supporting_wm = X11::atom(&connection, "_NET_SUPPORTING_WM_CHECK").unwrap();
+            assert!(wait_for(|| {
+                connection
+                    .get_property(false, root, supporting_wm, AtomEnum::WINDOW, 0, 1)
+                    .ok()
+                    .and_then(|cookie| cookie.reply().ok())
+                    .map(|reply| !reply.value.is_empty())
+                    .unwrap_or(false)
+            }));
+
+            let first = create_window(&connection, root, "first window", "First", 1111);
+            let second = create_window(&connection, root, "zweites Fenster ü", "Second", 2222);
+            for window in [first, second, first] {
+                assert!(wait_for(|| {
+                    PlatformForeground::set_foreground_window(window as isize).is_ok()
+                        && PlatformForeground::get_foreground_window() == window as isize
+                }));
+            }
+
+            let info = PlatformForeground::get_window_info(first as isize);
+            assert_eq!(info.handle, first as isize);
+            assert_eq!(info.title.as_deref(), Some("first window"));
+            assert_eq!(info.app_name.as_deref(), Some("First"));
+            assert_eq!(info.pid, Some(1111));
+            let info = PlatformForeground::get_window_info(second as isize);
+            assert_eq!(info.title.as_deref(), Some("zweites Fenster ü"));
+            assert_eq!(info.app_name.as_deref(), Some("Second"));
+            assert_eq!(info.pid, Some(2222));
+        }
     }
 }
@@ -1,24 +1,386 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::markdown::InsertFormat;
+
+/// how auto input gets text into the target app
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TypingStrategy {
+    /// paste through the clipboard, line breaks included
+    #[default]
+    Paste,
+    /// type every character as a Unicode keystroke
+    Keystrokes,
+    /// paste line by line with Shift+Enter in between, for chat apps where
+    /// Enter sends the message
+    PasteShiftEnter,
+}
+
+/// apps where Enter sends the message, unless the config says otherwise
+const SHIFT_ENTER_APPS: &[&str] = &[
+    "slack",
+    "discord",
+    "telegram",
+    "wechat",
+    "weixin",
+    "qq",
+    "teams",
+    "microsoft teams",
+    "dingtalk",
+    "feishu",
+    "lark",
+];
+
+fn default_interval_ms() -> u64 {
+    30
+}
 
-pub struct CrossformInput;
-
-impl CrossformInput {
-    pub fn auto_input_text_using_copy(text: &str) -> Result<()> {
-        if text.contains('\n') {
-            // 分割字符串
-            let mut splits = text.split('\n').peekable();
-            while let Some(line) = splits.next() {
-                // 输入一行文本
-                crate::select::copy_and_paste(line.to_string())?;
-                if splits.peek().is_some() {
-                    // 如果不是最后一行则输入回车换行符
-                    std::thread::sleep(std::time::Duration::from_millis(30));
-                    crate::select::press_enter();
-                }
+/// `typing` section of the app config
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingConfig {
+    #[serde(default)]
+    pub strategy: TypingStrategy,
+    /// strategy by app name, e.g. `{"Code": "keystrokes"}`, case insensitive
+    #[serde(default)]
+    pub app_strategies: HashMap<String, TypingStrategy>,
+    /// least time between two pastes or keystrokes
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    /// log what would be typed instead of typing it
+    #[serde(default)]
+    pub dry_run: bool,
+    /// how Markdown answers are inserted
+    #[serde(default)]
+    pub format: InsertFormat,
+    /// format by app name, e.g. `{"Notepad": "markdown"}`, case insensitive
+    #[serde(default)]
+    pub app_formats: HashMap<String, InsertFormat>,
+}
+
+impl Default for TypingConfig {
+    fn default() -> Self {
+        Self {
+            strategy: TypingStrategy::default(),
+            app_strategies: HashMap::new(),
+            interval_ms: default_interval_ms(),
+            dry_run: false,
+            format: InsertFormat::default(),
+            app_formats: HashMap::new(),
+        }
+    }
+}
+
+/// how to type into one particular app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingSettings {
+    pub strategy: TypingStrategy,
+    pub interval: Duration,
+    pub dry_run: bool,
+    pub format: InsertFormat,
+}
+
+impl Default for TypingSettings {
+    fn default() -> Self {
+        TypingConfig::default().settings_for(None)
+    }
+}
+
+/// the value configured for `app_name`, which is in lower case
+fn find_app<T: Copy>(apps: &HashMap<String, T>, app_name: &str) -> Option<T> {
+    apps.iter()
+        .find(|(name, _)| name.to_lowercase() == app_name)
+        .map(|(_, value)| *value)
+}
+
+impl TypingConfig {
+    pub fn settings_for(&self, app_name: Option<&str>) -> TypingSettings {
+        let app_name = app_name.map(|app_name| app_name.to_lowercase());
+        let strategy = app_name
+            .as_deref()
+            .and_then(|app_name| {
+                find_app(&self.app_strategies, app_name).or_else(|| {
+                    SHIFT_ENTER_APPS
+                        .contains(&app_name)
+                        .then_some(TypingStrategy::PasteShiftEnter)
+                })
+            })
+            .unwrap_or(self.strategy);
+        let format = app_name
+            .as_deref()
+            .and_then(|app_name| {
+                find_app(&self.app_formats, app_name)
+                    .or_else(|| super::markdown::default_format(app_name))
+            })
+            .unwrap_or(self.format);
+        TypingSettings {
+            strategy,
+            interval: Duration::from_millis(self.interval_ms),
+            dry_run: self.dry_run,
+            format,
+        }
+    }
+}
+
+/// A way of typing into the focused window.
+pub trait InputStrategy: Send {
+    /// type one line, which may end with `\n`
+    fn type_text(&mut self, text: &str) -> Result<()>;
+    fn backspace(&mut self) -> Result<()>;
+
+    /// the app's undo shortcut
+    fn undo(&mut self) -> Result<()> {
+        crate::select::press_undo();
+        Ok(())
+    }
+
+    /// insert formatted text, typed as `text` by strategies without a clipboard
+    fn paste_html(&mut self, _html: &str, text: &str) -> Result<()> {
+        for line in text.split_inclusive('\n') {
+            self.type_text(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// keeps at least `interval` between two inputs
+struct Pacer {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Pacer {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Some(last) = self.last {
+            let elapsed = last.elapsed();
+            if elapsed < self.interval {
+                std::thread::sleep(self.interval - elapsed);
             }
-        } else {
-            crate::select::copy_and_paste(text.to_string())?
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+struct PasteInput {
+    pacer: Pacer,
+}
+
+impl InputStrategy for PasteInput {
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        // one paste keeps editors from auto indenting the next line
+        self.pacer.wait();
+        crate::select::copy_and_paste(text.to_string())
+    }
+
+    fn backspace(&mut self) -> Result<()> {
+        self.pacer.wait();
+        crate::select::press_backspace();
+        Ok(())
+    }
+
+    fn paste_html(&mut self, html: &str, text: &str) -> Result<()> {
+        self.pacer.wait();
+        crate::select::copy_and_paste_html(html.to_string(), text.to_string())
+    }
+}
+
+struct KeystrokeInput {
+    pacer: Pacer,
+}
+
+impl InputStrategy for KeystrokeInput {
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        use enigo::*;
+        let mut enigo = Enigo::new();
+        let mut buffer = [0u8; 4];
+        for char in text.chars() {
+            self.pacer.wait();
+            if char == '\n' {
+                crate::select::press_enter();
+            } else {
+                enigo.key_sequence(char.encode_utf8(&mut buffer));
+            }
+        }
+        Ok(())
+    }
+
+    fn backspace(&mut self) -> Result<()> {
+        self.pacer.wait();
+        crate::select::press_backspace();
+        Ok(())
+    }
+}
+
+struct PasteShiftEnterInput {
+    pacer: Pacer,
+}
+
+impl InputStrategy for PasteShiftEnterInput {
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        let (line, newline) = match text.strip_suffix('\n') {
+            Some(line) => (line, true),
+            None => (text, false),
         };
+        if !line.is_empty() {
+            self.pacer.wait();
+            crate::select::copy_and_paste(line.to_string())?;
+        }
+        if newline {
+            use enigo::*;
+            self.pacer.wait();
+            let mut enigo = Enigo::new();
+            enigo.key_down(Key::Shift);
+            enigo.key_click(Key::Return);
+            enigo.key_up(Key::Shift);
+        }
+        Ok(())
+    }
+
+    fn backspace(&mut self) -> Result<()> {
+        self.pacer.wait();
+        crate::select::press_backspace();
+        Ok(())
+    }
+
+    /// one paste has no Enter the app could take for sending
+    fn paste_html(&mut self, html: &str, text: &str) -> Result<()> {
+        self.pacer.wait();
+        crate::select::copy_and_paste_html(html.to_string(), text.to_string())
+    }
+}
+
+/// logs instead of typing
+struct DryRunInput {
+    strategy: TypingStrategy,
+}
+
+impl InputStrategy for DryRunInput {
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        tracing::info!(dry_run =? self.strategy, type_text = text);
+        Ok(())
+    }
+
+    fn backspace(&mut self) -> Result<()> {
+        tracing::info!(dry_run =? self.strategy, backspace = 1);
         Ok(())
     }
+
+    fn undo(&mut self) -> Result<()> {
+        tracing::info!(dry_run =? self.strategy, undo = 1);
+        Ok(())
+    }
+
+    fn paste_html(&mut self, html: &str, _text: &str) -> Result<()> {
+        tracing::info!(dry_run =? self.strategy, paste_html = html);
+        Ok(())
+    }
+}
+
+pub fn create_input(settings: &TypingSettings) -> Box<dyn InputStrategy> {
+    tracing::info!(typing_settings =? settings);
+    if settings.dry_run {
+        return Box::new(DryRunInput {
+            strategy: settings.strategy,
+        });
+    }
+    let pacer = Pacer::new(settings.interval);
+    match settings.strategy {
+        TypingStrategy::Paste => Box::new(PasteInput { pacer }),
+        TypingStrategy::Keystrokes => Box::new(KeystrokeInput { pacer }),
+        TypingStrategy::PasteShiftEnter => Box::new(PasteShiftEnterInput { pacer }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(
+        app_strategies: &[(&str, TypingStrategy)],
+        app_formats: &[(&str, InsertFormat)],
+    ) -> TypingConfig {
+        TypingConfig {
+            strategy: TypingStrategy::Keystrokes,
+            format: InsertFormat::Markdown,
+            app_strategies: app_strategies
+                .iter()
+                .map(|(name, strategy)| (name.to_string(), *strategy))
+                .collect(),
+            app_formats: app_formats
+                .iter()
+                .map(|(name, format)| (name.to_string(), *format))
+                .collect(),
+            ..TypingConfig::default()
+        }
+    }
+
+    #[test]
+    fn apps_without_settings_use_the_global_ones() {
+        let settings = config(&[], &[]).settings_for(Some("gnome-terminal"));
+        assert_eq!(settings.strategy, TypingStrategy::Keystrokes);
+        assert_eq!(settings.format, InsertFormat::Markdown);
+        assert_eq!(settings.interval, Duration::from_millis(30));
+
+        let settings = config(&[], &[]).settings_for(None);
+        assert_eq!(settings.strategy, TypingStrategy::Keystrokes);
+        assert_eq!(settings.format, InsertFormat::Markdown);
+    }
+
+    #[test]
+    fn built_in_apps_win_over_the_global_settings() {
+        let settings = config(&[], &[]).settings_for(Some("Slack"));
+        assert_eq!(settings.strategy, TypingStrategy::PasteShiftEnter);
+        assert_eq!(settings.format, InsertFormat::Rich);
+
+        let settings = config(&[], &[]).settings_for(Some("WINWORD"));
+        assert_eq!(settings.strategy, TypingStrategy::Keystrokes);
+        assert_eq!(settings.format, InsertFormat::Rich);
+    }
+
+    #[test]
+    fn configured_apps_win_over_the_built_in_ones() {
+        let config = config(
+            &[("slack", TypingStrategy::Paste)],
+            &[("slack", InsertFormat::Plain)],
+        );
+        let settings = config.settings_for(Some("Slack"));
+        assert_eq!(settings.strategy, TypingStrategy::Paste);
+        assert_eq!(settings.format, InsertFormat::Plain);
+    }
+
+    #[test]
+    fn app_names_match_in_any_case() {
+        let config = config(
+            &[("Code", TypingStrategy::Paste)],
+            &[("NotePad", InsertFormat::Rich)],
+        );
+        assert_eq!(
+            config.settings_for(Some("code")).strategy,
+            TypingStrategy::Paste
+        );
+        assert_eq!(
+            config.settings_for(Some("CODE")).strategy,
+            TypingStrategy::Paste
+        );
+        assert_eq!(
+            config.settings_for(Some("notepad")).format,
+            InsertFormat::Rich
+        );
+        // the configured strategy doesn't touch the built-in format
+        assert_eq!(
+            config.settings_for(Some("Code")).format,
+            InsertFormat::Markdown
+        );
+    }
 }
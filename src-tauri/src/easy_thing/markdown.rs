@@ -0,0 +1,228 @@
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+/// how a Markdown answer ends up in the target app
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InsertFormat {
+    /// formatting stripped, for terminals, inputs and plain editors
+    #[default]
+    Plain,
+    /// rendered to HTML, pasted together with a plain text flavor for apps
+    /// like Word, Outlook or Slack
+    Rich,
+    /// the Markdown source as the model wrote it
+    Markdown,
+}
+
+/// apps that paste HTML as formatted text, unless the config says otherwise
+const RICH_TEXT_APPS: &[&str] = &[
+    "winword",
+    "microsoft word",
+    "outlook",
+    "microsoft outlook",
+    "onenote",
+    "mail",
+    "thunderbird",
+    "slack",
+    "teams",
+    "microsoft teams",
+    "notion",
+    "pages",
+    "libreoffice",
+    "soffice",
+];
+
+/// apps that are better off with the Markdown source
+const MARKDOWN_APPS: &[&str] = &["code", "obsidian", "typora", "sublime_text", "zed"];
+
+/// built-in format for an app, `app_name` in lower case
+pub fn default_format(app_name: &str) -> Option<InsertFormat> {
+    if RICH_TEXT_APPS.contains(&app_name) {
+        Some(InsertFormat::Rich)
+    } else if MARKDOWN_APPS.contains(&app_name) {
+        Some(InsertFormat::Markdown)
+    } else {
+        None
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+pub fn to_html(markdown: &str) -> String {
+    let mut output = String::new();
+    html::push_html(&mut output, Parser::new_ext(markdown, options()));
+    output
+}
+
+/// The text a reader sees in the rendered Markdown: markers removed, lists
+/// keep their bullets and numbers, links keep their url.
+pub fn to_plain_text(markdown: &str) -> String {
+    let mut output = String::new();
+    // next number of each open list, `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    // where the text of each open link starts
+    let mut links: Vec<usize> = Vec::new();
+    let mut first_cell = false;
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(tag) => match tag {
+                // a paragraph directly in a list item stays on the bullet's line
+                Tag::Paragraph if output.ends_with(' ') => {}
+                Tag::Paragraph
+                | Tag::Heading(..)
+                | Tag::BlockQuote
+                | Tag::Table(_)
+                | Tag::CodeBlock(_) => start_block(&mut output, lists.is_empty()),
+                Tag::List(start) => {
+                    if lists.is_empty() {
+                        start_block(&mut output, true);
+                    }
+                    lists.push(start);
+                }
+                Tag::Item => {
+                    start_line(&mut output);
+                    output.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                    match lists.last_mut() {
+                        Some(Some(number)) => {
+                            output.push_str(&format!("{}. ", number));
+                            *number += 1;
+                        }
+                        _ => output.push_str("- "),
+                    }
+                }
+                Tag::TableRow | Tag::TableHead => {
+                    start_line(&mut output);
+                    first_cell = true;
+                }
+                Tag::TableCell => {
+                    if !first_cell {
+                        output.push('\t');
+                    }
+                    first_cell = false;
+                }
+                Tag::Link(..) => links.push(output.len()),
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                Tag::List(_) => {
+                    lists.pop();
+                }
+                Tag::CodeBlock(CodeBlockKind::Fenced(_) | CodeBlockKind::Indented) => {
+                    while output.ends_with('\n') {
+                        output.pop();
+                    }
+                }
+                Tag::Link(_, url, _) => {
+                    let start = links.pop().unwrap_or(output.len());
+                    let text = &output[start..];
+                    if !url.is_empty() && text != url.as_ref() && !url.starts_with('#') {
+                        output.push_str(&format!(" ({})", url));
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(text) | Event::Code(text) | Event::Html(text) => output.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => output.push('\n'),
+            Event::Rule => {
+                start_block(&mut output, true);
+                output.push_str("---");
+            }
+            Event::TaskListMarker(checked) => {
+                output.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            Event::FootnoteReference(name) => output.push_str(&format!("[{}]", name)),
+        }
+    }
+    output.trim_end().to_string()
+}
+
+/// a block starts on a new line, separated by an empty line outside lists
+fn start_block(output: &mut String, blank_line: bool) {
+    if output.is_empty() {
+        return;
+    }
+    start_line(output);
+    if blank_line && !output.ends_with("\n\n") {
+        output.push('\n');
+    }
+}
+
+fn start_line(output: &mut String) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_and_emphasis_lose_their_markers() {
+        assert_eq!(
+            to_plain_text("# Title\n\nSome **bold** and *italic* `code`.\n\n## Next\n\ntext"),
+            "Title\n\nSome bold and italic code.\n\nNext\n\ntext"
+        );
+    }
+
+    #[test]
+    fn nested_lists_keep_bullets_and_numbers() {
+        let markdown = "1. one\n2. two\n   - a\n   - b\n3. three\n\nafter";
+        assert_eq!(
+            to_plain_text(markdown),
+            "1. one\n2. two\n  - a\n  - b\n3. three\n\nafter"
+        );
+        assert_eq!(to_plain_text("3. three\n4. four"), "3. three\n4. four");
+        assert_eq!(
+            to_plain_text("- [x] done\n- [ ] todo"),
+            "- [x] done\n- [ ] todo"
+        );
+    }
+
+    #[test]
+    fn fenced_code_is_kept_as_is() {
+        let markdown = "Run:\n\n```rust\nfn main() {\n    println!(\"*hi*\");\n}\n```\n\ndone";
+        assert_eq!(
+            to_plain_text(markdown),
+            "Run:\n\nfn main() {\n    println!(\"*hi*\");\n}\n\ndone"
+        );
+    }
+
+    #[test]
+    fn links_keep_their_url() {
+        assert_eq!(
+            to_plain_text("see [the docs](https://example.com/docs)"),
+            "see the docs (https://example.com/docs)"
+        );
+        assert_eq!(
+            to_plain_text("<https://example.com>"),
+            "https://example.com"
+        );
+        assert_eq!(to_plain_text("[below](#usage)"), "below");
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        assert_eq!(
+            to_html("# Title\n\n- a\n  1. b\n\n[x](https://x.y) ~~no~~"),
+            "<h1>Title</h1>\n<ul>\n<li>a\n<ol>\n<li>b</li>\n</ol>\n</li>\n</ul>\n\
+             <p><a href=\"https://x.y\">x</a> <del>no</del></p>\n"
+        );
+        assert_eq!(
+            to_html("```sh\necho <hi>\n```"),
+            "<pre><code class=\"language-sh\">echo &lt;hi&gt;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn apps_get_their_built_in_format() {
+        assert_eq!(default_format("winword"), Some(InsertFormat::Rich));
+        assert_eq!(default_format("code"), Some(InsertFormat::Markdown));
+        assert_eq!(default_format("gnome-terminal"), None);
+        // names are matched in lower case, see `TypingConfig::settings_for`
+        assert_eq!(default_format("WINWORD"), None);
+    }
+}
@@ -1,14 +1,293 @@
 use tauri::{AppHandle, Manager};
 
+use auto_input::{AutoInputJob, AutoInputQueue, Insertion, UndoPlan};
+
+pub mod accessibility;
+pub mod auto_input;
 pub mod foreground;
 pub mod input;
+pub mod markdown;
+
+/// bring back the window the current selection was made in
+pub fn restore_foreground_window(handle: &AppHandle) {
+    let state: tauri::State<crate::AppState> = handle.state();
+    let hwnd = state
+        .foreground_handle
+        .load(std::sync::atomic::Ordering::SeqCst);
+    if hwnd == 0 {
+        return;
+    }
+    set_foreground_window(hwnd);
+}
+
+fn set_foreground_window(hwnd: isize) {
+    #[cfg(target_os = "windows")]
+    foreground::PlatformForeground::set_foreground_window(hwnd);
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    if let Err(err) = foreground::PlatformForeground::set_foreground_window(hwnd) {
+        tracing::warn!(set_foreground_window_error = err);
+    }
+}
+
+/// the window in front right now, 0 when unknown
+fn current_foreground_window() -> isize {
+    #[cfg(target_os = "macos")]
+    let hwnd = foreground::PlatformForeground::get_foreground_window().unwrap_or(0);
+    #[cfg(not(target_os = "macos"))]
+    let hwnd = foreground::PlatformForeground::get_foreground_window();
+    hwnd
+}
+
+/// Whether `hwnd` is in front, restoring it if another window took its place.
+/// Without a recorded window there is nothing to check against and input goes
+/// to the focused window as before. Blocks while the focus is handed over.
+pub fn ensure_foreground_window(hwnd: isize) -> bool {
+    if hwnd == 0 || current_foreground_window() == hwnd {
+        return true;
+    }
+    set_foreground_window(hwnd);
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    current_foreground_window() == hwnd
+}
+
+/// sent to all windows whenever an auto input job changes
+pub const AUTO_INPUT_PROGRESS_EVENT: &str = "auto-input-progress";
 
-pub fn send_auto_input_value(handle: &AppHandle, content: String) -> Result<(), String> {
+/// connects the auto input worker to the app
+pub struct LiveAutoInputHost;
+
+impl auto_input::AutoInputHost for LiveAutoInputHost {
+    fn ensure_foreground(&mut self, job: &AutoInputJob) -> bool {
+        // the window of the job, later selections may be in other windows
+        ensure_foreground_window(job.window)
+    }
+
+    fn paused(&mut self, job: &AutoInputJob) {
+        if let Some(handle) = crate::APP.get() {
+            notify_auto_input_paused(handle, job);
+        }
+    }
+
+    fn progress(&mut self, job: &AutoInputJob) {
+        if let Some(handle) = crate::APP.get() {
+            emit_auto_input_progress(handle, job);
+        }
+    }
+}
+
+fn emit_auto_input_progress(handle: &AppHandle, job: &AutoInputJob) {
+    if let Err(err) = handle.emit_all(AUTO_INPUT_PROGRESS_EVENT, job) {
+        tracing::warn!(emit_auto_input_progress_error = ?err);
+    }
+}
+
+fn notify_auto_input_paused(handle: &AppHandle, job: &AutoInputJob) {
+    emit_auto_input_progress(handle, job);
+    crate::tray::refresh_tray_menu(handle);
     let state: tauri::State<crate::AppState> = handle.state();
+    let window = state.foreground_window.read().clone();
+    let name = job
+        .app_name
+        .clone()
+        .or(window.app_name)
+        .or(window.title)
+        .unwrap_or_default();
+    let identifier = handle.config().tauri.bundle.identifier.clone();
+    let result = tauri::api::notification::Notification::new(&identifier)
+        .title("自动输入已暂停")
+        .body(format!("{} 不在前台, 可在托盘菜单中继续或取消", name))
+        .show();
+    if let Err(err) = result {
+        tracing::warn!(show_notification_error = ?err);
+    }
+}
+
+/// Bring back the source window and paste `content` over its selection. Nothing
+/// is pasted if another window ends up in front, that would write into the
+/// wrong app.
+/// The Markdown of `content` is rendered in the app's insert format.
+pub fn replace_selection(handle: &AppHandle, content: String) -> anyhow::Result<()> {
+    let state: tauri::State<crate::AppState> = handle.state();
+    let hwnd = state
+        .foreground_handle
+        .load(std::sync::atomic::Ordering::SeqCst);
+    if hwnd == 0 {
+        return Err(anyhow::anyhow!(
+            "not found the window the text was selected in"
+        ));
+    }
+    restore_foreground_window(handle);
+    // the window manager needs a moment to hand over the focus
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    let current = current_foreground_window();
+    if current != hwnd {
+        return Err(anyhow::anyhow!(
+            "window {} is in front instead of {}, not replacing",
+            current,
+            hwnd
+        ));
+    }
+    let app_name = state.foreground_window.read().app_name.clone();
+    let format = crate::app_config::get_app_config()
+        .unwrap_or_default()
+        .typing
+        .unwrap_or_default()
+        .settings_for(app_name.as_deref())
+        .format;
+    match format {
+        markdown::InsertFormat::Markdown => crate::select::copy_and_paste(content),
+        markdown::InsertFormat::Plain => {
+            crate::select::copy_and_paste(markdown::to_plain_text(&content))
+        }
+        markdown::InsertFormat::Rich => crate::select::copy_and_paste_html(
+            markdown::to_html(&content),
+            markdown::to_plain_text(&content),
+        ),
+    }
+}
+
+fn auto_input_queue(handle: &AppHandle) -> AutoInputQueue {
+    let state: tauri::State<crate::AppState> = handle.state();
+    let queue = crate::command::get_or_init_auto_input(&state).clone();
+    queue
+}
+
+/// Start a job for a new answer, typed into the window of the current
+/// selection once the jobs before it are done.
+pub fn begin_auto_input(handle: &AppHandle) -> AutoInputJob {
+    let state: tauri::State<crate::AppState> = handle.state();
+    let app_name = state.foreground_window.read().app_name.clone();
+    let window = state
+        .foreground_handle
+        .load(std::sync::atomic::Ordering::SeqCst);
+    let settings = crate::app_config::get_app_config()
+        .unwrap_or_default()
+        .typing
+        .unwrap_or_default()
+        .settings_for(app_name.as_deref());
+    let job = auto_input_queue(handle).begin(settings, app_name, window);
+    emit_auto_input_progress(handle, &job);
+    job
+}
+
+/// the whole answer so far for job `job_id`
+pub async fn send_auto_input_value(
+    handle: &AppHandle,
+    job_id: u64,
+    content: String,
+) -> Result<(), String> {
     //crate::tauri_windows::search::show_foreground_window();
-    let answer_sender = crate::command::get_or_init_auto_input(&state);
-    answer_sender
-        .send(content)
-        .map_err(|err| format!("{:?}", err))?;
-    Ok(())
+    let queue = auto_input_queue(handle);
+    match queue.job(job_id) {
+        Some(job) if job.state.is_finished() || job.complete => {
+            return Err(format!("auto input job {} takes no more values", job_id))
+        }
+        Some(_) => {}
+        None => return Err(format!("not found auto input job {}", job_id)),
+    }
+    queue
+        .send_value(job_id, content)
+        .await
+        .map_err(|err| format!("{:?}", err))
+}
+
+/// the answer of job `job_id` is complete
+pub async fn end_auto_input(handle: &AppHandle, job_id: u64) -> Result<(), String> {
+    auto_input_queue(handle)
+        .end(job_id)
+        .await
+        .map_err(|err| format!("{:?}", err))
+}
+
+/// type a whole answer as a job of its own
+pub async fn insert_text(handle: &AppHandle, content: String) -> Result<AutoInputJob, String> {
+    let job = begin_auto_input(handle);
+    send_auto_input_value(handle, job.id, content).await?;
+    end_auto_input(handle, job.id).await?;
+    Ok(job)
+}
+
+pub fn list_auto_input_jobs(handle: &AppHandle) -> Vec<AutoInputJob> {
+    auto_input_queue(handle).jobs()
+}
+
+/// pause, resume or cancel one job and tell the windows and the tray
+pub fn control_auto_input_job(
+    handle: &AppHandle,
+    job_id: u64,
+    control: impl FnOnce(&AutoInputQueue, u64) -> anyhow::Result<AutoInputJob>,
+) -> Result<AutoInputJob, String> {
+    let job = control(&auto_input_queue(handle), job_id).map_err(|err| format!("{}", err))?;
+    emit_auto_input_progress(handle, &job);
+    crate::tray::refresh_tray_menu(handle);
+    Ok(job)
+}
+
+/// resume every paused job, the target window is brought back first
+pub fn resume_auto_input(handle: &AppHandle) {
+    for job_id in auto_input_queue(handle).active_job_ids() {
+        let _ = control_auto_input_job(handle, job_id, AutoInputQueue::resume);
+    }
+}
+
+/// cancel every job that isn't finished
+pub fn cancel_auto_input(handle: &AppHandle) {
+    let job_ids = auto_input_queue(handle).active_job_ids();
+    tracing::info!(cancel_auto_input =? job_ids);
+    for job_id in job_ids {
+        let _ = control_auto_input_job(handle, job_id, AutoInputQueue::cancel);
+    }
+}
+
+/// Take back what auto input typed last, with the app's undo or backspaces
+/// depending on how it was typed. The window it went into has to be in front,
+/// nothing is deleted in any other window.
+pub fn undo_last_insertion(handle: &AppHandle) -> anyhow::Result<Insertion> {
+    let queue = auto_input_queue(handle);
+    let mut insertion = queue
+        .last_insertion()
+        .ok_or_else(|| anyhow::anyhow!("nothing was inserted"))?;
+    if queue.active_job_ids().contains(&insertion.job_id) {
+        let _ = control_auto_input_job(handle, insertion.job_id, AutoInputQueue::cancel);
+        // let the chunk that is being typed finish
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        insertion = queue
+            .last_insertion()
+            .ok_or_else(|| anyhow::anyhow!("nothing was inserted"))?;
+    }
+    if insertion.window == 0 {
+        return Err(anyhow::anyhow!(
+            "not found the window auto input job {} typed into",
+            insertion.job_id
+        ));
+    }
+    if current_foreground_window() != insertion.window {
+        set_foreground_window(insertion.window);
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        let current = current_foreground_window();
+        if current != insertion.window {
+            return Err(anyhow::anyhow!(
+                "window {} is in front instead of {}, not undoing",
+                current,
+                insertion.window
+            ));
+        }
+    }
+    let plan = insertion.undo_plan();
+    tracing::info!(undo_last_insertion =? insertion, undo_plan =? plan);
+    let mut input = input::create_input(&insertion.settings);
+    match plan {
+        UndoPlan::Shortcut(count) => {
+            for _ in 0..count {
+                input.undo()?;
+            }
+        }
+        UndoPlan::Backspaces(count) => {
+            for _ in 0..count {
+                input.backspace()?;
+            }
+        }
+    }
+    queue.mark_undone(insertion.job_id)?;
+    Ok(insertion)
 }
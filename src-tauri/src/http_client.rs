@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::app_config::AppConfig;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What outbound connections need from the app config.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HttpSettings {
+    /// `http://`, `https://` or `socks5://` url, credentials may be in it
+    pub proxy: Option<String>,
+    pub proxy_username: Option<String>,
+    pub proxy_password: Option<String>,
+    /// hosts that bypass the proxy, in `NO_PROXY` syntax
+    pub no_proxy: Option<String>,
+    /// PEM files with extra root certificates, e.g. a corporate proxy's
+    pub ca_certs: Vec<String>,
+}
+
+impl HttpSettings {
+    pub fn from_config(app_config: &AppConfig) -> Self {
+        Self {
+            proxy: app_config
+                .proxy
+                .clone()
+                .filter(|proxy| !proxy.trim().is_empty()),
+            proxy_username: app_config.proxy_username.clone(),
+            proxy_password: app_config.proxy_password.clone(),
+            no_proxy: app_config
+                .no_proxy
+                .clone()
+                .or_else(|| std::env::var("NO_PROXY").ok())
+                .or_else(|| std::env::var("no_proxy").ok()),
+            ca_certs: app_config.ca_certs.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// `host:port` without a scheme is taken as an http proxy
+fn proxy_url(proxy: &str) -> String {
+    let proxy = proxy.trim();
+    if proxy.contains("://") {
+        proxy.to_string()
+    } else {
+        format!("http://{}", proxy)
+    }
+}
+
+fn build_proxy(settings: &HttpSettings, proxy: &str) -> Result<reqwest::Proxy> {
+    let url = proxy_url(proxy);
+    let scheme = url.split("://").next().unwrap_or_default();
+    if !matches!(scheme, "http" | "https" | "socks5" | "socks5h") {
+        return Err(anyhow!("unsupported proxy scheme {}", scheme));
+    }
+    let mut proxy = reqwest::Proxy::all(&url).with_context(|| format!("invalid proxy {}", url))?;
+    if let Some(username) = settings
+        .proxy_username
+        .as_deref()
+        .filter(|username| !username.is_empty())
+    {
+        proxy = proxy.basic_auth(username, settings.proxy_password.as_deref().unwrap_or(""));
+    }
+    let no_proxy = settings
+        .no_proxy
+        .as_deref()
+        .and_then(reqwest::NoProxy::from_string);
+    Ok(proxy.no_proxy(no_proxy))
+}
+
+pub fn build_client(settings: &HttpSettings) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().connect_timeout(CONNECT_TIMEOUT);
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(build_proxy(settings, proxy)?);
+    }
+    for path in &settings.ca_certs {
+        let pem = std::fs::read(path).with_context(|| format!("read ca cert {}", path))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("invalid ca cert {}", path))?;
+        if certs.is_empty() {
+            return Err(anyhow!("no certificate in ca cert {}", path));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// the last client built and the settings it was built with
+static CLIENT: Lazy<Mutex<Option<(HttpSettings, reqwest::Client)>>> =
+    Lazy::new(|| Mutex::new(None));
+
+/// The client every outbound request should use, so the proxy and
+/// certificates of the app config apply. Rebuilt when they change.
+///
+/// The Tauri updater downloads with a client of its own and doesn't go
+/// through this one, so it ignores the proxy and certificates of the config.
+pub fn client() -> Result<reqwest::Client> {
+    let app_config = crate::app_config::get_app_config()
+        .map_err(|err| anyhow!("read app config error {}", err))?;
+    let settings = HttpSettings::from_config(&app_config);
+    let mut cached = CLIENT.lock();
+    if let Some((cached_settings, client)) = cached.as_ref() {
+        if cached_settings == &settings {
+            return Ok(client.clone());
+        }
+    }
+    // the proxy url may hold a password
+    tracing::info!(
+        http_proxy = settings.proxy.is_some(),
+        no_proxy =? settings.no_proxy,
+        ca_certs =? settings.ca_certs
+    );
+    let client = build_client(&settings)?;
+    *cached = Some((settings, client.clone()));
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use super::*;
+
+    /// answers one request and hands back what it received
+    fn serve_once() -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .unwrap();
+            String::from_utf8_lossy(&request).to_lowercase()
+        });
+        (address, server)
+    }
+
+    fn temp_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn settings_come_from_the_app_config() {
+        let app_config = AppConfig {
+            proxy: Some("  ".to_string()),
+            no_proxy: Some("localhost,.corp".to_string()),
+            ca_certs: Some(vec!["/etc/corp.pem".to_string()]),
+            ..AppConfig::default()
+        };
+        let settings = HttpSettings::from_config(&app_config);
+        assert_eq!(settings.proxy, None);
+        assert_eq!(settings.no_proxy.as_deref(), Some("localhost,.corp"));
+        assert_eq!(settings.ca_certs, vec!["/etc/corp.pem".to_string()]);
+    }
+
+    #[test]
+    fn only_known_proxy_schemes_are_taken() {
+        assert_eq!(proxy_url("127.0.0.1:7890"), "http://127.0.0.1:7890");
+        assert_eq!(proxy_url(" socks5://host:1080 "), "socks5://host:1080");
+        let settings = HttpSettings::default();
+        for proxy in [
+            "127.0.0.1:7890",
+            "http://proxy.corp:8080",
+            "https://proxy.corp:8443",
+            // the host of a socks5 proxy is resolved right away
+            "socks5://127.0.0.1:1080",
+            "socks5h://127.0.0.1:1080",
+        ] {
+            assert!(build_proxy(&settings, proxy).is_ok(), "{}", proxy);
+        }
+        for proxy in ["ftp://proxy.corp:21", "socks4://127.0.0.1:1080", "http://"] {
+            assert!(build_proxy(&settings, proxy).is_err(), "{}", proxy);
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_the_proxy_with_its_credentials() {
+        let (proxy, server) = serve_once();
+        let client = build_client(&HttpSettings {
+            proxy: Some(proxy),
+            proxy_username: Some("user".to_string()),
+            proxy_password: Some("pass".to_string()),
+            ..HttpSettings::default()
+        })
+        .unwrap();
+        let response = client
+            .get("http://example.com/models")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+        let request = server.join().unwrap();
+        assert!(request.starts_with("get http://example.com/models http/1.1\r\n"));
+        // base64 of `user:pass`
+        assert!(request.contains("proxy-authorization: basic dxnlcjpwyxnz\r\n"));
+    }
+
+    #[tokio::test]
+    async fn no_proxy_hosts_are_reached_directly() {
+        let (target, server) = serve_once();
+        let client = build_client(&HttpSettings {
+            // nothing listens there, a proxied request would fail
+            proxy: Some("http://127.0.0.1:9".to_string()),
+            no_proxy: Some("example.com, 127.0.0.1".to_string()),
+            ..HttpSettings::default()
+        })
+        .unwrap();
+        let response = client
+            .get(format!("http://{}/models", target))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("get /models http/1.1\r\n"));
+    }
+
+    #[test]
+    fn ca_bundles_must_hold_certificates() {
+        let with_certs = |path: &str| HttpSettings {
+            ca_certs: vec![path.to_string()],
+            ..HttpSettings::default()
+        };
+        let err = build_client(&with_certs("/nonexistent/corp.pem")).unwrap_err();
+        assert!(format!("{:#}", err).starts_with("read ca cert /nonexistent/corp.pem"));
+
+        let path = temp_file("not-a-cert.pem", "not a certificate");
+        let err = build_client(&with_certs(&path)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("no certificate in ca cert {}", path)
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let path = temp_file(
+            "broken-cert.pem",
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n",
+        );
+        let err = build_client(&with_certs(&path)).unwrap_err();
+        assert_eq!(err.to_string(), format!("invalid ca cert {}", path));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use super::provider::{parse_json, stream_error, LlmProvider, StreamEvent, TokenUsage};
+use super::sse::SseEvent;
+use super::{ChatRequest, LlmError};
+
+pub const DEFAULT_HOST: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+/// the messages api needs a limit, this one is used when the request has none
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Anthropic's messages api. System messages go in a field of their own and
+/// the answer streams as typed events.
+pub struct AnthropicProvider {
+    host: String,
+    api_key: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamData {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    delta: Option<TextDelta>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+/// `message_start` has the prompt's usage, `message_delta` the answer's so far
+#[derive(Deserialize, Debug)]
+struct UsageData {
+    #[serde(default)]
+    message: Option<UsageMessage>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct UsageMessage {
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: Option<usize>,
+    #[serde(default)]
+    output_tokens: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TextDelta {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl AnthropicProvider {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        Self {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+fn request_body(request: &ChatRequest) -> serde_json::Value {
+    let system: Vec<&str> = request
+        .messages
+        .iter()
+        .filter(|message| message.role == "system")
+        .map(|message| message.content.as_str())
+        .collect();
+    let messages: Vec<_> = request
+        .messages
+        .iter()
+        .filter(|message| message.role != "system")
+        .collect();
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": true,
+    });
+    if !system.is_empty() {
+        body["system"] = json!(system.join("\n\n"));
+    }
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    body
+}
+
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        request: &ChatRequest,
+    ) -> Result<reqwest::RequestBuilder, LlmError> {
+        Ok(client
+            .post(format!("{}/v1/messages", self.host))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&request_body(request)))
+    }
+
+    fn parse_event(&self, event: &SseEvent) -> Result<StreamEvent, LlmError> {
+        let data: StreamData = parse_json(&event.data)?;
+        let kind = event.event.as_deref().unwrap_or(&data.kind);
+        match kind {
+            "content_block_delta" => Ok(StreamEvent::Delta(
+                data.delta.and_then(|delta| delta.text).unwrap_or_default(),
+            )),
+            "message_stop" => Ok(StreamEvent::Done),
+            "error" => Err(stream_error(&data.error.unwrap_or_default())),
+            _ => Ok(StreamEvent::Ignore),
+        }
+    }
+
+    fn parse_usage(&self, event: &SseEvent) -> Option<TokenUsage> {
+        if !event.data.contains("\"usage\"") {
+            return None;
+        }
+        let data: UsageData = serde_json::from_str(&event.data).ok()?;
+        let usage = data
+            .usage
+            .or_else(|| data.message.and_then(|message| message.usage))?;
+        Some(TokenUsage {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use super::provider::{LlmProvider, StreamEvent, TokenUsage};
+use super::sse::SseEvent;
+use super::{ChatRequest, LlmError};
+
+pub const DEFAULT_API_VERSION: &str = "2024-02-01";
+/// first api version that takes `stream_options`
+const STREAM_USAGE_API_VERSION: &str = "2024-09-01-preview";
+
+/// Azure OpenAI: the model is picked by the deployment in the url and the key
+/// goes in an `api-key` header. Bodies and streams are OpenAI's.
+pub struct AzureProvider {
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    api_key: String,
+}
+
+impl AzureProvider {
+    pub fn new(endpoint: &str, deployment: &str, api_version: &str, api_key: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            deployment: deployment.to_string(),
+            api_version: api_version.to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+impl LlmProvider for AzureProvider {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        request: &ChatRequest,
+    ) -> Result<reqwest::RequestBuilder, LlmError> {
+        if self.deployment.is_empty() {
+            return Err(LlmError::InvalidRequest {
+                message: "azure profile without deployment".to_string(),
+            });
+        }
+        Ok(client
+            .post(format!(
+                "{}/openai/deployments/{}/chat/completions",
+                self.endpoint, self.deployment
+            ))
+            .query(&[("api-version", &self.api_version)])
+            .header("api-key", &self.api_key)
+            .json(&super::openai::request_body(
+                request,
+                // versions are dates, so they sort as strings
+                self.api_version.as_str() >= STREAM_USAGE_API_VERSION,
+            )))
+    }
+
+    fn parse_event(&self, event: &SseEvent) -> Result<StreamEvent, LlmError> {
+        super::openai::parse_event(event)
+    }
+
+    fn parse_usage(&self, event: &SseEvent) -> Option<TokenUsage> {
+        super::openai::parse_usage(event)
+    }
+}
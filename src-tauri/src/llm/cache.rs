@@ -0,0 +1,514 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{ChatMessage, ChatRequest};
+
+const DEFAULT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_MAX_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+/// hit counters alone are written at most this often, and on exit
+const HITS_WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `responseCache` of the app config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheConfig {
+    /// off unless turned on
+    #[serde(default)]
+    pub enabled: bool,
+    /// answers older than this are asked again
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// prompts and answers together, the least recently used go first
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_ttl_secs() -> u64 {
+    DEFAULT_TTL_SECS
+}
+
+fn default_max_bytes() -> usize {
+    DEFAULT_MAX_BYTES
+}
+
+fn default_max_entries() -> usize {
+    DEFAULT_MAX_ENTRIES
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: DEFAULT_TTL_SECS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn load() -> Self {
+        crate::app_config::get_app_config()
+            .ok()
+            .and_then(|app_config| app_config.response_cache)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub key: String,
+    /// profile or host the answer came from
+    pub source: String,
+    pub provider: String,
+    pub model: String,
+    #[serde(default)]
+    pub action_id: Option<String>,
+    /// the last message of the prompt, to tell entries apart when listing
+    pub prompt: String,
+    pub response: String,
+    /// seconds since the unix epoch
+    pub created_at: u64,
+    pub last_hit_at: u64,
+    #[serde(default)]
+    pub hits: u64,
+}
+
+impl CacheEntry {
+    fn size(&self) -> usize {
+        self.prompt.len() + self.response.len()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: u64,
+    pub ttl_secs: u64,
+    pub max_bytes: usize,
+    pub max_entries: usize,
+}
+
+/// what makes two requests get the same answer
+#[derive(Serialize)]
+struct KeyMaterial<'a> {
+    source: &'a str,
+    provider: &'a str,
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    max_tokens: Option<u32>,
+    temperature: Option<f32>,
+}
+
+/// Hex sha256 of everything that goes into the answer. `request` must be
+/// the one sent, after the context was trimmed.
+pub fn cache_key(source: &str, provider: &str, request: &ChatRequest) -> String {
+    let material = KeyMaterial {
+        source,
+        provider,
+        model: &request.model,
+        messages: &request.messages,
+        max_tokens: request.max_tokens,
+        temperature: request.temperature,
+    };
+    let json = serde_json::to_vec(&material).unwrap_or_default();
+    Sha256::digest(&json)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Drop expired entries, then the least recently used ones until the
+/// limits hold. Returns whether anything was dropped.
+fn prune(entries: &mut Vec<CacheEntry>, config: &CacheConfig, now: u64) -> bool {
+    let before = entries.len();
+    entries.retain(|entry| entry.created_at.saturating_add(config.ttl_secs) > now);
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_hit_at));
+    let mut bytes = 0;
+    let mut keep = 0;
+    for entry in entries.iter() {
+        if keep >= config.max_entries || bytes + entry.size() > config.max_bytes {
+            break;
+        }
+        bytes += entry.size();
+        keep += 1;
+    }
+    entries.truncate(keep);
+    entries.len() != before
+}
+
+/// Answers of earlier requests, kept in `llm-cache.json` next to the app
+/// config and loaded on first use.
+#[derive(Default)]
+pub struct ResponseCache {
+    /// the file of the app config dir when missing
+    path: Option<PathBuf>,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: Option<Vec<CacheEntry>>,
+    /// since when hit counters changed that aren't written yet
+    unsaved_hits: Option<Instant>,
+}
+
+/// what a change did to the entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    None,
+    /// only hit counters and last use
+    Hits,
+    Entries,
+}
+
+impl Change {
+    fn entries_if(changed: bool) -> Self {
+        if changed {
+            Change::Entries
+        } else {
+            Change::None
+        }
+    }
+}
+
+fn cache_path() -> Option<PathBuf> {
+    crate::app_config::app_config_dir().map(|dir| dir.join("llm-cache.json"))
+}
+
+fn read_entries(path: Option<&Path>) -> Vec<CacheEntry> {
+    let content = match path {
+        Some(path) if path.exists() => std::fs::read_to_string(path),
+        _ => return Vec::new(),
+    };
+    match content.map(|content| serde_json::from_str::<Vec<CacheEntry>>(&content)) {
+        Ok(Ok(entries)) => entries,
+        Ok(Err(err)) => {
+            tracing::warn!(parse_llm_cache_error = ?err);
+            Vec::new()
+        }
+        Err(err) => {
+            tracing::warn!(read_llm_cache_error = ?err);
+            Vec::new()
+        }
+    }
+}
+
+fn write_entries(path: Option<&Path>, entries: &[CacheEntry]) {
+    let path = match path {
+        Some(path) => path,
+        None => return,
+    };
+    let result = serde_json::to_string(entries)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| std::fs::write(path, content).map_err(anyhow::Error::from));
+    if let Err(err) = result {
+        tracing::warn!(write_llm_cache_error = ?err);
+    }
+}
+
+impl ResponseCache {
+    /// a cache kept in `path` instead of the app config dir
+    pub fn at(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            state: Mutex::default(),
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.path.clone().or_else(cache_path)
+    }
+
+    /// Entries are written when they change. Hits are counted in memory and
+    /// only written with the next change, once they are older than
+    /// `HITS_WRITE_INTERVAL` or by `flush`.
+    fn with_entries<T>(&self, change: impl FnOnce(&mut Vec<CacheEntry>) -> (T, Change)) -> T {
+        let path = self.path();
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        let entries = state
+            .entries
+            .get_or_insert_with(|| read_entries(path.as_deref()));
+        let (result, change) = change(entries);
+        let now = Instant::now();
+        if change == Change::Hits && state.unsaved_hits.is_none() {
+            state.unsaved_hits = Some(now);
+        }
+        let hits_due = state
+            .unsaved_hits
+            .map(|since| now.duration_since(since) >= HITS_WRITE_INTERVAL)
+            .unwrap_or(false);
+        if change == Change::Entries || hits_due {
+            write_entries(path.as_deref(), entries);
+            state.unsaved_hits = None;
+        }
+        result
+    }
+
+    /// write hit counters that weren't written yet
+    pub fn flush(&self) {
+        let mut state = self.state.lock();
+        if state.unsaved_hits.take().is_some() {
+            if let Some(entries) = &state.entries {
+                write_entries(self.path().as_deref(), entries);
+            }
+        }
+    }
+
+    /// the cached answer for `key`, counted as a hit
+    pub fn get(&self, config: &CacheConfig, key: &str) -> Option<String> {
+        let now = now_secs();
+        self.with_entries(|entries| {
+            let pruned = prune(entries, config, now);
+            match entries.iter_mut().find(|entry| entry.key == key) {
+                Some(entry) => {
+                    entry.hits += 1;
+                    entry.last_hit_at = now;
+                    let change = if pruned {
+                        Change::Entries
+                    } else {
+                        Change::Hits
+                    };
+                    (Some(entry.response.clone()), change)
+                }
+                None => (None, Change::entries_if(pruned)),
+            }
+        })
+    }
+
+    pub fn insert(&self, config: &CacheConfig, entry: CacheEntry) {
+        let now = now_secs();
+        self.with_entries(|entries| {
+            entries.retain(|existing| existing.key != entry.key);
+            entries.push(entry);
+            prune(entries, config, now);
+            ((), Change::Entries)
+        })
+    }
+
+    /// entries still within the ttl, most recently used first
+    pub fn list(&self, config: &CacheConfig) -> Vec<CacheEntry> {
+        let now = now_secs();
+        self.with_entries(|entries| {
+            let pruned = prune(entries, config, now);
+            (entries.clone(), Change::entries_if(pruned))
+        })
+    }
+
+    pub fn stats(&self, config: &CacheConfig) -> CacheStats {
+        let entries = self.list(config);
+        CacheStats {
+            enabled: config.enabled,
+            entries: entries.len(),
+            bytes: entries.iter().map(CacheEntry::size).sum(),
+            hits: entries.iter().map(|entry| entry.hits).sum(),
+            ttl_secs: config.ttl_secs,
+            max_bytes: config.max_bytes,
+            max_entries: config.max_entries,
+        }
+    }
+
+    /// drop the entry of `key`, or every entry when missing; returns how many
+    pub fn clear(&self, key: Option<&str>) -> usize {
+        self.with_entries(|entries| {
+            let before = entries.len();
+            match key {
+                Some(key) => entries.retain(|entry| entry.key != key),
+                None => entries.clear(),
+            }
+            let removed = before - entries.len();
+            (removed, Change::entries_if(removed > 0))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "gpt-4o".to_string(),
+            messages: vec![message("system", "translate"), message("user", "hallo")],
+            max_tokens: Some(64),
+            temperature: Some(0.5),
+        }
+    }
+
+    fn entry(key: &str, response: &str, created_at: u64, last_hit_at: u64) -> CacheEntry {
+        CacheEntry {
+            key: key.to_string(),
+            source: "profile:default".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            action_id: Some("translate".to_string()),
+            prompt: "hallo".to_string(),
+            response: response.to_string(),
+            created_at,
+            last_hit_at,
+            hits: 0,
+        }
+    }
+
+    fn keys(entries: &[CacheEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.key.as_str()).collect()
+    }
+
+    /// a cache file of its own in the temp dir, removed when dropped
+    struct TempCache {
+        path: PathBuf,
+    }
+
+    impl TempCache {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "quick-ai-{}-{}-{}.json",
+                name,
+                std::process::id(),
+                now_secs()
+            ));
+            let _ = std::fs::remove_file(&path);
+            Self { path }
+        }
+
+        fn open(&self) -> ResponseCache {
+            ResponseCache::at(self.path.clone())
+        }
+
+        fn saved(&self) -> Vec<CacheEntry> {
+            read_entries(Some(&self.path))
+        }
+    }
+
+    impl Drop for TempCache {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    #[test]
+    fn keys_are_stable_and_cover_the_request() {
+        let key = cache_key("profile:default", "openai", &request());
+        assert_eq!(key, cache_key("profile:default", "openai", &request()));
+        assert_eq!(key.len(), 64);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+
+        assert_ne!(key, cache_key("profile:work", "openai", &request()));
+        assert_ne!(key, cache_key("profile:default", "azure", &request()));
+        let mut other = request();
+        other.model = "gpt-4o-mini".to_string();
+        assert_ne!(key, cache_key("profile:default", "openai", &other));
+        let mut other = request();
+        other.messages[1].content = "hallo!".to_string();
+        assert_ne!(key, cache_key("profile:default", "openai", &other));
+        let mut other = request();
+        other.temperature = None;
+        assert_ne!(key, cache_key("profile:default", "openai", &other));
+        let mut other = request();
+        other.max_tokens = Some(65);
+        assert_ne!(key, cache_key("profile:default", "openai", &other));
+    }
+
+    #[test]
+    fn expired_entries_are_dropped() {
+        let config = CacheConfig {
+            ttl_secs: 100,
+            ..CacheConfig::default()
+        };
+        let mut entries = vec![
+            entry("old", "a", 1000, 1090),
+            entry("fresh", "b", 1001, 1001),
+        ];
+        assert!(!prune(&mut entries, &config, 1099));
+        assert_eq!(keys(&entries), vec!["old", "fresh"]);
+        // hits don't extend the ttl
+        assert!(prune(&mut entries, &config, 1100));
+        assert_eq!(keys(&entries), vec!["fresh"]);
+    }
+
+    #[test]
+    fn the_least_recently_used_are_evicted_first() {
+        let config = CacheConfig {
+            max_entries: 2,
+            ..CacheConfig::default()
+        };
+        let mut entries = vec![
+            entry("a", "x", 10, 30),
+            entry("b", "x", 20, 10),
+            entry("c", "x", 30, 20),
+        ];
+        assert!(prune(&mut entries, &config, 40));
+        assert_eq!(keys(&entries), vec!["a", "c"]);
+
+        // prompt and answer are 10 bytes each, so two fit in 25
+        let config = CacheConfig {
+            max_bytes: 25,
+            ..CacheConfig::default()
+        };
+        let mut entries = vec![
+            entry("a", "12345", 10, 10),
+            entry("b", "12345", 10, 30),
+            entry("c", "12345", 10, 20),
+        ];
+        assert!(prune(&mut entries, &config, 40));
+        assert_eq!(keys(&entries), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn answers_are_kept_on_disk() {
+        let temp = TempCache::new("cache-disk");
+        let config = CacheConfig::default();
+        let now = now_secs();
+        let cache = temp.open();
+        assert_eq!(cache.get(&config, "k"), None);
+        cache.insert(&config, entry("k", "bonjour", now, now));
+        assert_eq!(keys(&temp.saved()), vec!["k"]);
+
+        let reopened = temp.open();
+        assert_eq!(reopened.get(&config, "k").as_deref(), Some("bonjour"));
+        assert_eq!(reopened.clear(Some("k")), 1);
+        assert!(temp.saved().is_empty());
+    }
+
+    #[test]
+    fn hits_are_written_on_flush() {
+        let temp = TempCache::new("cache-hits");
+        let config = CacheConfig::default();
+        let now = now_secs();
+        let cache = temp.open();
+        cache.insert(&config, entry("k", "bonjour", now, now));
+        assert!(cache.get(&config, "k").is_some());
+        assert!(cache.get(&config, "k").is_some());
+        assert_eq!(cache.stats(&config).hits, 2);
+        // counted in memory only
+        assert_eq!(temp.saved()[0].hits, 0);
+        cache.flush();
+        assert_eq!(temp.saved()[0].hits, 2);
+    }
+}
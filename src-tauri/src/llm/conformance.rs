@@ -0,0 +1,550 @@
+//! Every provider against the stub server, built from its profile config:
+//! where the request goes, how it authenticates, how the stream is read and
+//! how errors are mapped.
+
+use std::time::Duration;
+
+use super::profile::ProviderConfig;
+use super::provider::{stream_chat, LlmProvider, TokenUsage};
+use super::stub::{client, Reply, StubServer};
+use super::{ChatMessage, ChatRequest, ErrorCode, LlmError, Timeouts};
+
+struct Case {
+    name: &'static str,
+    config: fn(&str) -> ProviderConfig,
+    /// path and query of the request
+    path: &'static str,
+    /// header and value carrying the key
+    auth: (&'static str, &'static str),
+    /// streams "Hello" and ", world" with a usage of 12 and 3 tokens, then
+    /// text that comes after the end
+    answer: Reply,
+    /// an error object after "Hello"
+    stream_error: (Reply, ErrorCode),
+    /// error responses and the codes they map to
+    errors: Vec<(Reply, ErrorCode, &'static str)>,
+}
+
+fn json_error(status: u16, body: &str) -> Reply {
+    Reply::new(status)
+        .header("content-type", "application/json")
+        .body(body)
+}
+
+fn openai_stream(events: &[&str]) -> Reply {
+    let chunks: Vec<String> = events
+        .iter()
+        .map(|data| format!("data: {}\n\n", data))
+        .collect();
+    let chunks: Vec<&[u8]> = chunks.iter().map(|chunk| chunk.as_bytes()).collect();
+    Reply::stream(&chunks)
+}
+
+fn openai_answer() -> Reply {
+    openai_stream(&[
+        r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""}}]}"#,
+        r#"{"choices":[{"index":0,"delta":{"content":"Hello"}}]}"#,
+        r#"{"choices":[{"index":0,"delta":{"content":", world"}}]}"#,
+        r#"{"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+        r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#,
+        "[DONE]",
+        r#"{"choices":[{"index":0,"delta":{"content":"after the end"}}]}"#,
+    ])
+}
+
+fn openai_stream_error() -> (Reply, ErrorCode) {
+    (
+        openai_stream(&[
+            r#"{"choices":[{"index":0,"delta":{"content":"Hello"}}]}"#,
+            r#"{"error":{"message":"The server had an error while processing your request","type":"server_error"}}"#,
+        ]),
+        ErrorCode::Server,
+    )
+}
+
+fn openai_errors() -> Vec<(Reply, ErrorCode, &'static str)> {
+    vec![
+        (
+            json_error(
+                401,
+                r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error","code":"invalid_api_key"}}"#,
+            ),
+            ErrorCode::Auth,
+            "Incorrect API key provided",
+        ),
+        (
+            json_error(
+                400,
+                r#"{"error":{"message":"This model's maximum context length is 8192 tokens","code":"context_length_exceeded"}}"#,
+            ),
+            ErrorCode::ContextTooLong,
+            "This model's maximum context length is 8192 tokens",
+        ),
+        (
+            json_error(
+                429,
+                r#"{"error":{"message":"You exceeded your current quota","code":"insufficient_quota"}}"#,
+            ),
+            ErrorCode::Quota,
+            "You exceeded your current quota",
+        ),
+        (
+            json_error(
+                503,
+                r#"{"error":{"message":"The engine is currently overloaded"}}"#,
+            ),
+            ErrorCode::Server,
+            "The engine is currently overloaded",
+        ),
+    ]
+}
+
+fn anthropic_stream(events: &[(&str, &str)]) -> Reply {
+    let chunks: Vec<String> = events
+        .iter()
+        .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+        .collect();
+    let chunks: Vec<&[u8]> = chunks.iter().map(|chunk| chunk.as_bytes()).collect();
+    Reply::stream(&chunks)
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "openai",
+            config: |url| ProviderConfig::OpenAi {
+                host: Some(format!("{}/", url)),
+                api_key: "sk-openai".to_string(),
+            },
+            path: "/v1/chat/completions",
+            auth: ("authorization", "Bearer sk-openai"),
+            answer: openai_answer(),
+            stream_error: openai_stream_error(),
+            errors: openai_errors(),
+        },
+        Case {
+            name: "azure",
+            config: |url| ProviderConfig::Azure {
+                endpoint: url.to_string(),
+                deployment: "gpt-4o".to_string(),
+                api_version: None,
+                api_key: "azure-key".to_string(),
+            },
+            path: "/openai/deployments/gpt-4o/chat/completions?api-version=2024-02-01",
+            auth: ("api-key", "azure-key"),
+            answer: openai_answer(),
+            stream_error: openai_stream_error(),
+            errors: openai_errors(),
+        },
+        Case {
+            name: "anthropic",
+            config: |url| ProviderConfig::Anthropic {
+                host: Some(url.to_string()),
+                api_key: "sk-ant".to_string(),
+            },
+            path: "/v1/messages",
+            auth: ("x-api-key", "sk-ant"),
+            answer: anthropic_stream(&[
+                (
+                    "message_start",
+                    r#"{"type":"message_start","message":{"id":"msg_1","role":"assistant","content":[],"usage":{"input_tokens":12,"output_tokens":1}}}"#,
+                ),
+                (
+                    "content_block_start",
+                    r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                ),
+                ("ping", r#"{"type":"ping"}"#),
+                (
+                    "content_block_delta",
+                    r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}"#,
+                ),
+                (
+                    "content_block_delta",
+                    r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":", world"}}"#,
+                ),
+                (
+                    "content_block_stop",
+                    r#"{"type":"content_block_stop","index":0}"#,
+                ),
+                (
+                    "message_delta",
+                    r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":3}}"#,
+                ),
+                ("message_stop", r#"{"type":"message_stop"}"#),
+                (
+                    "content_block_delta",
+                    r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"after the end"}}"#,
+                ),
+            ]),
+            stream_error: (
+                anthropic_stream(&[
+                    (
+                        "content_block_delta",
+                        r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}"#,
+                    ),
+                    (
+                        "error",
+                        r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+                    ),
+                ]),
+                ErrorCode::Server,
+            ),
+            errors: vec![
+                (
+                    json_error(
+                        401,
+                        r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+                    ),
+                    ErrorCode::Auth,
+                    "invalid x-api-key",
+                ),
+                (
+                    json_error(
+                        400,
+                        r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 200001 tokens > 200000 maximum"}}"#,
+                    ),
+                    ErrorCode::ContextTooLong,
+                    "prompt is too long: 200001 tokens > 200000 maximum",
+                ),
+                (
+                    json_error(
+                        400,
+                        r#"{"type":"error","error":{"type":"invalid_request_error","message":"Your credit balance is too low to access the Anthropic API"}}"#,
+                    ),
+                    ErrorCode::Quota,
+                    "Your credit balance is too low to access the Anthropic API",
+                ),
+                (
+                    json_error(
+                        529,
+                        r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+                    ),
+                    ErrorCode::Server,
+                    "Overloaded",
+                ),
+            ],
+        },
+        Case {
+            name: "ollama",
+            config: |url| ProviderConfig::Ollama {
+                host: Some(url.to_string()),
+                api_key: Some("ollama-token".to_string()),
+            },
+            path: "/api/chat",
+            auth: ("authorization", "Bearer ollama-token"),
+            answer: Reply::new(200)
+                .header("content-type", "application/x-ndjson")
+                .body(concat!(
+                    r#"{"model":"llama3","message":{"role":"assistant","content":"Hello"},"done":false}"#,
+                    "\n",
+                    r#"{"model":"llama3","message":{"role":"assistant","content":", world"},"done":false}"#,
+                    "\n\n",
+                    r#"{"model":"llama3","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":12,"eval_count":3}"#,
+                    "\n",
+                    r#"{"model":"llama3","message":{"role":"assistant","content":"after the end"},"done":false}"#,
+                    "\n",
+                )),
+            stream_error: (
+                Reply::new(200).body(concat!(
+                    r#"{"model":"llama3","message":{"role":"assistant","content":"Hello"},"done":false}"#,
+                    "\n",
+                    r#"{"error":"an unknown error was encountered while running the model"}"#,
+                    "\n",
+                )),
+                ErrorCode::InvalidRequest,
+            ),
+            errors: vec![
+                (
+                    json_error(404, r#"{"error":"model \"llama3\" not found, try pulling it first"}"#),
+                    ErrorCode::InvalidRequest,
+                    "model \"llama3\" not found, try pulling it first",
+                ),
+                (
+                    json_error(500, r#"{"error":"llama runner process has terminated"}"#),
+                    ErrorCode::Server,
+                    "llama runner process has terminated",
+                ),
+            ],
+        },
+    ]
+}
+
+fn message(role: &str, content: &str) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: content.to_string(),
+    }
+}
+
+fn request() -> ChatRequest {
+    ChatRequest {
+        model: "test-model".to_string(),
+        messages: vec![
+            message("system", "be brief"),
+            message("user", "hi"),
+            message("assistant", "hello"),
+            message("system", "answer in english"),
+            message("user", "say hello to the world"),
+        ],
+        max_tokens: Some(64),
+        temperature: Some(0.5),
+    }
+}
+
+struct Sent {
+    server: StubServer,
+    result: Result<String, LlmError>,
+    streamed: String,
+    usage: TokenUsage,
+}
+
+async fn send(case: &Case, reply: Reply) -> Sent {
+    let server = StubServer::start(vec![reply]);
+    let provider = (case.config)(&server.url).build();
+    let timeouts = Timeouts {
+        response: Duration::from_secs(5),
+        idle: Duration::from_secs(5),
+    };
+    let mut streamed = String::new();
+    let mut usage = TokenUsage::default();
+    let result = stream_chat(
+        provider.as_ref(),
+        &client(),
+        &request(),
+        timeouts,
+        |delta| streamed.push_str(delta),
+        |reported| usage.merge(reported),
+    )
+    .await;
+    Sent {
+        server,
+        result,
+        streamed,
+        usage,
+    }
+}
+
+#[tokio::test]
+async fn requests_go_to_the_provider_url_with_its_key() {
+    for case in cases() {
+        let Sent { server, result, .. } = send(&case, case.answer.clone()).await;
+        let received = server.received();
+        assert_eq!(received.len(), 1, "{}", case.name);
+        let request = &received[0];
+        assert_eq!(request.method, "POST", "{}", case.name);
+        assert_eq!(request.path, case.path, "{}", case.name);
+        assert_eq!(
+            request.header(case.auth.0),
+            Some(case.auth.1),
+            "{}",
+            case.name
+        );
+        assert_eq!(
+            request.header("content-type"),
+            Some("application/json"),
+            "{}",
+            case.name
+        );
+        let body = request.json();
+        assert_eq!(body["stream"], true, "{}", case.name);
+        if case.name != "azure" {
+            assert_eq!(body["model"], "test-model", "{}", case.name);
+        }
+        assert!(result.is_ok(), "{}: {:?}", case.name, result);
+    }
+}
+
+#[tokio::test]
+async fn streams_are_read_in_the_provider_format() {
+    for case in cases() {
+        let Sent {
+            result, streamed, ..
+        } = send(&case, case.answer.clone()).await;
+        assert_eq!(result.unwrap(), "Hello, world", "{}", case.name);
+        assert_eq!(streamed, "Hello, world", "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn usage_is_read_from_the_stream() {
+    for case in cases() {
+        let Sent { usage, .. } = send(&case, case.answer.clone()).await;
+        assert_eq!(
+            usage,
+            TokenUsage {
+                prompt_tokens: Some(12),
+                completion_tokens: Some(3),
+            },
+            "{}",
+            case.name
+        );
+    }
+}
+
+/// the json body `provider` sends for `request`, without sending it
+fn body_of(provider: &dyn LlmProvider, request: &ChatRequest) -> serde_json::Value {
+    let built = provider
+        .request(&client(), request)
+        .unwrap()
+        .build()
+        .unwrap();
+    serde_json::from_slice(built.body().unwrap().as_bytes().unwrap()).unwrap()
+}
+
+#[test]
+fn usage_is_asked_for_only_where_the_api_takes_it() {
+    let openai = ProviderConfig::OpenAi {
+        host: None,
+        api_key: "sk-openai".to_string(),
+    };
+    let body = body_of(openai.build().as_ref(), &request());
+    assert_eq!(body["stream_options"]["include_usage"], true);
+
+    let compatible = ProviderConfig::OpenAi {
+        host: Some("http://localhost:8080".to_string()),
+        api_key: "sk-openai".to_string(),
+    };
+    let body = body_of(compatible.build().as_ref(), &request());
+    assert!(body.get("stream_options").is_none());
+
+    let azure = |api_version: Option<&str>| ProviderConfig::Azure {
+        endpoint: "https://example.openai.azure.com".to_string(),
+        deployment: "gpt-4o".to_string(),
+        api_version: api_version.map(String::from),
+        api_key: "azure-key".to_string(),
+    };
+    let body = body_of(azure(None).build().as_ref(), &request());
+    assert!(body.get("stream_options").is_none());
+    let body = body_of(azure(Some("2024-10-21")).build().as_ref(), &request());
+    assert_eq!(body["stream_options"]["include_usage"], true);
+}
+
+#[test]
+fn unset_options_are_left_out() {
+    let mut request = request();
+    let provider = ProviderConfig::OpenAi {
+        host: Some("http://localhost:8080".to_string()),
+        api_key: "sk-openai".to_string(),
+    }
+    .build();
+    let body = body_of(provider.as_ref(), &request);
+    assert_eq!(body["max_tokens"], 64);
+    assert_eq!(body["temperature"], 0.5);
+
+    request.max_tokens = None;
+    request.temperature = None;
+    let body = body_of(provider.as_ref(), &request);
+    assert!(body.get("max_tokens").is_none());
+    assert!(body.get("temperature").is_none());
+}
+
+#[tokio::test]
+async fn errors_inside_the_stream_are_mapped() {
+    for case in cases() {
+        let (reply, code) = case.stream_error.clone();
+        let Sent {
+            result, streamed, ..
+        } = send(&case, reply).await;
+        let error = result.unwrap_err();
+        assert!(
+            matches!(error, LlmError::Api { .. }),
+            "{}: {:?}",
+            case.name,
+            error
+        );
+        assert_eq!(error.code(), code, "{}", case.name);
+        assert_eq!(streamed, "Hello", "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn error_statuses_are_mapped() {
+    for case in cases() {
+        for (reply, code, message) in case.errors.clone() {
+            let status = reply.status;
+            let Sent { result, .. } = send(&case, reply).await;
+            assert_eq!(
+                result.unwrap_err(),
+                LlmError::Http {
+                    status,
+                    code,
+                    message: message.to_string(),
+                    retry_after_ms: None,
+                },
+                "{} {}",
+                case.name,
+                status
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn retry_after_is_kept_for_every_provider() {
+    for case in cases() {
+        let reply = json_error(429, r#"{"error":{"message":"rate limit reached"}}"#)
+            .header("retry-after", "3");
+        let Sent { result, .. } = send(&case, reply).await;
+        let error = result.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::RateLimit, "{}", case.name);
+        assert_eq!(
+            error.retry_after(),
+            Some(Duration::from_secs(3)),
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[tokio::test]
+async fn anthropic_hoists_system_messages() {
+    let case = cases().remove(2);
+    assert_eq!(case.name, "anthropic");
+    let Sent { server, .. } = send(&case, case.answer.clone()).await;
+    let body = server.received()[0].json();
+    assert_eq!(body["system"], "be brief\n\nanswer in english");
+    let roles: Vec<&str> = body["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|message| message["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, vec!["user", "assistant", "user"]);
+    assert_eq!(body["max_tokens"], 64);
+    assert_eq!(
+        server.received()[0].header("anthropic-version"),
+        Some("2023-06-01")
+    );
+}
+
+#[tokio::test]
+async fn ollama_puts_limits_in_its_options() {
+    let case = cases().remove(3);
+    assert_eq!(case.name, "ollama");
+    let Sent { server, .. } = send(&case, case.answer.clone()).await;
+    let body = server.received()[0].json();
+    assert_eq!(body["options"]["num_predict"], 64);
+    assert_eq!(body["options"]["temperature"], 0.5);
+    assert_eq!(body["messages"].as_array().unwrap().len(), 5);
+}
+
+#[tokio::test]
+async fn azure_needs_a_deployment() {
+    let provider = ProviderConfig::Azure {
+        endpoint: "http://127.0.0.1:9".to_string(),
+        deployment: String::new(),
+        api_version: None,
+        api_key: "azure-key".to_string(),
+    }
+    .build();
+    let result = stream_chat(
+        provider.as_ref(),
+        &client(),
+        &request(),
+        Timeouts::default(),
+        |_| {},
+        |_| {},
+    )
+    .await;
+    assert_eq!(result.unwrap_err().code(), ErrorCode::InvalidRequest);
+}
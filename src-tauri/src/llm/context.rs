@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+
+use super::tokenizer::{Encoding, REPLY_PRIMING_TOKENS};
+use super::ChatMessage;
+
+/// limits for the messages sent with a request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextOptions {
+    /// context window of the model
+    pub max_context_tokens: usize,
+    /// tokens kept free for the reply
+    #[serde(default)]
+    pub max_tokens: usize,
+    /// earlier messages kept besides the system prompt and the latest one
+    #[serde(default)]
+    pub message_count: Option<usize>,
+    /// only the latest message is sent when false
+    #[serde(default = "default_use_chat_context")]
+    pub use_chat_context: bool,
+}
+
+fn default_use_chat_context() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimReason {
+    /// chat context is turned off
+    ChatContext,
+    MessageCount,
+    TokenBudget,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrimmedMessage {
+    /// position in the messages passed in
+    pub index: usize,
+    pub role: String,
+    pub tokens: usize,
+    pub reason: TrimReason,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextReport {
+    pub encoding: Encoding,
+    /// what to send, in the original order
+    pub messages: Vec<ChatMessage>,
+    /// tokens of `messages` including the reply priming
+    pub prompt_tokens: usize,
+    /// tokens the messages may take
+    pub budget: usize,
+    /// the system prompt and the latest message are always sent, even when
+    /// they alone don't fit
+    pub over_budget: bool,
+    pub trimmed: Vec<TrimmedMessage>,
+}
+
+/// Pick the messages to send: the system prompt at the start, then the most
+/// recent messages that fit the message count and the token budget. Older
+/// messages are dropped first and nothing newer than a dropped message is
+/// skipped, so the conversation stays contiguous.
+pub fn build_context(
+    messages: &[ChatMessage],
+    options: &ContextOptions,
+    encoding: Encoding,
+) -> ContextReport {
+    let budget = options
+        .max_context_tokens
+        .saturating_sub(options.max_tokens)
+        .saturating_sub(REPLY_PRIMING_TOKENS);
+    let tokens: Vec<usize> = messages
+        .iter()
+        .map(|message| encoding.count_message_tokens(message))
+        .collect();
+    let has_system = messages
+        .first()
+        .map(|message| message.role == "system")
+        .unwrap_or(false);
+    let first = usize::from(has_system);
+
+    let mut used = if has_system { tokens[0] } else { 0 };
+    let mut kept = Vec::new();
+    let mut trimmed = Vec::new();
+    let mut reason = None;
+    for index in (first..messages.len()).rev() {
+        let latest = index + 1 == messages.len();
+        if reason.is_none() && !latest {
+            if !options.use_chat_context {
+                reason = Some(TrimReason::ChatContext);
+            } else if options
+                .message_count
+                .map(|count| kept.len() > count)
+                .unwrap_or(false)
+            {
+                reason = Some(TrimReason::MessageCount);
+            } else if used + tokens[index] > budget {
+                reason = Some(TrimReason::TokenBudget);
+            }
+        }
+        match reason {
+            Some(reason) => trimmed.push(TrimmedMessage {
+                index,
+                role: messages[index].role.clone(),
+                tokens: tokens[index],
+                reason,
+            }),
+            None => {
+                used += tokens[index];
+                kept.push(index);
+            }
+        }
+    }
+    if has_system {
+        kept.push(0);
+    }
+    kept.reverse();
+    trimmed.reverse();
+
+    ContextReport {
+        encoding,
+        messages: kept.iter().map(|index| messages[*index].clone()).collect(),
+        prompt_tokens: used + REPLY_PRIMING_TOKENS,
+        budget,
+        over_budget: used > budget,
+        trimmed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            message("system", "You are a helpful assistant."),
+            message("user", "ok"),
+            message("assistant", "What would you like to talk about today?"),
+            message("user", "Tell me about the weather in Paris in spring."),
+            message(
+                "assistant",
+                "Paris is mild in spring, often around 15 degrees.",
+            ),
+            message("user", "And in Berlin?"),
+        ]
+    }
+
+    fn options(max_context_tokens: usize) -> ContextOptions {
+        ContextOptions {
+            max_context_tokens,
+            max_tokens: 0,
+            message_count: None,
+            use_chat_context: true,
+        }
+    }
+
+    fn tokens(messages: &[ChatMessage]) -> usize {
+        messages
+            .iter()
+            .map(|message| Encoding::Cl100kBase.count_message_tokens(message))
+            .sum()
+    }
+
+    fn trimmed(report: &ContextReport) -> Vec<(usize, TrimReason)> {
+        report
+            .trimmed
+            .iter()
+            .map(|message| (message.index, message.reason))
+            .collect()
+    }
+
+    #[test]
+    fn everything_is_sent_when_it_fits() {
+        let messages = conversation();
+        let report = build_context(&messages, &options(4096), Encoding::Cl100kBase);
+        assert_eq!(report.messages, messages);
+        assert!(report.trimmed.is_empty());
+        assert!(!report.over_budget);
+        assert_eq!(
+            report.prompt_tokens,
+            Encoding::Cl100kBase.count_prompt_tokens(&messages)
+        );
+        assert_eq!(report.budget, 4096 - REPLY_PRIMING_TOKENS);
+    }
+
+    #[test]
+    fn message_count_keeps_the_system_prompt_and_the_latest_messages() {
+        let messages = conversation();
+        let options = ContextOptions {
+            message_count: Some(2),
+            ..options(4096)
+        };
+        let report = build_context(&messages, &options, Encoding::Cl100kBase);
+        assert_eq!(
+            report.messages,
+            vec![
+                messages[0].clone(),
+                messages[3].clone(),
+                messages[4].clone(),
+                messages[5].clone(),
+            ]
+        );
+        assert_eq!(
+            trimmed(&report),
+            vec![(1, TrimReason::MessageCount), (2, TrimReason::MessageCount)]
+        );
+        assert_eq!(report.trimmed[1].role, "assistant");
+    }
+
+    #[test]
+    fn the_oldest_messages_are_dropped_to_fit_the_budget() {
+        let messages = conversation();
+        let kept = vec![
+            messages[0].clone(),
+            messages[4].clone(),
+            messages[5].clone(),
+        ];
+        // room for the kept ones, the reply and the short "ok", not for the 4th
+        let options = ContextOptions {
+            max_tokens: 100,
+            ..options(tokens(&kept) + tokens(&messages[1..2]) + 100 + REPLY_PRIMING_TOKENS)
+        };
+        let report = build_context(&messages, &options, Encoding::Cl100kBase);
+        assert_eq!(report.messages, kept);
+        // the short "ok" would fit but is older than a dropped message
+        assert_eq!(
+            trimmed(&report),
+            vec![
+                (1, TrimReason::TokenBudget),
+                (2, TrimReason::TokenBudget),
+                (3, TrimReason::TokenBudget),
+            ]
+        );
+        assert_eq!(report.prompt_tokens, tokens(&kept) + REPLY_PRIMING_TOKENS);
+        assert!(!report.over_budget);
+    }
+
+    #[test]
+    fn the_system_prompt_and_latest_message_are_sent_over_budget() {
+        let messages = conversation();
+        let report = build_context(&messages, &options(10), Encoding::Cl100kBase);
+        assert_eq!(
+            report.messages,
+            vec![messages[0].clone(), messages[5].clone()]
+        );
+        assert!(report.over_budget);
+        assert_eq!(report.budget, 10 - REPLY_PRIMING_TOKENS);
+        assert_eq!(report.trimmed.len(), 4);
+        assert!(report
+            .trimmed
+            .iter()
+            .all(|message| message.reason == TrimReason::TokenBudget));
+    }
+
+    #[test]
+    fn without_chat_context_only_the_latest_message_is_sent() {
+        let messages = conversation();
+        let options = ContextOptions {
+            use_chat_context: false,
+            ..options(4096)
+        };
+        let report = build_context(&messages, &options, Encoding::Cl100kBase);
+        assert_eq!(
+            report.messages,
+            vec![messages[0].clone(), messages[5].clone()]
+        );
+        assert_eq!(
+            trimmed(&report),
+            (1..5)
+                .map(|index| (index, TrimReason::ChatContext))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn a_first_user_message_is_not_kept_like_a_system_prompt() {
+        let messages = conversation()[1..].to_vec();
+        let options = ContextOptions {
+            message_count: Some(1),
+            ..options(4096)
+        };
+        let report = build_context(&messages, &options, Encoding::Cl100kBase);
+        assert_eq!(report.messages, messages[3..].to_vec());
+        assert_eq!(
+            trimmed(&report),
+            vec![
+                (0, TrimReason::MessageCount),
+                (1, TrimReason::MessageCount),
+                (2, TrimReason::MessageCount),
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// What went wrong with a model call, for the UI to act on: ask for a new
+/// key, shorten the conversation, wait, and so on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// the key is missing, wrong or lacks access to the model
+    Auth,
+    /// the account is out of credit
+    Quota,
+    /// too many requests, try again later
+    RateLimit,
+    /// the prompt doesn't fit the model's context window
+    ContextTooLong,
+    /// the server couldn't be reached
+    Network,
+    Timeout,
+    /// the server failed or is overloaded
+    Server,
+    Cancelled,
+    /// the server refused the request for another reason
+    InvalidRequest,
+    /// the answer can't be understood
+    InvalidResponse,
+    /// a budget of the app config is used up
+    BudgetExceeded,
+}
+
+impl ErrorCode {
+    /// whether the same request may succeed when sent again
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::RateLimit | ErrorCode::Network | ErrorCode::Timeout | ErrorCode::Server
+        )
+    }
+}
+
+const CONTEXT_MARKERS: &[&str] = &[
+    "context_length_exceeded",
+    "maximum context length",
+    "context window",
+    "prompt is too long",
+    "too many tokens",
+    "reduce the length",
+];
+const QUOTA_MARKERS: &[&str] = &["insufficient_quota", "quota", "billing", "credit balance"];
+const RATE_LIMIT_MARKERS: &[&str] = &["rate limit", "rate_limit"];
+/// overloads and failures the provider names, e.g. anthropic's
+/// `overloaded_error` after the stream started
+const SERVER_MARKERS: &[&str] = &[
+    "overloaded",
+    "server_error",
+    "internal_error",
+    "internal server error",
+    "service unavailable",
+];
+const AUTH_MARKERS: &[&str] = &[
+    "invalid_api_key",
+    "invalid api key",
+    "authentication",
+    "unauthorized",
+    "permission",
+];
+
+/// The code of an error response with `status`, or of an error object inside
+/// a stream when `status` is missing. `text` is the body or the error object;
+/// OpenAI, Azure and Anthropic all name the problem in it. An error object
+/// that names nothing known isn't retried, sending it again may repeat it.
+pub fn classify(status: Option<u16>, text: &str) -> ErrorCode {
+    let text = text.to_lowercase();
+    let mentions = |markers: &[&str]| markers.iter().any(|marker| text.contains(marker));
+    match status {
+        Some(401) | Some(403) => ErrorCode::Auth,
+        Some(402) => ErrorCode::Quota,
+        Some(429) if mentions(QUOTA_MARKERS) => ErrorCode::Quota,
+        Some(429) => ErrorCode::RateLimit,
+        Some(408) => ErrorCode::Timeout,
+        Some(413) => ErrorCode::ContextTooLong,
+        Some(status) if status >= 500 => ErrorCode::Server,
+        Some(_) if mentions(CONTEXT_MARKERS) => ErrorCode::ContextTooLong,
+        Some(_) if mentions(QUOTA_MARKERS) => ErrorCode::Quota,
+        Some(_) => ErrorCode::InvalidRequest,
+        None if mentions(CONTEXT_MARKERS) => ErrorCode::ContextTooLong,
+        None if mentions(QUOTA_MARKERS) => ErrorCode::Quota,
+        None if mentions(RATE_LIMIT_MARKERS) => ErrorCode::RateLimit,
+        None if mentions(AUTH_MARKERS) => ErrorCode::Auth,
+        None if mentions(SERVER_MARKERS) => ErrorCode::Server,
+        None => ErrorCode::InvalidRequest,
+    }
+}
+
+/// How long the server asks to wait: `retry-after-ms` of OpenAI and Azure,
+/// else `retry-after` in seconds or as an http date.
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if let Some(millis) =
+        header("retry-after-ms").and_then(|value| value.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_millis(millis.max(0.0) as u64));
+    }
+    let value = header("retry-after")?.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some(Duration::from_millis((secs.max(0.0) * 1000.0) as u64));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.timestamp_millis() - chrono::Utc::now().timestamp_millis();
+    Some(Duration::from_millis(wait.max(0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_pick_the_code() {
+        assert_eq!(classify(Some(401), ""), ErrorCode::Auth);
+        assert_eq!(
+            classify(Some(429), "rate limit reached"),
+            ErrorCode::RateLimit
+        );
+        assert_eq!(
+            classify(Some(429), r#"{"code":"insufficient_quota"}"#),
+            ErrorCode::Quota
+        );
+        assert_eq!(classify(Some(503), ""), ErrorCode::Server);
+        assert_eq!(
+            classify(Some(400), "maximum context length is 4097 tokens"),
+            ErrorCode::ContextTooLong
+        );
+        assert_eq!(classify(Some(404), "not found"), ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn unknown_stream_errors_are_not_retried() {
+        let overloaded = r#"{"type":"overloaded_error","message":"Overloaded"}"#;
+        assert_eq!(classify(None, overloaded), ErrorCode::Server);
+        assert_eq!(
+            classify(None, r#"{"type":"server_error"}"#),
+            ErrorCode::Server
+        );
+        let unknown = classify(None, "an unknown error was encountered");
+        assert_eq!(unknown, ErrorCode::InvalidRequest);
+        assert!(!unknown.is_retryable());
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Client side limits of a profile, so a burst of selection actions doesn't
+/// run into the provider's own limits. Missing or 0 means unlimited.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimits {
+    /// requests streaming at the same time
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// requests started in any 60 seconds, retries included
+    #[serde(default)]
+    pub requests_per_minute: Option<usize>,
+}
+
+struct Limiter {
+    limits: RateLimits,
+    slots: Option<Arc<Semaphore>>,
+    /// start of every request in the last minute, oldest first
+    started: Mutex<VecDeque<Instant>>,
+}
+
+impl Limiter {
+    fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            slots: limits
+                .max_concurrent
+                .filter(|max| *max > 0)
+                .map(|max| Arc::new(Semaphore::new(max))),
+            started: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// counts a request as started, or says how long until one may start
+    fn try_start(&self, now: Instant) -> Result<(), Duration> {
+        let per_minute = match self.limits.requests_per_minute.filter(|max| *max > 0) {
+            Some(per_minute) => per_minute,
+            None => return Ok(()),
+        };
+        let mut started = self.started.lock();
+        while started
+            .front()
+            .map(|start| now.duration_since(*start) >= RATE_WINDOW)
+            .unwrap_or(false)
+        {
+            started.pop_front();
+        }
+        if started.len() < per_minute {
+            started.push_back(now);
+            return Ok(());
+        }
+        Err(started[0] + RATE_WINDOW - now)
+    }
+}
+
+/// held while a request streams, frees its slot when dropped
+pub struct Permit {
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+/// One limiter per profile or host, made when first used and remade when
+/// its limits change in the config.
+#[derive(Default)]
+pub struct RateLimiters {
+    limiters: Mutex<HashMap<String, Arc<Limiter>>>,
+}
+
+impl RateLimiters {
+    fn limiter(&self, source: &str, limits: RateLimits) -> Arc<Limiter> {
+        let mut limiters = self.limiters.lock();
+        match limiters.get(source) {
+            Some(limiter) if limiter.limits == limits => limiter.clone(),
+            _ => {
+                let limiter = Arc::new(Limiter::new(limits));
+                limiters.insert(source.to_string(), limiter.clone());
+                limiter
+            }
+        }
+    }
+
+    /// wait for a free slot and for room in the per minute budget of `source`
+    pub async fn acquire(&self, source: &str, limits: RateLimits) -> Permit {
+        let limiter = self.limiter(source, limits);
+        let slot = match &limiter.slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        while let Err(wait) = limiter.try_start(Instant::now()) {
+            tracing::info!(rate_limited = source, wait =? wait);
+            tokio::time::sleep(wait).await;
+        }
+        Permit { _slot: slot }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn per_minute(requests: usize) -> RateLimits {
+        RateLimits {
+            max_concurrent: None,
+            requests_per_minute: Some(requests),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_per_minute_wait_for_the_window() {
+        let limiters = RateLimiters::default();
+        let start = Instant::now();
+        for _ in 0..2 {
+            limiters.acquire("profile:default", per_minute(2)).await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        tokio::time::advance(Duration::from_secs(20)).await;
+        // the third waits until the first is a minute old
+        limiters.acquire("profile:default", per_minute(2)).await;
+        assert_eq!(start.elapsed(), RATE_WINDOW);
+        // other sources have their own window
+        limiters.acquire("profile:local", per_minute(2)).await;
+        assert_eq!(start.elapsed(), RATE_WINDOW);
+        // the second started at 0 as well
+        limiters.acquire("profile:default", per_minute(2)).await;
+        assert_eq!(start.elapsed(), RATE_WINDOW);
+        limiters.acquire("profile:default", per_minute(2)).await;
+        assert_eq!(start.elapsed(), RATE_WINDOW * 2);
+    }
+
+    #[test]
+    fn the_window_slides() {
+        let limiter = Limiter::new(per_minute(2));
+        let start = Instant::now();
+        assert_eq!(limiter.try_start(start), Ok(()));
+        assert_eq!(limiter.try_start(start + Duration::from_secs(30)), Ok(()));
+        assert_eq!(
+            limiter.try_start(start + Duration::from_secs(45)),
+            Err(Duration::from_secs(15))
+        );
+        assert_eq!(limiter.try_start(start + RATE_WINDOW), Ok(()));
+        assert_eq!(
+            limiter.try_start(start + RATE_WINDOW),
+            Err(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn zero_or_missing_is_unlimited() {
+        let limiter = Limiter::new(per_minute(0));
+        let start = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(limiter.try_start(start), Ok(()));
+        }
+        assert!(Limiter::new(RateLimits::default()).slots.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_requests_wait_for_a_free_slot() {
+        let limiters = RateLimiters::default();
+        let limits = RateLimits {
+            max_concurrent: Some(1),
+            requests_per_minute: None,
+        };
+        let first = limiters.acquire("profile:default", limits).await;
+        let waiting = limiters.acquire("profile:default", limits);
+        tokio::pin!(waiting);
+        assert!(tokio::time::timeout(Duration::from_secs(5), &mut waiting)
+            .await
+            .is_err());
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("a slot is free");
+    }
+}
@@ -0,0 +1,703 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+
+pub mod anthropic;
+pub mod azure;
+pub mod cache;
+#[cfg(test)]
+mod conformance;
+pub mod context;
+pub mod error;
+pub mod limit;
+pub mod ollama;
+pub mod openai;
+pub mod profile;
+pub mod provider;
+pub mod retry;
+pub mod sse;
+#[cfg(test)]
+pub mod stub;
+pub mod tokenizer;
+pub mod usage;
+
+/// sent to all windows for every piece of an answer
+pub const LLM_TOKEN_EVENT: &str = "llm-token";
+
+/// time for the server to start answering
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+/// longest pause between two pieces of a streamed answer
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatRequest {
+    /// the model of the profile when empty
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// until the response headers arrive
+    pub response: Duration,
+    /// between two chunks of the body
+    pub idle: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            response: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            idle: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+        }
+    }
+}
+
+pub use error::ErrorCode;
+
+/// Why a model call failed, sent to the frontend as `{kind, code, ...}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LlmError {
+    /// the server took too long to answer or stopped sending
+    Timeout {
+        message: String,
+    },
+    Cancelled,
+    /// the server answered with an error status
+    Http {
+        status: u16,
+        code: ErrorCode,
+        message: String,
+        /// how long the server asked to wait before trying again
+        retry_after_ms: Option<u64>,
+    },
+    /// an error object inside an otherwise successful stream
+    Api {
+        code: ErrorCode,
+        message: String,
+    },
+    /// the request didn't reach the server
+    Network {
+        message: String,
+    },
+    /// the body isn't what the protocol promises
+    InvalidResponse {
+        message: String,
+    },
+    InvalidRequest {
+        message: String,
+    },
+    /// a budget of the app config is used up
+    Budget {
+        message: String,
+    },
+}
+
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LlmError::Timeout { message } => write!(f, "timeout: {}", message),
+            LlmError::Cancelled => write!(f, "cancelled"),
+            LlmError::Http {
+                status, message, ..
+            } => write!(f, "http {}: {}", status, message),
+            LlmError::Api { message, .. } => write!(f, "api error: {}", message),
+            LlmError::Network { message } => write!(f, "network error: {}", message),
+            LlmError::InvalidResponse { message } => write!(f, "invalid response: {}", message),
+            LlmError::InvalidRequest { message } => write!(f, "invalid request: {}", message),
+            LlmError::Budget { message } => write!(f, "budget exceeded: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+impl LlmError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            LlmError::Timeout { .. } => ErrorCode::Timeout,
+            LlmError::Cancelled => ErrorCode::Cancelled,
+            LlmError::Http { code, .. } | LlmError::Api { code, .. } => *code,
+            LlmError::Network { .. } => ErrorCode::Network,
+            LlmError::InvalidResponse { .. } => ErrorCode::InvalidResponse,
+            LlmError::InvalidRequest { .. } => ErrorCode::InvalidRequest,
+            LlmError::Budget { .. } => ErrorCode::BudgetExceeded,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            LlmError::Http { retry_after_ms, .. } => retry_after_ms.map(Duration::from_millis),
+            _ => None,
+        }
+    }
+
+    pub fn with_retry_after(self, wait: Option<Duration>) -> Self {
+        match self {
+            LlmError::Http {
+                status,
+                code,
+                message,
+                retry_after_ms,
+            } => LlmError::Http {
+                status,
+                code,
+                message,
+                retry_after_ms: wait.map(|wait| wait.as_millis() as u64).or(retry_after_ms),
+            },
+            error => error,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            LlmError::Timeout { .. } => "timeout",
+            LlmError::Cancelled => "cancelled",
+            LlmError::Http { .. } => "http",
+            LlmError::Api { .. } => "api",
+            LlmError::Network { .. } => "network",
+            LlmError::InvalidResponse { .. } => "invalid_response",
+            LlmError::InvalidRequest { .. } => "invalid_request",
+            LlmError::Budget { .. } => "budget",
+        }
+    }
+}
+
+impl Serialize for LlmError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Payload<'a> {
+            kind: &'static str,
+            code: ErrorCode,
+            retryable: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            status: Option<u16>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            message: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            retry_after_ms: Option<u64>,
+        }
+        let (status, message, retry_after_ms) = match self {
+            LlmError::Http {
+                status,
+                message,
+                retry_after_ms,
+                ..
+            } => (Some(*status), Some(message), *retry_after_ms),
+            LlmError::Timeout { message }
+            | LlmError::Api { message, .. }
+            | LlmError::Network { message }
+            | LlmError::InvalidResponse { message }
+            | LlmError::InvalidRequest { message }
+            | LlmError::Budget { message } => (None, Some(message), None),
+            LlmError::Cancelled => (None, None, None),
+        };
+        Payload {
+            kind: self.kind(),
+            code: self.code(),
+            retryable: self.code().is_retryable(),
+            status,
+            message: message.map(String::as_str),
+            retry_after_ms,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            LlmError::Timeout {
+                message: err.to_string(),
+            }
+        } else if let Some(status) = err.status() {
+            LlmError::Http {
+                status: status.as_u16(),
+                code: error::classify(Some(status.as_u16()), ""),
+                message: err.to_string(),
+                retry_after_ms: None,
+            }
+        } else if err.is_builder() {
+            LlmError::InvalidRequest {
+                message: err.to_string(),
+            }
+        } else {
+            LlmError::Network {
+                message: err.to_string(),
+            }
+        }
+    }
+}
+
+/// payload of `LLM_TOKEN_EVENT`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatToken {
+    pub request_id: String,
+    /// the new piece of the answer
+    pub delta: String,
+    /// the whole answer so far
+    pub content: String,
+    /// replayed from the response cache
+    pub cached: bool,
+}
+
+/// payload of the `chat_completion` command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatCompletionPayload {
+    /// chosen by the caller, used to cancel and to match token events
+    pub request_id: String,
+    /// profile of the app config, the default one when missing
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// an OpenAI compatible api for when the config has no profiles, can't
+    /// be given together with `profile`
+    #[serde(default)]
+    pub host: Option<String>,
+    /// key for `host`, the key of the app config when missing
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(flatten)]
+    pub request: ChatRequest,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// trim the messages to this context window, see `context::build_context`
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+    /// the selection action asking; only their answers are cached
+    #[serde(default)]
+    pub action_id: Option<String>,
+    /// ask the model even when the answer is cached, e.g. to regenerate
+    #[serde(default)]
+    pub bypass_cache: bool,
+}
+
+/// payload of the `count_tokens` command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensPayload {
+    pub text: String,
+    /// picks the encoding when `encoding` is missing
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub encoding: Option<tokenizer::Encoding>,
+}
+
+impl CountTokensPayload {
+    pub fn count(&self) -> usize {
+        let encoding = self.encoding.unwrap_or_else(|| {
+            tokenizer::Encoding::for_model(self.model.as_deref().unwrap_or_default())
+        });
+        encoding.count_tokens(&self.text)
+    }
+}
+
+/// payload of the `build_chat_context` command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildContextPayload {
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub model: String,
+    pub max_context_tokens: usize,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    /// from the app config when missing
+    #[serde(default)]
+    pub message_count: Option<usize>,
+    /// from the app config when missing
+    #[serde(default)]
+    pub use_chat_context: Option<bool>,
+}
+
+impl BuildContextPayload {
+    pub fn build(&self) -> context::ContextReport {
+        let mut options = context_options(self.max_context_tokens, self.max_tokens);
+        if self.message_count.is_some() {
+            options.message_count = self.message_count;
+        }
+        if let Some(use_chat_context) = self.use_chat_context {
+            options.use_chat_context = use_chat_context;
+        }
+        context::build_context(
+            &self.messages,
+            &options,
+            tokenizer::Encoding::for_model(&self.model),
+        )
+    }
+}
+
+/// context limits with the message count and chat context of the app config
+fn context_options(
+    max_context_tokens: usize,
+    max_tokens: Option<usize>,
+) -> context::ContextOptions {
+    let app_config = crate::app_config::get_app_config().unwrap_or_default();
+    context::ContextOptions {
+        max_context_tokens,
+        max_tokens: max_tokens.unwrap_or_default(),
+        message_count: app_config
+            .message_context_count
+            .map(|count| count.max(0) as usize),
+        use_chat_context: app_config.use_chat_context,
+    }
+}
+
+/// Model calls in flight, so they can be cancelled by id.
+#[derive(Default)]
+pub struct LlmRequests {
+    requests: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl LlmRequests {
+    /// fails when a request with this id is already running
+    pub fn register(&self, request_id: &str) -> Result<RunningRequest<'_>, LlmError> {
+        let mut requests = self.requests.lock();
+        if requests.contains_key(request_id) {
+            return Err(LlmError::InvalidRequest {
+                message: format!("request {} is already running", request_id),
+            });
+        }
+        let cancel = Arc::new(Notify::new());
+        requests.insert(request_id.to_string(), cancel.clone());
+        Ok(RunningRequest {
+            requests: self,
+            request_id: request_id.to_string(),
+            cancel,
+        })
+    }
+
+    /// whether a request with this id was running
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.requests.lock().remove(request_id) {
+            Some(cancel) => {
+                // stores a permit if the request isn't waiting right now
+                cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A registered request, see `LlmRequests::register`. Its id is free again
+/// once it is dropped, also when the command exits early.
+pub struct RunningRequest<'a> {
+    requests: &'a LlmRequests,
+    request_id: String,
+    cancel: Arc<Notify>,
+}
+
+impl RunningRequest<'_> {
+    /// `future` until it ends or the request is cancelled by its id
+    pub async fn run<T>(
+        self,
+        future: impl std::future::Future<Output = Result<T, LlmError>>,
+    ) -> Result<T, LlmError> {
+        tokio::select! {
+            result = future => result,
+            _ = self.cancel.notified() => Err(LlmError::Cancelled),
+        }
+    }
+}
+
+impl Drop for RunningRequest<'_> {
+    fn drop(&mut self) {
+        let mut requests = self.requests.requests.lock();
+        // a cancelled id may already belong to a newer request
+        if requests
+            .get(&self.request_id)
+            .map(|cancel| Arc::ptr_eq(cancel, &self.cancel))
+            .unwrap_or(false)
+        {
+            requests.remove(&self.request_id);
+        }
+    }
+}
+
+/// sent to all windows when a budget reaches its warning or its cap
+pub const LLM_BUDGET_EVENT: &str = "llm-budget";
+
+/// keep `record` and warn about the budgets it used up
+fn record_usage(
+    handle: &AppHandle,
+    record: usage::UsageRecord,
+    budget: Option<&usage::BudgetConfig>,
+) {
+    let state: tauri::State<crate::AppState> = handle.state();
+    state.llm_usage.record(record);
+    let warnings = budget
+        .map(|budget| state.llm_usage.new_warnings(budget))
+        .unwrap_or_default();
+    for status in warnings {
+        tracing::warn!(llm_budget =? status);
+        if let Err(err) = handle.emit_all(LLM_BUDGET_EVENT, status.clone()) {
+            tracing::warn!(emit_llm_budget_error = ?err);
+        }
+        let period = match status.period {
+            usage::BudgetPeriod::Day => "今日",
+            usage::BudgetPeriod::Month => "本月",
+        };
+        let title = match status.level {
+            usage::BudgetLevel::Exceeded => "预算已用完",
+            _ => "预算即将用完",
+        };
+        let identifier = handle.config().tauri.bundle.identifier.clone();
+        let result = tauri::api::notification::Notification::new(&identifier)
+            .title(title)
+            .body(format!(
+                "{}已花费 ${:.2}, 预算 ${:.2}",
+                period, status.spent, status.limit
+            ))
+            .show();
+        if let Err(err) = result {
+            tracing::warn!(show_notification_error = ?err);
+        }
+    }
+}
+
+/// Stream an answer, emitting `LLM_TOKEN_EVENT` for every piece, and return
+/// the whole of it.
+pub async fn chat_completion(
+    handle: &AppHandle,
+    payload: ChatCompletionPayload,
+) -> Result<String, LlmError> {
+    let registry = profile::ProviderRegistry::load();
+    let mut request = payload.request;
+    // `source` keys the response cache and the rate limiter
+    let (backend, source, limits) = match (payload.host, payload.profile) {
+        (Some(host), Some(name)) => {
+            return Err(LlmError::InvalidRequest {
+                message: format!("got both host {} and llm profile {}", host, name),
+            });
+        }
+        (Some(host), None) => {
+            let source = format!("host:{}", host);
+            let api_key = payload.api_key.unwrap_or_else(|| {
+                crate::app_config::get_app_config()
+                    .ok()
+                    .and_then(|app_config| app_config.api_key)
+                    .unwrap_or_default()
+            });
+            let backend = profile::ProviderConfig::OpenAi {
+                host: Some(host),
+                api_key,
+            }
+            .build();
+            (backend, source, limit::RateLimits::default())
+        }
+        (None, name) => {
+            let profile = registry.profile(name.as_deref())?;
+            if request.model.is_empty() {
+                request.model = profile.model.clone();
+            }
+            (
+                profile.provider.build(),
+                format!("profile:{}", profile.name),
+                profile.limits,
+            )
+        }
+    };
+    let timeouts = Timeouts {
+        response: payload
+            .timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(Timeouts::default().response),
+        idle: payload
+            .idle_timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(Timeouts::default().idle),
+    };
+    if let Some(max_context_tokens) = payload.max_context_tokens {
+        let options = context_options(
+            max_context_tokens,
+            request.max_tokens.map(|max_tokens| max_tokens as usize),
+        );
+        let report = context::build_context(
+            &request.messages,
+            &options,
+            tokenizer::Encoding::for_model(&request.model),
+        );
+        tracing::info!(
+            prompt_tokens = report.prompt_tokens,
+            over_budget = report.over_budget,
+            trimmed =? report.trimmed
+        );
+        request.messages = report.messages;
+    }
+
+    let state: tauri::State<crate::AppState> = handle.state();
+    let request_id = payload.request_id;
+    let cache_config = cache::CacheConfig::load();
+    let use_cache = cache_config.enabled
+        && !payload.bypass_cache
+        && payload
+            .action_id
+            .as_deref()
+            .and_then(|id| crate::actions::find_action(handle, id))
+            .map(|action| !action.bypass_cache)
+            .unwrap_or(false);
+    let cache_key = use_cache.then(|| cache::cache_key(&source, backend.name(), &request));
+    if let Some(key) = &cache_key {
+        if let Some(answer) = state.response_cache.get(&cache_config, key) {
+            tracing::info!(chat_completion = request_id, cache_hit = key);
+            let token = ChatToken {
+                request_id,
+                delta: answer.clone(),
+                content: answer.clone(),
+                cached: true,
+            };
+            if let Err(err) = handle.emit_all(LLM_TOKEN_EVENT, token) {
+                tracing::warn!(emit_llm_token_error = ?err);
+            }
+            return Ok(answer);
+        }
+    }
+
+    let budget = usage::BudgetConfig::load();
+    if let Some(exceeded) = budget
+        .as_ref()
+        .and_then(|budget| state.llm_usage.exceeded(budget))
+    {
+        return Err(LlmError::Budget {
+            message: format!(
+                "spent ${:.2} of ${:.2} in {}",
+                exceeded.spent, exceeded.limit, exceeded.key
+            ),
+        });
+    }
+
+    let client = crate::http_client::client().map_err(|err| LlmError::InvalidRequest {
+        message: format!("{:#}", err),
+    })?;
+    let running = state.llm_requests.register(&request_id)?;
+    let started = std::time::Instant::now();
+    let mut content = String::new();
+    let mut reported = provider::TokenUsage::default();
+    let attempts = retry::Attempts {
+        limiters: &state.llm_limiters,
+        source: &source,
+        limits,
+        retry: retry::RetryConfig::load(),
+        timeouts,
+    };
+    let on_token = |delta: &str| {
+        content.push_str(delta);
+        let token = ChatToken {
+            request_id: request_id.clone(),
+            delta: delta.to_string(),
+            content: content.clone(),
+            cached: false,
+        };
+        if let Err(err) = handle.emit_all(LLM_TOKEN_EVENT, token) {
+            tracing::warn!(emit_llm_token_error = ?err);
+        }
+    };
+    let on_usage = |usage: provider::TokenUsage| reported.merge(usage);
+    let on_retry = |attempt: u32, delay: Duration, error: &LlmError| {
+        tracing::warn!(
+            retry_chat_completion = request_id,
+            attempt,
+            delay =? delay,
+            error =? error
+        );
+        let notice = retry::RetryNotice {
+            request_id: request_id.clone(),
+            attempt,
+            delay_ms: delay.as_millis() as u64,
+            error: error.clone(),
+        };
+        if let Err(err) = handle.emit_all(retry::LLM_RETRY_EVENT, notice) {
+            tracing::warn!(emit_llm_retry_error = ?err);
+        }
+    };
+    let streaming = retry::stream_with_retry(
+        backend.as_ref(),
+        &client,
+        &request,
+        attempts,
+        on_token,
+        on_usage,
+        on_retry,
+    );
+    let result = running.run(streaming).await;
+    let billed = usage::billed_tokens(
+        reported,
+        result.is_ok(),
+        &request.messages,
+        &content,
+        tokenizer::Encoding::for_model(&request.model),
+    );
+    let timestamp = chrono::Local::now().timestamp();
+    let record = usage::UsageRecord {
+        timestamp,
+        day: usage::local_day(timestamp),
+        source: source.clone(),
+        provider: backend.name().to_string(),
+        model: request.model.clone(),
+        action_id: payload.action_id.clone(),
+        prompt_tokens: billed.prompt_tokens,
+        completion_tokens: billed.completion_tokens,
+        latency_ms: started.elapsed().as_millis() as u64,
+        cost: usage::cost(
+            &usage::pricing(),
+            &request.model,
+            billed.prompt_tokens,
+            billed.completion_tokens,
+        ),
+        error: result.as_ref().err().map(LlmError::code),
+        partial: billed.partial,
+        estimated: billed.estimated,
+    };
+    record_usage(handle, record, budget.as_ref());
+    if let (Some(key), Ok(answer)) = (cache_key, &result) {
+        if !answer.trim().is_empty() {
+            let now = cache::now_secs();
+            let entry = cache::CacheEntry {
+                key,
+                source,
+                provider: backend.name().to_string(),
+                model: request.model.clone(),
+                action_id: payload.action_id.clone(),
+                prompt: request
+                    .messages
+                    .last()
+                    .map(|message| message.content.clone())
+                    .unwrap_or_default(),
+                response: answer.clone(),
+                created_at: now,
+                last_hit_at: now,
+                hits: 0,
+            };
+            state.response_cache.insert(&cache_config, entry);
+        }
+    }
+    tracing::info!(
+        chat_completion = request_id,
+        provider = backend.name(),
+        result =? result.as_ref().map(|answer| answer.len())
+    );
+    result
+}
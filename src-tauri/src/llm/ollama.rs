@@ -0,0 +1,114 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use super::provider::{
+    parse_json, stream_error, LlmProvider, StreamEvent, StreamFormat, TokenUsage,
+};
+use super::sse::SseEvent;
+use super::{ChatRequest, LlmError};
+
+pub const DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// A local Ollama server. It needs no key and streams one JSON object per
+/// line instead of server sent events.
+pub struct OllamaProvider {
+    host: String,
+    /// for servers behind a proxy that checks a bearer token
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamLine {
+    #[serde(default)]
+    message: Option<LineMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+/// counts of the last line
+#[derive(Deserialize, Debug)]
+struct UsageLine {
+    #[serde(default)]
+    prompt_eval_count: Option<usize>,
+    #[serde(default)]
+    eval_count: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LineMessage {
+    #[serde(default)]
+    content: String,
+}
+
+impl OllamaProvider {
+    pub fn new(host: &str, api_key: Option<&str>) -> Self {
+        Self {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key
+                .filter(|api_key| !api_key.is_empty())
+                .map(String::from),
+        }
+    }
+}
+
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        request: &ChatRequest,
+    ) -> Result<reqwest::RequestBuilder, LlmError> {
+        let mut options = json!({});
+        if let Some(temperature) = request.temperature {
+            options["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            options["num_predict"] = json!(max_tokens);
+        }
+        let mut builder = client.post(format!("{}/api/chat", self.host)).json(&json!({
+            "model": request.model,
+            "messages": request.messages,
+            "stream": true,
+            "options": options,
+        }));
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        Ok(builder)
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::JsonLines
+    }
+
+    fn parse_event(&self, event: &SseEvent) -> Result<StreamEvent, LlmError> {
+        let line: StreamLine = parse_json(&event.data)?;
+        if let Some(error) = line.error {
+            return Err(stream_error(&error));
+        }
+        if line.done {
+            return Ok(StreamEvent::Done);
+        }
+        Ok(StreamEvent::Delta(
+            line.message
+                .map(|message| message.content)
+                .unwrap_or_default(),
+        ))
+    }
+
+    fn parse_usage(&self, event: &SseEvent) -> Option<TokenUsage> {
+        if !event.data.contains("eval_count\"") {
+            return None;
+        }
+        let line: UsageLine = serde_json::from_str(&event.data).ok()?;
+        Some(TokenUsage {
+            prompt_tokens: line.prompt_eval_count,
+            completion_tokens: line.eval_count,
+        })
+    }
+}
@@ -0,0 +1,144 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use super::provider::{parse_json, stream_error, LlmProvider, StreamEvent, TokenUsage};
+use super::sse::SseEvent;
+use super::{ChatRequest, LlmError};
+
+/// host of the OpenAI api, the only one known to take `stream_options`
+const OPENAI_API_HOST: &str = "api.openai.com";
+
+/// OpenAI and compatible `/v1/chat/completions` apis.
+pub struct OpenAiProvider {
+    host: String,
+    api_key: String,
+    /// compatible servers may answer unknown fields with a 400
+    include_usage: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+/// `usage` of the last chunk, sent when the request asks for it
+#[derive(Deserialize, Debug)]
+struct UsageChunk {
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Usage {
+    #[serde(default)]
+    prompt_tokens: Option<usize>,
+    #[serde(default)]
+    completion_tokens: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: Option<StreamDelta>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+/// The body of a streaming chat completion, shared with Azure. With
+/// `include_usage` the last chunk has the token counts of the call. Unset
+/// options are left out, some compatible servers reject nulls.
+pub fn request_body(request: &ChatRequest, include_usage: bool) -> serde_json::Value {
+    let mut body = json!({
+        "model": request.model,
+        "messages": request.messages,
+        "stream": true,
+    });
+    if let Some(max_tokens) = request.max_tokens {
+        body["max_tokens"] = json!(max_tokens);
+    }
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if include_usage {
+        body["stream_options"] = json!({ "include_usage": true });
+    }
+    body
+}
+
+/// an event of a chat completion stream, shared with Azure
+pub fn parse_event(event: &SseEvent) -> Result<StreamEvent, LlmError> {
+    if event.data.trim() == "[DONE]" {
+        return Ok(StreamEvent::Done);
+    }
+    let chunk: StreamChunk = parse_json(&event.data)?;
+    if let Some(error) = chunk.error {
+        return Err(stream_error(&error));
+    }
+    Ok(StreamEvent::Delta(
+        chunk
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.delta.and_then(|delta| delta.content))
+            .collect(),
+    ))
+}
+
+/// the token counts of a chat completion stream, shared with Azure
+pub fn parse_usage(event: &SseEvent) -> Option<TokenUsage> {
+    if !event.data.contains("\"usage\"") {
+        return None;
+    }
+    let usage = serde_json::from_str::<UsageChunk>(&event.data)
+        .ok()?
+        .usage?;
+    Some(TokenUsage {
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+    })
+}
+
+impl OpenAiProvider {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        let include_usage = reqwest::Url::parse(host)
+            .ok()
+            .and_then(|url| url.host_str().map(|name| name == OPENAI_API_HOST))
+            .unwrap_or(false);
+        Self {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            include_usage,
+        }
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        request: &ChatRequest,
+    ) -> Result<reqwest::RequestBuilder, LlmError> {
+        Ok(client
+            .post(format!("{}/v1/chat/completions", self.host))
+            .bearer_auth(&self.api_key)
+            .json(&request_body(request, self.include_usage)))
+    }
+
+    fn parse_event(&self, event: &SseEvent) -> Result<StreamEvent, LlmError> {
+        parse_event(event)
+    }
+
+    fn parse_usage(&self, event: &SseEvent) -> Option<TokenUsage> {
+        parse_usage(event)
+    }
+}
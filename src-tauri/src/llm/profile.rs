@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+
+use super::anthropic::AnthropicProvider;
+use super::azure::AzureProvider;
+use super::limit::RateLimits;
+use super::ollama::OllamaProvider;
+use super::openai::OpenAiProvider;
+use super::provider::LlmProvider;
+use super::LlmError;
+use crate::app_config::AppConfig;
+
+/// name of the profile made from `apiKey` when the config has none
+pub const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_OPENAI_HOST: &str = "https://api.openai.com";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-3.5-turbo";
+
+/// where a profile sends its requests, by protocol
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum ProviderConfig {
+    #[serde(rename = "openai", rename_all = "camelCase")]
+    OpenAi {
+        #[serde(default)]
+        host: Option<String>,
+        #[serde(default)]
+        api_key: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Azure {
+        /// `https://<resource>.openai.azure.com`
+        endpoint: String,
+        deployment: String,
+        #[serde(default)]
+        api_version: Option<String>,
+        #[serde(default)]
+        api_key: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Anthropic {
+        #[serde(default)]
+        host: Option<String>,
+        #[serde(default)]
+        api_key: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Ollama {
+        #[serde(default)]
+        host: Option<String>,
+        #[serde(default)]
+        api_key: Option<String>,
+    },
+}
+
+impl ProviderConfig {
+    pub fn build(&self) -> Box<dyn LlmProvider> {
+        match self {
+            ProviderConfig::OpenAi { host, api_key } => Box::new(OpenAiProvider::new(
+                host.as_deref().unwrap_or(DEFAULT_OPENAI_HOST),
+                api_key,
+            )),
+            ProviderConfig::Azure {
+                endpoint,
+                deployment,
+                api_version,
+                api_key,
+            } => Box::new(AzureProvider::new(
+                endpoint,
+                deployment,
+                api_version
+                    .as_deref()
+                    .unwrap_or(super::azure::DEFAULT_API_VERSION),
+                api_key,
+            )),
+            ProviderConfig::Anthropic { host, api_key } => Box::new(AnthropicProvider::new(
+                host.as_deref().unwrap_or(super::anthropic::DEFAULT_HOST),
+                api_key,
+            )),
+            ProviderConfig::Ollama { host, api_key } => Box::new(OllamaProvider::new(
+                host.as_deref().unwrap_or(super::ollama::DEFAULT_HOST),
+                api_key.as_deref(),
+            )),
+        }
+    }
+}
+
+/// A named provider and model, e.g. "work" for an Azure deployment and
+/// "local" for Ollama.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmProfile {
+    pub name: String,
+    /// used when a request doesn't name a model
+    #[serde(default)]
+    pub model: String,
+    #[serde(flatten)]
+    pub provider: ProviderConfig,
+    #[serde(default, flatten)]
+    pub limits: RateLimits,
+}
+
+/// a profile as listed to the frontend, without its key
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmProfileSummary {
+    pub name: String,
+    pub provider: &'static str,
+    pub model: String,
+    pub is_default: bool,
+}
+
+/// The profiles of the app config. Without any, the old `apiKey` setting
+/// makes an OpenAI profile named `default`.
+pub struct ProviderRegistry {
+    profiles: Vec<LlmProfile>,
+    default_profile: String,
+    /// the profiles come from the config, not from `apiKey`
+    configured: bool,
+}
+
+impl ProviderRegistry {
+    pub fn from_config(app_config: &AppConfig) -> Self {
+        let configured = app_config
+            .llm_profiles
+            .iter()
+            .any(|profiles| !profiles.is_empty());
+        let profiles = app_config
+            .llm_profiles
+            .clone()
+            .filter(|profiles| !profiles.is_empty())
+            .unwrap_or_else(|| {
+                vec![LlmProfile {
+                    name: DEFAULT_PROFILE.to_string(),
+                    model: DEFAULT_OPENAI_MODEL.to_string(),
+                    provider: ProviderConfig::OpenAi {
+                        host: None,
+                        api_key: app_config.api_key.clone().unwrap_or_default(),
+                    },
+                    limits: RateLimits::default(),
+                }]
+            });
+        let default_profile = app_config
+            .default_llm_profile
+            .clone()
+            .filter(|name| profiles.iter().any(|profile| &profile.name == name))
+            .unwrap_or_else(|| profiles[0].name.clone());
+        Self {
+            profiles,
+            default_profile,
+            configured,
+        }
+    }
+
+    pub fn load() -> Self {
+        Self::from_config(&crate::app_config::get_app_config().unwrap_or_default())
+    }
+
+    /// profile `name`, or the default one
+    pub fn profile(&self, name: Option<&str>) -> Result<&LlmProfile, LlmError> {
+        let name = name.unwrap_or(&self.default_profile);
+        self.profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| LlmError::InvalidRequest {
+                message: format!("not found llm profile {}", name),
+            })
+    }
+
+    /// the profiles to pick from, none when the config only has `apiKey`
+    /// and requests go to the api host of the settings instead
+    pub fn summaries(&self) -> Vec<LlmProfileSummary> {
+        if !self.configured {
+            return Vec::new();
+        }
+        self.profiles
+            .iter()
+            .map(|profile| LlmProfileSummary {
+                name: profile.name.clone(),
+                provider: profile.provider.build().name(),
+                model: profile.model.clone(),
+                is_default: profile.name == self.default_profile,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,366 @@
+use serde_json::json;
+
+use super::error::{classify, retry_after};
+use super::sse::{JsonLinesParser, SseEvent, SseParser};
+use super::{ChatRequest, LlmError, Timeouts};
+
+/// what one event of a streamed answer means
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// the next piece of the answer
+    Delta(String),
+    /// the answer is complete
+    Done,
+    /// pings, roles and other bookkeeping
+    Ignore,
+}
+
+/// Token counts a provider reported for a call, e.g. in the last event of
+/// the stream. Counts it didn't report are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: Option<usize>,
+    pub completion_tokens: Option<usize>,
+}
+
+impl TokenUsage {
+    /// later reports replace the counts they have
+    pub fn merge(&mut self, other: TokenUsage) {
+        self.prompt_tokens = other.prompt_tokens.or(self.prompt_tokens);
+        self.completion_tokens = other.completion_tokens.or(self.completion_tokens);
+    }
+}
+
+/// how the body of a streamed answer is split into events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// `text/event-stream`
+    Sse,
+    /// one JSON object per line, each passed on as the data of an event
+    JsonLines,
+}
+
+/// One model api: its URL layout, authentication, streaming format and
+/// errors. The streaming itself is shared, see `stream_chat`.
+pub trait LlmProvider: Send + Sync {
+    /// name of the protocol, as written in a profile
+    fn name(&self) -> &'static str;
+
+    /// the streaming request for `request`
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        request: &ChatRequest,
+    ) -> Result<reqwest::RequestBuilder, LlmError>;
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Sse
+    }
+
+    fn parse_event(&self, event: &SseEvent) -> Result<StreamEvent, LlmError>;
+
+    /// the token counts in `event`, if it has any
+    fn parse_usage(&self, _event: &SseEvent) -> Option<TokenUsage> {
+        None
+    }
+
+    /// the error for a response with an error status
+    fn map_error(&self, status: u16, body: &str) -> LlmError {
+        LlmError::Http {
+            status,
+            code: classify(Some(status), body),
+            message: error_message(body),
+            retry_after_ms: None,
+        }
+    }
+}
+
+/// the message of an `{"error": ...}` body, or the body itself
+pub fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| {
+            let error = value.get("error")?;
+            error
+                .get("message")
+                .and_then(|message| message.as_str())
+                .map(String::from)
+                .or_else(|| error.as_str().map(String::from))
+        })
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// an `Api` error for an error object found inside a stream
+pub fn stream_error(error: &serde_json::Value) -> LlmError {
+    LlmError::Api {
+        code: classify(None, &error.to_string()),
+        message: error_message(&json!({ "error": error }).to_string()),
+    }
+}
+
+pub fn parse_json<T: serde::de::DeserializeOwned>(data: &str) -> Result<T, LlmError> {
+    serde_json::from_str(data).map_err(|err| LlmError::InvalidResponse {
+        message: format!("{}: {}", err, data),
+    })
+}
+
+enum Parser {
+    Sse(SseParser),
+    JsonLines(JsonLinesParser),
+}
+
+impl Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        match self {
+            Parser::Sse(parser) => parser.feed(chunk),
+            Parser::JsonLines(parser) => parser.feed(chunk),
+        }
+    }
+
+    fn finish(&mut self) -> Option<SseEvent> {
+        match self {
+            Parser::Sse(parser) => parser.finish(),
+            Parser::JsonLines(parser) => parser.finish(),
+        }
+    }
+}
+
+/// Stream the answer to `request` from `provider`, `on_token` gets every new
+/// piece and `on_usage` the token counts the provider reports. The whole
+/// answer is returned once the provider says it's done or the server closes
+/// the stream.
+pub async fn stream_chat(
+    provider: &dyn LlmProvider,
+    client: &reqwest::Client,
+    request: &ChatRequest,
+    timeouts: Timeouts,
+    mut on_token: impl FnMut(&str),
+    mut on_usage: impl FnMut(TokenUsage),
+) -> Result<String, LlmError> {
+    let send = provider.request(client, request)?.send();
+    let mut response = tokio::time::timeout(timeouts.response, send)
+        .await
+        .map_err(|_| LlmError::Timeout {
+            message: format!("no response in {:?}", timeouts.response),
+        })??;
+    let status = response.status();
+    if !status.is_success() {
+        let wait = retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        return Err(provider
+            .map_error(status.as_u16(), &body)
+            .with_retry_after(wait));
+    }
+
+    let mut parser = match provider.stream_format() {
+        StreamFormat::Sse => Parser::Sse(SseParser::new()),
+        StreamFormat::JsonLines => Parser::JsonLines(JsonLinesParser::new()),
+    };
+    let mut answer = String::new();
+    loop {
+        let chunk = tokio::time::timeout(timeouts.idle, response.chunk())
+            .await
+            .map_err(|_| LlmError::Timeout {
+                message: format!("nothing received in {:?}", timeouts.idle),
+            })??;
+        let (events, end) = match chunk {
+            Some(chunk) => (parser.feed(&chunk), false),
+            None => (parser.finish().into_iter().collect(), true),
+        };
+        for event in events {
+            if let Some(usage) = provider.parse_usage(&event) {
+                on_usage(usage);
+            }
+            match provider.parse_event(&event)? {
+                StreamEvent::Delta(delta) if !delta.is_empty() => {
+                    answer.push_str(&delta);
+                    on_token(&delta);
+                }
+                StreamEvent::Delta(_) | StreamEvent::Ignore => {}
+                StreamEvent::Done => return Ok(answer),
+            }
+        }
+        if end {
+            return Ok(answer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::llm::openai::OpenAiProvider;
+    use crate::llm::stub::{client, Reply, StubServer};
+    use crate::llm::{ChatMessage, ErrorCode, LlmRequests};
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "gpt-4o-mini".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: "hi".to_string(),
+            }],
+            max_tokens: None,
+            temperature: None,
+        }
+    }
+
+    fn timeouts(millis: u64) -> Timeouts {
+        Timeouts {
+            response: Duration::from_millis(millis),
+            idle: Duration::from_millis(millis),
+        }
+    }
+
+    async fn stream(
+        server: &StubServer,
+        timeouts: Timeouts,
+    ) -> (Result<String, LlmError>, Vec<String>) {
+        let provider = OpenAiProvider::new(&server.url, "sk-test");
+        let mut tokens = Vec::new();
+        let result = stream_chat(
+            &provider,
+            &client(),
+            &request(),
+            timeouts,
+            |delta| tokens.push(delta.to_string()),
+            |_| {},
+        )
+        .await;
+        (result, tokens)
+    }
+
+    #[tokio::test]
+    async fn stream_joins_chunks_split_inside_lines_and_characters() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"你好\"}}]}\n\n",
+            ": keep-alive\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\r\n\r\n",
+            "data: [DONE]\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"after done\"}}]}\n\n",
+        )
+        .as_bytes();
+        // inside the first line, inside the 3 bytes of `你`, inside `[DONE]`
+        let first = body.iter().position(|byte| *byte == b'r').unwrap();
+        let character = body
+            .windows(3)
+            .position(|bytes| bytes == "你".as_bytes())
+            .unwrap()
+            + 1;
+        let done = body.windows(4).position(|bytes| bytes == b"DONE").unwrap() + 2;
+        let server = StubServer::start(vec![Reply::stream(&[
+            &body[..first],
+            &body[first..character],
+            &body[character..done],
+            &body[done..],
+        ])]);
+
+        let (result, tokens) = stream(&server, timeouts(5000)).await;
+        assert_eq!(result.unwrap(), "你好 world");
+        assert_eq!(tokens, vec!["你好", " world"]);
+    }
+
+    #[tokio::test]
+    async fn stream_ends_with_the_body_without_done() {
+        let server = StubServer::start(vec![Reply::stream(&[
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"a\"}}]}\n\n",
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"b\"}}]}",
+        ])]);
+        let (result, _) = stream(&server, timeouts(5000)).await;
+        assert_eq!(result.unwrap(), "ab");
+    }
+
+    #[tokio::test]
+    async fn error_status_keeps_retry_after() {
+        let server = StubServer::start(vec![Reply::new(429)
+            .header("content-type", "application/json")
+            .header("retry-after", "7")
+            .body(r#"{"error":{"message":"Rate limit reached","type":"requests"}}"#)]);
+        let (result, tokens) = stream(&server, timeouts(5000)).await;
+        assert_eq!(
+            result.unwrap_err(),
+            LlmError::Http {
+                status: 429,
+                code: ErrorCode::RateLimit,
+                message: "Rate limit reached".to_string(),
+                retry_after_ms: Some(7000),
+            }
+        );
+        assert!(tokens.is_empty());
+    }
+
+    #[tokio::test]
+    async fn error_object_inside_the_stream() {
+        let server = StubServer::start(vec![Reply::stream(&[
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"a\"}}]}\n\n",
+            b"data: {\"error\":{\"message\":\"This model's maximum context length is 4097 tokens\"}}\n\n",
+        ])]);
+        let (result, tokens) = stream(&server, timeouts(5000)).await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::ContextTooLong);
+        assert_eq!(tokens, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn response_timeout() {
+        let server = StubServer::start(vec![
+            Reply::stream(&[b"data: [DONE]\n\n"]).delay(Duration::from_millis(1000))
+        ]);
+        let (result, _) = stream(&server, timeouts(200)).await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::Timeout);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_after_the_first_piece() {
+        let server = StubServer::start(vec![Reply::stream(&[
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"a\"}}]}\n\n",
+        ])
+        .chunk(Duration::from_millis(1000), b"data: [DONE]\n\n")]);
+        let (result, tokens) = stream(&server, timeouts(200)).await;
+        assert!(matches!(result, Err(LlmError::Timeout { .. })));
+        assert_eq!(tokens, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn cancel_by_request_id() {
+        let server = StubServer::start(vec![Reply::stream(&[
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"a\"}}]}\n\n",
+        ])
+        .chunk(Duration::from_millis(2000), b"data: [DONE]\n\n")]);
+        let requests = LlmRequests::default();
+        let running = requests.register("chat-1").unwrap();
+        assert!(requests.register("chat-1").is_err());
+
+        let (result, cancelled) = tokio::join!(
+            running.run(async { stream(&server, timeouts(5000)).await.0 }),
+            async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                (requests.cancel("other"), requests.cancel("chat-1"))
+            }
+        );
+        assert_eq!(cancelled, (false, true));
+        assert_eq!(result.unwrap_err(), LlmError::Cancelled);
+        // the id is free again
+        assert!(!requests.cancel("chat-1"));
+        assert!(requests.register("chat-1").is_ok());
+    }
+
+    #[test]
+    fn dropped_requests_free_their_id() {
+        let requests = LlmRequests::default();
+        // e.g. the command failed before streaming or its future was dropped
+        drop(requests.register("chat-1").unwrap());
+        assert!(!requests.cancel("chat-1"));
+
+        let cancelled = requests.register("chat-2").unwrap();
+        assert!(requests.cancel("chat-2"));
+        let newer = requests.register("chat-2").unwrap();
+        // the cancelled one leaves the newer request alone
+        drop(cancelled);
+        assert!(requests.register("chat-2").is_err());
+        drop(newer);
+        assert!(requests.register("chat-2").is_ok());
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::limit::{RateLimiters, RateLimits};
+use super::provider::{stream_chat, LlmProvider, TokenUsage};
+use super::{ChatRequest, LlmError, Timeouts};
+
+/// sent to all windows before a failed request is tried again
+pub const LLM_RETRY_EVENT: &str = "llm-retry";
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_BASE_DELAY_MS: u64 = 1000;
+const DEFAULT_MAX_DELAY_MS: u64 = 60_000;
+
+/// `llmRetry` of the app config
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryConfig {
+    /// 0 turns retrying off
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// wait before the first retry, doubled for every further one
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+    /// longest wait; a `Retry-After` beyond it fails the request instead
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+fn default_max_retries() -> u32 {
+    DEFAULT_MAX_RETRIES
+}
+
+fn default_base_delay_ms() -> u64 {
+    DEFAULT_BASE_DELAY_MS
+}
+
+fn default_max_delay_ms() -> u64 {
+    DEFAULT_MAX_DELAY_MS
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay_ms: DEFAULT_BASE_DELAY_MS,
+            max_delay_ms: DEFAULT_MAX_DELAY_MS,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn load() -> Self {
+        crate::app_config::get_app_config()
+            .ok()
+            .and_then(|app_config| app_config.llm_retry)
+            .unwrap_or_default()
+    }
+
+    /// How long to wait before retry number `attempt` (from 1) after `error`,
+    /// or `None` to give up. The server's `Retry-After` wins over the
+    /// backoff, which is exponential with half of it jittered.
+    pub fn delay(&self, attempt: u32, error: &LlmError) -> Option<Duration> {
+        if attempt > self.max_retries || !error.code().is_retryable() {
+            return None;
+        }
+        let max_delay = Duration::from_millis(self.max_delay_ms);
+        if let Some(retry_after) = error.retry_after() {
+            return (retry_after <= max_delay).then_some(retry_after);
+        }
+        let backoff = self
+            .base_delay_ms
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay_ms);
+        let half = backoff / 2;
+        let jitter = rand::thread_rng().gen_range(0..=backoff - half);
+        Some(Duration::from_millis(half + jitter))
+    }
+}
+
+/// payload of `LLM_RETRY_EVENT`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryNotice {
+    pub request_id: String,
+    /// 1 for the first retry
+    pub attempt: u32,
+    pub delay_ms: u64,
+    pub error: LlmError,
+}
+
+/// where a request goes and how hard to try
+pub struct Attempts<'a> {
+    pub limiters: &'a RateLimiters,
+    /// the limiter to use, see `RateLimiters::acquire`
+    pub source: &'a str,
+    pub limits: RateLimits,
+    pub retry: RetryConfig,
+    /// of every attempt
+    pub timeouts: Timeouts,
+}
+
+/// `stream_chat` within the rate limits of the source, sent again after
+/// errors that may pass. Once a piece of the answer went out a retry would
+/// repeat it, so the error is returned instead.
+pub async fn stream_with_retry(
+    provider: &dyn LlmProvider,
+    client: &reqwest::Client,
+    request: &ChatRequest,
+    attempts: Attempts<'_>,
+    mut on_token: impl FnMut(&str),
+    mut on_usage: impl FnMut(TokenUsage),
+    mut on_retry: impl FnMut(u32, Duration, &LlmError),
+) -> Result<String, LlmError> {
+    let mut attempt = 0;
+    loop {
+        let mut received = false;
+        let permit = attempts
+            .limiters
+            .acquire(attempts.source, attempts.limits)
+            .await;
+        let result = stream_chat(
+            provider,
+            client,
+            request,
+            attempts.timeouts,
+            |delta| {
+                received = true;
+                on_token(delta);
+            },
+            &mut on_usage,
+        )
+        .await;
+        drop(permit);
+        let error = match result {
+            Ok(answer) => return Ok(answer),
+            Err(error) => error,
+        };
+        attempt += 1;
+        let delay = match attempts.retry.delay(attempt, &error) {
+            Some(delay) if !received => delay,
+            _ => return Err(error),
+        };
+        on_retry(attempt, delay, &error);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ErrorCode;
+
+    fn http(status: u16, code: ErrorCode, retry_after_ms: Option<u64>) -> LlmError {
+        LlmError::Http {
+            status,
+            code,
+            message: String::new(),
+            retry_after_ms,
+        }
+    }
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            max_retries: 10,
+            base_delay_ms: 1000,
+            max_delay_ms: 10_000,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_half_of_it_jittered() {
+        let error = http(503, ErrorCode::Server, None);
+        for (attempt, backoff) in [(1, 1000), (2, 2000), (3, 4000), (4, 8000), (5, 10_000)] {
+            for _ in 0..50 {
+                let delay = config().delay(attempt, &error).unwrap().as_millis() as u64;
+                assert!(
+                    (backoff / 2..=backoff).contains(&delay),
+                    "attempt {}: {}",
+                    attempt,
+                    delay
+                );
+            }
+        }
+        // no overflow for late attempts
+        let delay = config().delay(10, &error).unwrap();
+        assert!(delay <= Duration::from_millis(10_000));
+    }
+
+    #[test]
+    fn retry_after_wins_up_to_the_max_delay() {
+        let error = http(429, ErrorCode::RateLimit, Some(7000));
+        assert_eq!(config().delay(1, &error), Some(Duration::from_millis(7000)));
+        let error = http(429, ErrorCode::RateLimit, Some(10_000));
+        assert_eq!(
+            config().delay(3, &error),
+            Some(Duration::from_millis(10_000))
+        );
+        // waiting longer than allowed fails the request instead
+        let error = http(429, ErrorCode::RateLimit, Some(10_001));
+        assert_eq!(config().delay(1, &error), None);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries_and_on_final_errors() {
+        let config = RetryConfig {
+            max_retries: 2,
+            ..config()
+        };
+        let error = http(503, ErrorCode::Server, None);
+        assert!(config.delay(2, &error).is_some());
+        assert_eq!(config.delay(3, &error), None);
+        assert_eq!(config.delay(1, &http(401, ErrorCode::Auth, None)), None);
+        assert_eq!(config.delay(1, &LlmError::Cancelled), None);
+        let off = RetryConfig {
+            max_retries: 0,
+            ..config
+        };
+        assert_eq!(off.delay(1, &error), None);
+    }
+}
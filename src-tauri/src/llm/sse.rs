@@ -0,0 +1,126 @@
+/// Incremental parser for `text/event-stream` bodies. Chunks may split lines
+/// and even UTF-8 characters, only complete lines are decoded.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+    event: Option<String>,
+}
+
+/// one dispatched server sent event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// the `event:` field, `None` for plain `message` events
+    pub event: Option<String>,
+    pub data: String,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// events completed by `chunk`
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        for line in take_lines(&mut self.buffer) {
+            if let Some(event) = self.line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// the event still open when the body ended without a blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if let Some(line) = take_rest(&mut self.buffer) {
+            self.line(&line);
+        }
+        self.dispatch()
+    }
+
+    fn line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => self.data.push(value.to_string()),
+            "event" => self.event = Some(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data).join("\n");
+        Some(SseEvent { event, data })
+    }
+}
+
+/// Parser for bodies with one JSON object per line, like Ollama's. Every
+/// line that isn't blank becomes the data of an event.
+#[derive(Debug, Default)]
+pub struct JsonLinesParser {
+    buffer: Vec<u8>,
+}
+
+impl JsonLinesParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        take_lines(&mut self.buffer)
+            .into_iter()
+            .filter_map(json_line)
+            .collect()
+    }
+
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        take_rest(&mut self.buffer).and_then(json_line)
+    }
+}
+
+fn json_line(line: String) -> Option<SseEvent> {
+    let data = line.trim();
+    (!data.is_empty()).then(|| SseEvent {
+        event: None,
+        data: data.to_string(),
+    })
+}
+
+/// complete lines at the start of `buffer`, without their line break
+fn take_lines(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+        let line: Vec<u8> = buffer.drain(..=end).collect();
+        let line = String::from_utf8_lossy(&line);
+        lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+    }
+    lines
+}
+
+/// the last line when the body didn't end with a line break
+fn take_rest(buffer: &mut Vec<u8>) -> Option<String> {
+    if buffer.is_empty() {
+        return None;
+    }
+    let line = String::from_utf8_lossy(buffer)
+        .trim_end_matches('\r')
+        .to_string();
+    buffer.clear();
+    Some(line)
+}
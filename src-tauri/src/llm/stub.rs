@@ -0,0 +1,172 @@
+//! A local http server for the provider tests, answering each connection
+//! with the next canned reply and keeping the requests it got.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+/// what the server sends back for one request
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// wait before the status line
+    pub delay: Duration,
+    /// pieces of the body, each written after its pause
+    pub chunks: Vec<(Duration, Vec<u8>)>,
+}
+
+impl Reply {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            delay: Duration::ZERO,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// a `text/event-stream` answer sent in `chunks`
+    pub fn stream(chunks: &[&[u8]]) -> Self {
+        chunks.iter().fold(
+            Self::new(200).header("content-type", "text/event-stream"),
+            |reply, chunk| reply.chunk(Duration::from_millis(20), chunk),
+        )
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn chunk(mut self, pause: Duration, chunk: &[u8]) -> Self {
+        self.chunks.push((pause, chunk.to_vec()));
+        self
+    }
+
+    pub fn body(self, body: &str) -> Self {
+        self.chunk(Duration::ZERO, body.as_bytes())
+    }
+}
+
+/// a request as the server got it
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: String,
+    /// path and query
+    pub path: String,
+    /// names in lower case
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Received {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).expect("json request body")
+    }
+}
+
+pub struct StubServer {
+    pub url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl StubServer {
+    /// serves `replies` in order, one per connection
+    pub fn start(replies: Vec<Reply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind the stub server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let requests = received.clone();
+        std::thread::spawn(move || {
+            for reply in replies {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(_) => return,
+                };
+                let requests = requests.clone();
+                std::thread::spawn(move || serve(stream, &reply, &requests));
+            }
+        });
+        Self { url, received }
+    }
+
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().clone()
+    }
+}
+
+fn serve(mut stream: TcpStream, reply: &Reply, received: &Mutex<Vec<Received>>) -> Option<()> {
+    stream.set_nodelay(true).ok()?;
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+    }
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    received.lock().push(Received {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    });
+
+    // the body ends when the connection closes, so chunks go out as written
+    std::thread::sleep(reply.delay);
+    let mut head = format!("HTTP/1.1 {} Stub\r\nconnection: close\r\n", reply.status);
+    for (name, value) in &reply.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    if stream.write_all(head.as_bytes()).is_ok() {
+        for (pause, chunk) in &reply.chunks {
+            std::thread::sleep(*pause);
+            if stream
+                .write_all(chunk)
+                .and_then(|_| stream.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+    Some(())
+}
+
+/// a client that ignores the proxy settings of the environment
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder().no_proxy().build().unwrap()
+}
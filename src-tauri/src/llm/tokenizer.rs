@@ -0,0 +1,93 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+
+use super::ChatMessage;
+
+/// every message is wrapped in `<|start|>{role}\n{content}<|end|>\n`
+const TOKENS_PER_MESSAGE: usize = 3;
+/// every reply is primed with `<|start|>assistant<|message|>`
+pub const REPLY_PRIMING_TOKENS: usize = 3;
+
+/// BPE vocabularies bundled with the app
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Cl100kBase,
+    O200kBase,
+}
+
+static CL100K_BASE: Lazy<CoreBPE> =
+    Lazy::new(|| tiktoken_rs::cl100k_base().expect("bundled cl100k_base vocabulary"));
+static O200K_BASE: Lazy<CoreBPE> =
+    Lazy::new(|| tiktoken_rs::o200k_base().expect("bundled o200k_base vocabulary"));
+
+impl Encoding {
+    /// The encoding of an OpenAI model. Other models get cl100k, which is
+    /// close enough for budgeting.
+    pub fn for_model(model: &str) -> Self {
+        match tiktoken_rs::tokenizer::get_tokenizer(model) {
+            Some(tiktoken_rs::tokenizer::Tokenizer::O200kBase) => Encoding::O200kBase,
+            _ => Encoding::Cl100kBase,
+        }
+    }
+
+    fn bpe(self) -> &'static CoreBPE {
+        match self {
+            Encoding::Cl100kBase => &CL100K_BASE,
+            Encoding::O200kBase => &O200K_BASE,
+        }
+    }
+
+    /// special tokens in `text` count as plain text, as the api treats them
+    pub fn count_tokens(self, text: &str) -> usize {
+        self.bpe().encode_ordinary(text).len()
+    }
+
+    pub fn count_message_tokens(self, message: &ChatMessage) -> usize {
+        TOKENS_PER_MESSAGE + self.count_tokens(&message.role) + self.count_tokens(&message.content)
+    }
+
+    /// tokens of a whole prompt, as the api bills them
+    pub fn count_prompt_tokens(self, messages: &[ChatMessage]) -> usize {
+        messages
+            .iter()
+            .map(|message| self.count_message_tokens(message))
+            .sum::<usize>()
+            + REPLY_PRIMING_TOKENS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_pick_their_encoding() {
+        assert_eq!(Encoding::for_model("gpt-4o"), Encoding::O200kBase);
+        assert_eq!(Encoding::for_model("gpt-4"), Encoding::Cl100kBase);
+        assert_eq!(Encoding::for_model("gpt-3.5-turbo"), Encoding::Cl100kBase);
+        assert_eq!(Encoding::for_model("llama3"), Encoding::Cl100kBase);
+    }
+
+    #[test]
+    fn counts_text_and_messages() {
+        assert_eq!(Encoding::Cl100kBase.count_tokens("hello world"), 2);
+        assert_eq!(Encoding::Cl100kBase.count_tokens(""), 0);
+        // a special token in the text is not one token
+        assert!(Encoding::Cl100kBase.count_tokens("<|endoftext|>") > 1);
+
+        let message = ChatMessage {
+            role: "user".to_string(),
+            content: "hello world".to_string(),
+        };
+        assert_eq!(
+            Encoding::Cl100kBase.count_message_tokens(&message),
+            TOKENS_PER_MESSAGE + 1 + 2
+        );
+        assert_eq!(
+            Encoding::Cl100kBase.count_prompt_tokens(&[message.clone(), message]),
+            2 * (TOKENS_PER_MESSAGE + 3) + REPLY_PRIMING_TOKENS
+        );
+    }
+}
@@ -0,0 +1,665 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{Local, TimeZone};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use super::provider::TokenUsage;
+use super::tokenizer::Encoding;
+use super::{ChatMessage, ErrorCode};
+
+const DEFAULT_WARN_RATIO: f64 = 0.8;
+
+/// What a model costs, in dollars per million tokens.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    /// prefix of the model names it applies to, the longest one wins
+    pub model: String,
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// list prices, used until the app config has its own table
+pub fn default_pricing() -> Vec<ModelPrice> {
+    fn price(model: &str, prompt: f64, completion: f64) -> ModelPrice {
+        ModelPrice {
+            model: model.to_string(),
+            prompt,
+            completion,
+        }
+    }
+    vec![
+        price("gpt-3.5-turbo", 0.5, 1.5),
+        price("gpt-4", 30.0, 60.0),
+        price("gpt-4-turbo", 10.0, 30.0),
+        price("gpt-4o", 2.5, 10.0),
+        price("gpt-4o-mini", 0.15, 0.6),
+        price("claude-3-haiku", 0.25, 1.25),
+        price("claude-3-5-sonnet", 3.0, 15.0),
+        price("claude-3-opus", 15.0, 75.0),
+    ]
+}
+
+/// `llmPricing` of the app config, or the list prices
+pub fn pricing() -> Vec<ModelPrice> {
+    crate::app_config::get_app_config()
+        .ok()
+        .and_then(|app_config| app_config.llm_pricing)
+        .unwrap_or_else(default_pricing)
+}
+
+/// Dollars for a call to `model`. Models without a price, e.g. local ones,
+/// are free.
+pub fn cost(
+    pricing: &[ModelPrice],
+    model: &str,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+) -> f64 {
+    pricing
+        .iter()
+        .filter(|price| model.starts_with(&price.model))
+        .max_by_key(|price| price.model.len())
+        .map(|price| {
+            (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion)
+                / 1_000_000.0
+        })
+        .unwrap_or_default()
+}
+
+/// One model call. Cache hits aren't calls and aren't recorded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    /// seconds since the unix epoch
+    pub timestamp: i64,
+    /// local date, `2024-05-01`
+    pub day: String,
+    /// profile or host the call went to
+    pub source: String,
+    pub provider: String,
+    pub model: String,
+    #[serde(default)]
+    pub action_id: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    /// until the whole answer arrived, retries included
+    pub latency_ms: u64,
+    /// dollars, by the pricing table at the time of the call
+    pub cost: f64,
+    /// why the call failed; failures that got no answer count no tokens
+    #[serde(default)]
+    pub error: Option<ErrorCode>,
+    /// failed or cancelled after part of the answer arrived, which still
+    /// bills the whole prompt
+    #[serde(default)]
+    pub partial: bool,
+    /// counted with the local tokenizer because the provider reported none
+    #[serde(default)]
+    pub estimated: bool,
+}
+
+/// the tokens a call is billed for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BilledTokens {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    /// see `UsageRecord::partial`
+    pub partial: bool,
+    /// see `UsageRecord::estimated`
+    pub estimated: bool,
+}
+
+/// The tokens of a call that `completed` or not: the counts the provider
+/// reported, the local tokenizer's for the rest. A failed call bills nothing
+/// unless the provider reported the prompt or part of the answer arrived.
+pub fn billed_tokens(
+    reported: TokenUsage,
+    completed: bool,
+    messages: &[ChatMessage],
+    answer: &str,
+    encoding: Encoding,
+) -> BilledTokens {
+    if !completed && answer.is_empty() && reported.prompt_tokens.is_none() {
+        return BilledTokens::default();
+    }
+    let completion_tokens = match reported.completion_tokens {
+        // pieces may have arrived after the last report of a call cut short
+        Some(reported) if !completed => Some(reported.max(encoding.count_tokens(answer))),
+        reported => reported,
+    };
+    BilledTokens {
+        prompt_tokens: reported
+            .prompt_tokens
+            .unwrap_or_else(|| encoding.count_prompt_tokens(messages)),
+        completion_tokens: completion_tokens.unwrap_or_else(|| encoding.count_tokens(answer)),
+        partial: !completed && !answer.is_empty(),
+        estimated: reported.prompt_tokens.is_none() || completion_tokens.is_none(),
+    }
+}
+
+pub fn local_day(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_else(Local::now)
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// `2024-05` of `2024-05-01`
+fn month_of(day: &str) -> &str {
+    day.get(..7).unwrap_or(day)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroup {
+    Day,
+    Model,
+    Action,
+    Provider,
+}
+
+/// payload of the `query_llm_usage` command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQuery {
+    pub group_by: UsageGroup,
+    /// first day included, `2024-05-01`
+    #[serde(default)]
+    pub from: Option<String>,
+    /// last day included
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotal {
+    /// the day, model, action id or provider; calls from the chat window
+    /// have no action and are under ""
+    pub key: String,
+    pub requests: usize,
+    pub failed: usize,
+    /// failed calls that got part of an answer, they are in `failed` too
+    pub partial: usize,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub cost: f64,
+    pub avg_latency_ms: u64,
+}
+
+/// totals of the records within the query's days, ordered by key
+pub fn totals(records: &[UsageRecord], query: &UsageQuery) -> Vec<UsageTotal> {
+    let mut groups: BTreeMap<String, (UsageTotal, u64)> = BTreeMap::new();
+    let in_range = |record: &&UsageRecord| {
+        let day = record.day.as_str();
+        !matches!(query.from.as_deref(), Some(from) if day < from)
+            && !matches!(query.to.as_deref(), Some(to) if day > to)
+    };
+    for record in records.iter().filter(in_range) {
+        let key = match query.group_by {
+            UsageGroup::Day => record.day.clone(),
+            UsageGroup::Model => record.model.clone(),
+            UsageGroup::Action => record.action_id.clone().unwrap_or_default(),
+            UsageGroup::Provider => record.provider.clone(),
+        };
+        let (total, latency) = groups.entry(key).or_default();
+        total.requests += 1;
+        total.failed += usize::from(record.error.is_some());
+        total.partial += usize::from(record.partial);
+        total.prompt_tokens += record.prompt_tokens;
+        total.completion_tokens += record.completion_tokens;
+        total.cost += record.cost;
+        *latency += record.latency_ms;
+    }
+    groups
+        .into_iter()
+        .map(|(key, (total, latency))| UsageTotal {
+            key,
+            avg_latency_ms: latency / total.requests as u64,
+            ..total
+        })
+        .collect()
+}
+
+/// `llmBudget` of the app config, in dollars
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetConfig {
+    /// calls are refused for the rest of the day once it is spent
+    #[serde(default)]
+    pub daily: Option<f64>,
+    #[serde(default)]
+    pub monthly: Option<f64>,
+    /// share of a budget spent that warns
+    #[serde(default = "default_warn_ratio")]
+    pub warn_ratio: f64,
+}
+
+fn default_warn_ratio() -> f64 {
+    DEFAULT_WARN_RATIO
+}
+
+impl BudgetConfig {
+    pub fn load() -> Option<Self> {
+        crate::app_config::get_app_config()
+            .ok()
+            .and_then(|app_config| app_config.llm_budget)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLevel {
+    Ok,
+    Warning,
+    Exceeded,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetStatus {
+    pub period: BudgetPeriod,
+    /// `2024-05-01` or `2024-05`
+    pub key: String,
+    pub spent: f64,
+    pub limit: f64,
+    pub level: BudgetLevel,
+}
+
+/// the configured budgets as of `today`
+pub fn budget_status(
+    records: &[UsageRecord],
+    config: &BudgetConfig,
+    today: &str,
+) -> Vec<BudgetStatus> {
+    let periods = [
+        (BudgetPeriod::Day, config.daily, today),
+        (BudgetPeriod::Month, config.monthly, month_of(today)),
+    ];
+    periods
+        .into_iter()
+        .filter_map(|(period, limit, key)| {
+            let limit = limit.filter(|limit| *limit > 0.0)?;
+            let spent: f64 = records
+                .iter()
+                .filter(|record| match period {
+                    BudgetPeriod::Day => record.day == key,
+                    BudgetPeriod::Month => month_of(&record.day) == key,
+                })
+                .map(|record| record.cost)
+                .sum();
+            let level = if spent >= limit {
+                BudgetLevel::Exceeded
+            } else if spent >= limit * config.warn_ratio {
+                BudgetLevel::Warning
+            } else {
+                BudgetLevel::Ok
+            };
+            Some(BudgetStatus {
+                period,
+                key: key.to_string(),
+                spent,
+                limit,
+                level,
+            })
+        })
+        .collect()
+}
+
+/// Every model call, appended to `usage.jsonl` next to the app config and
+/// loaded on first use.
+#[derive(Default)]
+pub struct UsageStore {
+    records: Mutex<Option<Vec<UsageRecord>>>,
+    /// budget levels already warned about, each is warned once per period
+    warned: Mutex<HashSet<String>>,
+}
+
+fn usage_path() -> Option<PathBuf> {
+    crate::app_config::app_config_dir().map(|dir| dir.join("usage.jsonl"))
+}
+
+/// the records of `usage.jsonl`, lines that can't be read are skipped
+fn read_records() -> Vec<UsageRecord> {
+    let content = match usage_path() {
+        Some(path) if path.exists() => std::fs::read_to_string(path),
+        _ => return Vec::new(),
+    };
+    match content {
+        Ok(content) => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(record) => Some(record),
+                Err(err) => {
+                    tracing::warn!(parse_usage_record_error = ?err);
+                    None
+                }
+            })
+            .collect(),
+        Err(err) => {
+            tracing::warn!(read_usage_error = ?err);
+            Vec::new()
+        }
+    }
+}
+
+fn append_record(record: &UsageRecord) -> anyhow::Result<()> {
+    let path = usage_path().ok_or_else(|| anyhow::anyhow!("not found app config directory"))?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+impl UsageStore {
+    fn with_records<T>(&self, read: impl FnOnce(&mut Vec<UsageRecord>) -> T) -> T {
+        let mut records = self.records.lock();
+        read(records.get_or_insert_with(read_records))
+    }
+
+    pub fn record(&self, record: UsageRecord) {
+        // loaded before appending, or the new record would be read back too
+        self.with_records(|records| {
+            if let Err(err) = append_record(&record) {
+                tracing::warn!(write_usage_error = ?err);
+            }
+            records.push(record);
+        });
+    }
+
+    pub fn totals(&self, query: &UsageQuery) -> Vec<UsageTotal> {
+        self.with_records(|records| totals(records, query))
+    }
+
+    pub fn budget_status(&self, config: &BudgetConfig) -> Vec<BudgetStatus> {
+        let today = local_day(Local::now().timestamp());
+        self.with_records(|records| budget_status(records, config, &today))
+    }
+
+    /// a budget that is spent, calls are refused until its period is over
+    pub fn exceeded(&self, config: &BudgetConfig) -> Option<BudgetStatus> {
+        self.budget_status(config)
+            .into_iter()
+            .find(|status| status.level == BudgetLevel::Exceeded)
+    }
+
+    /// budgets that reached a warning or their cap since last asked
+    pub fn new_warnings(&self, config: &BudgetConfig) -> Vec<BudgetStatus> {
+        let mut warned = self.warned.lock();
+        self.budget_status(config)
+            .into_iter()
+            .filter(|status| status.level != BudgetLevel::Ok)
+            .filter(|status| warned.insert(format!("{}:{:?}", status.key, status.level)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages() -> Vec<ChatMessage> {
+        vec![ChatMessage {
+            role: "user".to_string(),
+            content: "say hello to the world".to_string(),
+        }]
+    }
+
+    fn reported(prompt_tokens: Option<usize>, completion_tokens: Option<usize>) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    #[test]
+    fn reported_usage_wins() {
+        let billed = billed_tokens(
+            reported(Some(120), Some(30)),
+            true,
+            &messages(),
+            "Hello, world",
+            Encoding::Cl100kBase,
+        );
+        assert_eq!(
+            billed,
+            BilledTokens {
+                prompt_tokens: 120,
+                completion_tokens: 30,
+                partial: false,
+                estimated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn missing_usage_is_counted_locally() {
+        let encoding = Encoding::Cl100kBase;
+        let billed = billed_tokens(
+            reported(None, Some(30)),
+            true,
+            &messages(),
+            "Hello, world",
+            encoding,
+        );
+        assert_eq!(
+            billed.prompt_tokens,
+            encoding.count_prompt_tokens(&messages())
+        );
+        assert_eq!(billed.completion_tokens, 30);
+        assert!(billed.estimated);
+    }
+
+    #[test]
+    fn cut_short_calls_bill_the_prompt_and_what_arrived() {
+        let encoding = Encoding::Cl100kBase;
+        let answer = "Hello, world, and a few more words";
+        let billed = billed_tokens(TokenUsage::default(), false, &messages(), answer, encoding);
+        assert_eq!(
+            billed,
+            BilledTokens {
+                prompt_tokens: encoding.count_prompt_tokens(&messages()),
+                completion_tokens: encoding.count_tokens(answer),
+                partial: true,
+                estimated: true,
+            }
+        );
+
+        // anthropic reports the prompt and 1 answer token when it starts
+        let billed = billed_tokens(
+            reported(Some(120), Some(1)),
+            false,
+            &messages(),
+            answer,
+            encoding,
+        );
+        assert_eq!(billed.prompt_tokens, 120);
+        assert_eq!(billed.completion_tokens, encoding.count_tokens(answer));
+        assert!(billed.partial);
+    }
+
+    #[test]
+    fn failures_without_an_answer_bill_only_a_reported_prompt() {
+        let encoding = Encoding::Cl100kBase;
+        let billed = billed_tokens(TokenUsage::default(), false, &messages(), "", encoding);
+        assert_eq!(billed, BilledTokens::default());
+
+        let billed = billed_tokens(
+            reported(Some(120), Some(1)),
+            false,
+            &messages(),
+            "",
+            encoding,
+        );
+        assert_eq!(billed.prompt_tokens, 120);
+        assert_eq!(billed.completion_tokens, 1);
+        assert!(!billed.partial);
+    }
+
+    #[test]
+    fn totals_count_partial_calls() {
+        let record = |error: Option<ErrorCode>, partial: bool| UsageRecord {
+            timestamp: 0,
+            day: "2024-05-01".to_string(),
+            source: "profile:default".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            action_id: None,
+            prompt_tokens: 10,
+            completion_tokens: 5,
+            latency_ms: 100,
+            cost: 0.0,
+            error,
+            partial,
+            estimated: false,
+        };
+        let records = vec![
+            record(None, false),
+            record(Some(ErrorCode::Cancelled), true),
+            record(Some(ErrorCode::Timeout), false),
+        ];
+        let query = UsageQuery {
+            group_by: UsageGroup::Model,
+            from: None,
+            to: None,
+        };
+        let totals = totals(&records, &query);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].requests, 3);
+        assert_eq!(totals[0].failed, 2);
+        assert_eq!(totals[0].partial, 1);
+    }
+
+    fn spent(day: &str, cost: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp: 0,
+            day: day.to_string(),
+            source: "profile:default".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            action_id: None,
+            prompt_tokens: 10,
+            completion_tokens: 5,
+            latency_ms: 100,
+            cost,
+            error: None,
+            partial: false,
+            estimated: false,
+        }
+    }
+
+    fn budget(daily: Option<f64>, monthly: Option<f64>) -> BudgetConfig {
+        BudgetConfig {
+            daily,
+            monthly,
+            warn_ratio: DEFAULT_WARN_RATIO,
+        }
+    }
+
+    fn store(records: Vec<UsageRecord>) -> UsageStore {
+        UsageStore {
+            records: Mutex::new(Some(records)),
+            warned: Mutex::new(HashSet::new()),
+        }
+    }
+
+    #[test]
+    fn budgets_count_their_own_period() {
+        let records = vec![
+            spent("2024-05-01", 1.0),
+            spent("2024-05-02", 2.0),
+            spent("2024-05-02", 0.5),
+            spent("2024-04-30", 4.0),
+        ];
+        let status = budget_status(&records, &budget(Some(10.0), Some(100.0)), "2024-05-02");
+        assert_eq!(
+            status,
+            vec![
+                BudgetStatus {
+                    period: BudgetPeriod::Day,
+                    key: "2024-05-02".to_string(),
+                    spent: 2.5,
+                    limit: 10.0,
+                    level: BudgetLevel::Ok,
+                },
+                BudgetStatus {
+                    period: BudgetPeriod::Month,
+                    key: "2024-05".to_string(),
+                    spent: 3.5,
+                    limit: 100.0,
+                    level: BudgetLevel::Ok,
+                },
+            ]
+        );
+        // unset and zero budgets aren't checked
+        assert!(budget_status(&records, &budget(None, Some(0.0)), "2024-05-02").is_empty());
+    }
+
+    #[test]
+    fn budgets_warn_and_then_run_out() {
+        let level = |cost: f64| {
+            let records = vec![spent("2024-05-02", cost)];
+            budget_status(&records, &budget(Some(10.0), None), "2024-05-02")[0].level
+        };
+        assert_eq!(level(7.9), BudgetLevel::Ok);
+        assert_eq!(level(8.0), BudgetLevel::Warning);
+        assert_eq!(level(9.9), BudgetLevel::Warning);
+        assert_eq!(level(10.0), BudgetLevel::Exceeded);
+        assert_eq!(level(12.0), BudgetLevel::Exceeded);
+    }
+
+    #[test]
+    fn below_the_warning_nothing_is_reported() {
+        let today = local_day(Local::now().timestamp());
+        let store = store(vec![spent(&today, 1.0)]);
+        let config = budget(Some(10.0), Some(100.0));
+        assert!(store.new_warnings(&config).is_empty());
+        assert!(store.exceeded(&config).is_none());
+    }
+
+    #[test]
+    fn each_level_is_warned_once() {
+        let today = local_day(Local::now().timestamp());
+        let store = store(vec![spent(&today, 8.5)]);
+        let config = budget(Some(10.0), None);
+        let warnings = store.new_warnings(&config);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].level, BudgetLevel::Warning);
+        assert!(store.new_warnings(&config).is_empty());
+
+        // running out is warned about even after the warning
+        store.with_records(|records| records.push(spent(&today, 2.0)));
+        let warnings = store.new_warnings(&config);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].level, BudgetLevel::Exceeded);
+        assert!(store.new_warnings(&config).is_empty());
+    }
+
+    #[test]
+    fn spent_budgets_refuse_calls() {
+        let today = local_day(Local::now().timestamp());
+        let store = store(vec![spent(&today, 3.0), spent(&today, 2.0)]);
+        assert!(store.exceeded(&budget(Some(10.0), None)).is_none());
+
+        let exceeded = store.exceeded(&budget(Some(10.0), Some(5.0))).unwrap();
+        assert_eq!(exceeded.period, BudgetPeriod::Month);
+        assert_eq!(exceeded.key, month_of(&today));
+        assert_eq!(exceeded.spent, 5.0);
+    }
+}
@@ -3,13 +3,22 @@
   windows_subsystem = "windows"
 )]
 
+mod actions;
 mod easy_thing;
+mod http_client;
+mod llm;
 mod app_config;
 mod command;
+mod prompt_template;
+mod remote;
 mod select;
+mod shortcut;
 #[cfg(not(target_os = "macos"))]
 mod task;
 mod tauri_windows;
+#[cfg(not(target_os = "macos"))]
+mod trace;
+mod tray;
 mod utils;
 
 use app_config::AppConfig;
@@ -17,26 +26,53 @@ use once_cell::sync::OnceCell;
 use parking_lot::RwLock;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicIsize;
+use std::sync::atomic::AtomicU64;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::Duration;
 use tauri::AppHandle;
 use tauri::Manager;
 use tauri::WindowEvent;
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc::UnboundedSender;
 use tokio::task::JoinHandle;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
 pub static APP: OnceCell<AppHandle> = OnceCell::new();
 
+/// label of the chat window declared in `tauri.conf.json`
 pub const CHATBOX_MAIN_WINDOWS: &str = "chatbox_main_windows";
 pub struct AppState {
     pub selected_content: Arc<RwLock<String>>,
     pub foreground_handle: AtomicIsize,
+    /// window the current selection was made in
+    pub foreground_window: RwLock<easy_thing::foreground::WindowInfo>,
     runtime: Runtime,
-    pub auto_input_sender: OnceCell<UnboundedSender<String>>,
+    pub auto_input: OnceCell<easy_thing::auto_input::AutoInputQueue>,
+    /// model calls started from `command::chat_completion`
+    pub llm_requests: llm::LlmRequests,
+    /// rate limits of the llm profiles
+    pub llm_limiters: llm::limit::RateLimiters,
+    /// tokens and cost of every model call
+    pub llm_usage: llm::usage::UsageStore,
+    /// answers of selection actions, see `llm::cache`
+    pub response_cache: llm::cache::ResponseCache,
     pub screen_size: (f64, f64), // (width, height)
     pub enable_select: AtomicBool,
+    /// the popup stays off until then, set from the tray
+    pub select_paused_until: RwLock<Option<chrono::DateTime<chrono::Local>>>,
+    pub select_pause_generation: AtomicU64,
+    /// latest selections first, shown in the tray
+    pub recent_selections: RwLock<VecDeque<String>>,
+    pub last_answer: RwLock<String>,
+    pub actions: RwLock<Vec<actions::SelectAction>>,
+    /// question the quick answer window asks when it opens
+    pub quick_answer_question: RwLock<tauri_windows::quick_answer::QuickAnswerQuestion>,
+    /// last known geometry of each managed window, by label
+    pub window_geometry: RwLock<HashMap<String, tauri_windows::geometry::WindowGeometry>>,
+    pub window_geometry_generation: AtomicU64,
+    /// outcome of the last shortcut registration
+    pub shortcut_report: RwLock<shortcut::ShortcutReport>,
 }
 
 impl AppState {
@@ -44,13 +80,33 @@ impl AppState {
         Self {
             selected_content: Arc::new(RwLock::new(String::new())),
             foreground_handle: AtomicIsize::new(0),
+            foreground_window: RwLock::new(Default::default()),
             runtime,
-            auto_input_sender: OnceCell::new(),
+            auto_input: OnceCell::new(),
+            llm_requests: Default::default(),
+            llm_limiters: Default::default(),
+            llm_usage: Default::default(),
+            response_cache: Default::default(),
             screen_size,
             enable_select: AtomicBool::new(app_config.enable_select.unwrap_or(true)),
+            select_paused_until: RwLock::new(None),
+            select_pause_generation: AtomicU64::new(0),
+            recent_selections: RwLock::new(VecDeque::new()),
+            last_answer: RwLock::new(String::new()),
+            actions: RwLock::new(actions::load_actions()),
+            quick_answer_question: RwLock::new(Default::default()),
+            window_geometry: RwLock::new(tauri_windows::geometry::load_geometry()),
+            window_geometry_generation: AtomicU64::new(0),
+            shortcut_report: RwLock::new(Default::default()),
         }
     }
 
+    /// whether the popup should appear on a new selection
+    pub fn is_select_active(&self) -> bool {
+        let paused = matches!(*self.select_paused_until.read(), Some(until) if until > chrono::Local::now());
+        self.enable_select.load(std::sync::atomic::Ordering::SeqCst) && !paused
+    }
+
     pub fn spawn_future<F>(&self, future: F) -> JoinHandle<F::Output>
     where
         F: std::future::Future + Send + 'static,
@@ -59,11 +115,22 @@ impl AppState {
         self.runtime.spawn(future)
     }
 
+    /// run `future` on a thread of its own, it may block between its awaits
+    pub fn spawn_blocking_future<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let runtime = self.runtime.handle().clone();
+        self.runtime.spawn_blocking(move || runtime.block_on(future))
+    }
+
+    /// run `task` on a thread that may block, e.g. to sleep or press keys
     pub fn spawn_task<F>(&self, task: F) -> JoinHandle<()>
     where
         F: Send + 'static + FnOnce() -> (),
     {
-        self.runtime.spawn(async move { task() })
+        self.runtime.spawn_blocking(task)
     }
 
     pub fn spawn_delay_task<F>(&self, future: F, delay_time: Duration) -> JoinHandle<F::Output>
@@ -95,7 +162,30 @@ fn main() {
       command::get_selected_content,
       command::set_size,
       command::run_auto_input,
+      command::begin_auto_input,
       command::send_auto_input_value,
+      command::end_auto_input,
+      command::list_auto_input_jobs,
+      command::pause_auto_input_job,
+      command::resume_auto_input_job,
+      command::cancel_auto_input_job,
+      command::cancel_auto_input,
+      command::resume_auto_input,
+      command::undo_last_insertion,
+      command::chat_completion,
+      command::cancel_chat_completion,
+      command::list_llm_profiles,
+      command::get_response_cache_stats,
+      command::list_cached_responses,
+      command::clear_response_cache,
+      command::query_llm_usage,
+      command::get_llm_budget_status,
+      command::get_llm_pricing,
+      command::count_tokens,
+      command::build_chat_context,
+      command::check_need_update,
+      command::get_sponsor_ad,
+      command::list_sponsor_about_banner,
       command::run_quick_answer,
       command::run_chat_mode,
       command::close_window,
@@ -106,10 +196,23 @@ fn main() {
       command::get_selected_content_from_cache,
       command::hide_select_window,
       command::trigger_select_click,
+      command::replay_select_trace,
+      command::list_actions,
+      command::create_action,
+      command::update_action,
+      command::delete_action,
+      command::reorder_actions,
+      command::validate_prompt_template,
+      command::get_quick_answer_question,
+      command::insert_quick_answer,
+      command::replace_selection,
+      command::open_quick_answer_in_chat,
+      command::toggle_window,
+      command::get_shortcut_report,
+      command::remember_answer,
   ]);
 
   builder
-  //.with_label(CHATBOX_MAIN_WINDOWS) // 添加标签到主窗口
   .setup(move |app| {
       tracing::info!(start = true);
       APP.get_or_init(|| app.handle());
@@ -119,20 +222,35 @@ fn main() {
           tokio::runtime::Runtime::new().expect("build tokio runtime error"),
           screen_size,
       ));
+      if let Some(window) = app_handle.get_window(CHATBOX_MAIN_WINDOWS) {
+          if let Err(err) = tauri_windows::geometry::restore(&app_handle, &window) {
+              tracing::warn!(restore_window_error = ?err);
+          }
+      }
 
       // 注册全局快捷键
-      //let _ = shortcut::ShortcutRegister::register_shortcut(&app_handle);
+      match shortcut::ShortcutRegister::register_shortcut(&app_handle) {
+          Ok(report) => {
+              let state: tauri::State<AppState> = app_handle.state();
+              *state.shortcut_report.write() = report;
+          }
+          Err(err) => tracing::warn!(register_shortcut_error = ?err),
+      }
       #[cfg(not(target_os = "macos"))]
       task::register_task(&app_handle);
+      tray::refresh_tray_menu(&app_handle);
       Ok(())
   })
-  //.system_tray(system_tray())
-  //.on_system_tray_event(handle_click_system_tray)
+  .system_tray(tray::system_tray())
+  .on_system_tray_event(tray::handle_click_system_tray)
   .build(context)
   .expect("error while running tauri application")
   //.plugin(tauri_plugin_store::Builder::default().build())
   .run(|app_handle, event| match event {
       tauri::RunEvent::WindowEvent { label, event, .. } => {
+          if let WindowEvent::Moved(_) | WindowEvent::Resized(_) = event {
+              crate::tauri_windows::geometry::remember(app_handle, &label);
+          }
           if label == crate::tauri_windows::SELECT_WINDOWS
               //|| label == crate::tauri_windows::search::SEARCH_WINDOWS
           {
@@ -146,6 +264,11 @@ fn main() {
               }
           }
       }
+      tauri::RunEvent::Exit => {
+          let state: tauri::State<AppState> = app_handle.state();
+          state.response_cache.flush();
+          crate::tauri_windows::geometry::flush(app_handle);
+      }
       _ => {}
   });
 
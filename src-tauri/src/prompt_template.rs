@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Variables a prompt template can use.
+///
+/// - `selection`: the selected text
+/// - `selection_markdown`: the selected text as a Markdown quote
+/// - `app_name`: the application the text was selected in
+/// - `window_title`: title of that application's window
+/// - `ui_language`: language of the app, e.g. `zh-cn`
+/// - `clipboard`: current clipboard text
+/// - `date`: today's date, `YYYY-MM-DD`
+pub const VARIABLES: &[&str] = &[
+    "selection",
+    "selection_markdown",
+    "app_name",
+    "window_title",
+    "ui_language",
+    "clipboard",
+    "date",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable {
+        name: String,
+        default: Option<String>,
+    },
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    UnknownVariable { name: String, position: usize },
+    UnclosedTag { position: usize },
+    UnclosedIf { name: String, position: usize },
+    UnexpectedTag { tag: String, position: usize },
+    EmptyTag { position: usize },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownVariable { name, position } => write!(
+                f,
+                "unknown variable `{}` at {}, available variables: {}",
+                name,
+                position,
+                VARIABLES.join(", ")
+            ),
+            TemplateError::UnclosedTag { position } => {
+                write!(f, "`{{{{` at {} is never closed with `}}}}`", position)
+            }
+            TemplateError::UnclosedIf { name, position } => {
+                write!(
+                    f,
+                    "`{{{{#if {}}}}}` at {} has no `{{{{/if}}}}`",
+                    name, position
+                )
+            }
+            TemplateError::UnexpectedTag { tag, position } => {
+                write!(f, "unexpected `{{{{{}}}}}` at {}", tag, position)
+            }
+            TemplateError::EmptyTag { position } => write!(f, "empty tag at {}", position),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// A parsed prompt template.
+///
+/// `{{name}}` inserts a variable, `{{name|fallback}}` uses `fallback` when the
+/// variable is empty, and `{{#if name}}..{{else}}..{{/if}}` keeps a block only
+/// when the variable is not empty. `\{{` is a literal `{{`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+struct Frame {
+    name: String,
+    position: usize,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Frame {
+    fn nodes(&mut self) -> &mut Vec<Node> {
+        match self.otherwise.as_mut() {
+            Some(otherwise) => otherwise,
+            None => &mut self.then,
+        }
+    }
+}
+
+fn check_variable(name: &str, position: usize) -> Result<(), TemplateError> {
+    if VARIABLES.contains(&name) {
+        Ok(())
+    } else {
+        Err(TemplateError::UnknownVariable {
+            name: name.to_string(),
+            position,
+        })
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut root = Vec::new();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut rest = source;
+        // position in characters, which is what the settings editor shows
+        let mut position = 0;
+
+        fn current<'a>(root: &'a mut Vec<Node>, stack: &'a mut [Frame]) -> &'a mut Vec<Node> {
+            match stack.last_mut() {
+                Some(frame) => frame.nodes(),
+                None => root,
+            }
+        }
+
+        while let Some(start) = rest.find("{{") {
+            if let Some(text) = rest[..start].strip_suffix('\\') {
+                current(&mut root, &mut stack).push(Node::Text(format!("{}{{{{", text)));
+                position += rest[..start].chars().count() + 2;
+                rest = &rest[start + 2..];
+                continue;
+            }
+            if start > 0 {
+                current(&mut root, &mut stack).push(Node::Text(rest[..start].to_string()));
+            }
+            position += rest[..start].chars().count();
+            let tag_start = position;
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or(TemplateError::UnclosedTag {
+                position: tag_start,
+            })?;
+            let tag = after[..end].trim();
+            position += 2 + after[..end].chars().count() + 2;
+            rest = &after[end + 2..];
+
+            if tag.is_empty() {
+                return Err(TemplateError::EmptyTag {
+                    position: tag_start,
+                });
+            } else if let Some(name) = tag.strip_prefix("#if ") {
+                let name = name.trim();
+                check_variable(name, tag_start)?;
+                stack.push(Frame {
+                    name: name.to_string(),
+                    position: tag_start,
+                    then: Vec::new(),
+                    otherwise: None,
+                });
+            } else if tag == "else" {
+                match stack.last_mut() {
+                    Some(frame) if frame.otherwise.is_none() => frame.otherwise = Some(Vec::new()),
+                    _ => {
+                        return Err(TemplateError::UnexpectedTag {
+                            tag: tag.to_string(),
+                            position: tag_start,
+                        })
+                    }
+                }
+            } else if tag == "/if" {
+                let frame = stack.pop().ok_or_else(|| TemplateError::UnexpectedTag {
+                    tag: tag.to_string(),
+                    position: tag_start,
+                })?;
+                let node = Node::If {
+                    name: frame.name,
+                    then: frame.then,
+                    otherwise: frame.otherwise.unwrap_or_default(),
+                };
+                current(&mut root, &mut stack).push(node);
+            } else if tag.starts_with('#') || tag.starts_with('/') {
+                return Err(TemplateError::UnexpectedTag {
+                    tag: tag.to_string(),
+                    position: tag_start,
+                });
+            } else {
+                let (name, default) = match tag.split_once('|') {
+                    Some((name, default)) => (name.trim(), Some(default.trim().to_string())),
+                    None => (tag, None),
+                };
+                check_variable(name, tag_start)?;
+                current(&mut root, &mut stack).push(Node::Variable {
+                    name: name.to_string(),
+                    default,
+                });
+            }
+        }
+        if !rest.is_empty() {
+            current(&mut root, &mut stack).push(Node::Text(rest.to_string()));
+        }
+        if let Some(frame) = stack.pop() {
+            return Err(TemplateError::UnclosedIf {
+                name: frame.name,
+                position: frame.position,
+            });
+        }
+        Ok(Self { nodes: root })
+    }
+
+    /// whether the template places the selection itself
+    pub fn uses_selection(&self) -> bool {
+        fn walk(nodes: &[Node]) -> bool {
+            nodes.iter().any(|node| match node {
+                Node::Text(_) => false,
+                Node::Variable { name, .. } => name == "selection" || name == "selection_markdown",
+                Node::If {
+                    then, otherwise, ..
+                } => walk(then) || walk(otherwise),
+            })
+        }
+        walk(&self.nodes)
+    }
+
+    pub fn render(&self, context: &TemplateContext) -> String {
+        fn walk(nodes: &[Node], context: &TemplateContext, output: &mut String) {
+            for node in nodes {
+                match node {
+                    Node::Text(text) => output.push_str(text),
+                    Node::Variable { name, default } => match context.get(name) {
+                        Some(value) => output.push_str(value),
+                        None => output.push_str(default.as_deref().unwrap_or_default()),
+                    },
+                    Node::If {
+                        name,
+                        then,
+                        otherwise,
+                    } => {
+                        if context.get(name).is_some() {
+                            walk(then, context, output)
+                        } else {
+                            walk(otherwise, context, output)
+                        }
+                    }
+                }
+            }
+        }
+        let mut output = String::new();
+        walk(&self.nodes, context, &mut output);
+        output
+    }
+}
+
+/// values for the template variables, empty values count as missing
+#[derive(Debug, Default, Clone)]
+pub struct TemplateContext {
+    values: HashMap<String, String>,
+}
+
+impl TemplateContext {
+    pub fn new(selection: &str) -> Self {
+        let selection_markdown = selection
+            .lines()
+            .map(|line| format!("> {}", line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self::default()
+            .with("selection", selection)
+            .with("selection_markdown", selection_markdown)
+            .with("date", chrono::Local::now().format("%Y-%m-%d").to_string())
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.values.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .get(name)
+            .map(|value| value.as_str())
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    /// everything known about the current selection and the app
+    pub fn capture(handle: &tauri::AppHandle, selection: &str) -> Self {
+        use clipboard::ClipboardProvider;
+        use tauri::Manager;
+
+        let state: tauri::State<crate::AppState> = handle.state();
+        let window = state.foreground_window.read().clone();
+        let app_config = crate::app_config::get_app_config().unwrap_or_default();
+        let clipboard = clipboard::ClipboardContext::new()
+            .and_then(|mut context| context.get_contents())
+            .unwrap_or_default();
+        Self::new(selection)
+            .with("app_name", window.app_name.unwrap_or_default())
+            .with("window_title", window.title.unwrap_or_default())
+            .with("ui_language", app_config.language)
+            .with("clipboard", clipboard)
+    }
+}
+
+/// Render an action prompt. Prompts written before templating don't mention the
+/// selection, it is appended to them like it always was.
+pub fn render_prompt(prompt: &str, context: &TemplateContext) -> Result<String, TemplateError> {
+    let template = Template::parse(prompt)?;
+    let rendered = template.render(context);
+    if template.uses_selection() {
+        Ok(rendered)
+    } else {
+        Ok(format!(
+            "{} {}",
+            rendered,
+            context.get("selection").unwrap_or_default()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: &TemplateContext) -> String {
+        Template::parse(source).unwrap().render(context)
+    }
+
+    #[test]
+    fn inserts_variables() {
+        let context = TemplateContext::new("hello").with("app_name", "Editor");
+        assert_eq!(
+            render("Fix {{selection}} from {{ app_name }}", &context),
+            "Fix hello from Editor"
+        );
+        assert_eq!(
+            render("{{selection_markdown}}", &TemplateContext::new("a\nb")),
+            "> a\n> b"
+        );
+    }
+
+    #[test]
+    fn uses_the_default_of_an_empty_variable() {
+        let context = TemplateContext::new("hello").with("ui_language", "  ");
+        assert_eq!(render("in {{ui_language|English}}", &context), "in English");
+        assert_eq!(render("{{app_name|}}!", &context), "!");
+        let context = context.with("ui_language", "zh-cn");
+        assert_eq!(render("in {{ui_language|English}}", &context), "in zh-cn");
+    }
+
+    #[test]
+    fn keeps_the_branch_of_an_if() {
+        let source = "{{#if app_name}}from {{app_name}}{{else}}from somewhere{{/if}}.";
+        let context = TemplateContext::new("hello");
+        assert_eq!(render(source, &context), "from somewhere.");
+        assert_eq!(
+            render(source, &context.clone().with("app_name", "Editor")),
+            "from Editor."
+        );
+        assert_eq!(
+            render(
+                "{{#if clipboard}}nested {{#if date}}{{/if}}{{/if}}x",
+                &context
+            ),
+            "x"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_variables_and_broken_tags() {
+        assert_eq!(
+            Template::parse("ab {{selected}}"),
+            Err(TemplateError::UnknownVariable {
+                name: "selected".to_string(),
+                position: 3,
+            })
+        );
+        assert_eq!(
+            Template::parse("{{#if title}}{{/if}}"),
+            Err(TemplateError::UnknownVariable {
+                name: "title".to_string(),
+                position: 0,
+            })
+        );
+        assert_eq!(
+            Template::parse("{{#if date}}x"),
+            Err(TemplateError::UnclosedIf {
+                name: "date".to_string(),
+                position: 0,
+            })
+        );
+        assert_eq!(
+            Template::parse("x {{date"),
+            Err(TemplateError::UnclosedTag { position: 2 })
+        );
+        assert_eq!(
+            Template::parse("{{else}}"),
+            Err(TemplateError::UnexpectedTag {
+                tag: "else".to_string(),
+                position: 0,
+            })
+        );
+        assert_eq!(
+            Template::parse("{{ }}"),
+            Err(TemplateError::EmptyTag { position: 0 })
+        );
+    }
+
+    #[test]
+    fn escapes_a_literal_open_tag() {
+        let context = TemplateContext::new("hello");
+        assert_eq!(
+            render(r"\{{selection}} is {{selection}}", &context),
+            "{{selection}} is hello"
+        );
+        // positions still count the escaped source
+        assert_eq!(
+            Template::parse(r"\{{}} {{nope}}"),
+            Err(TemplateError::UnknownVariable {
+                name: "nope".to_string(),
+                position: 6,
+            })
+        );
+    }
+
+    #[test]
+    fn appends_the_selection_to_prompts_without_it() {
+        let context = TemplateContext::new("hello");
+        assert_eq!(
+            render_prompt("Translate into English:", &context).unwrap(),
+            "Translate into English: hello"
+        );
+        assert_eq!(
+            render_prompt("Translate {{selection}} please", &context).unwrap(),
+            "Translate hello please"
+        );
+        assert_eq!(
+            render_prompt("{{#if app_name}}{{selection_markdown}}{{/if}}", &context).unwrap(),
+            ""
+        );
+        assert!(render_prompt("{{nope}}", &context).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+const RELEASE_HOST: &str = "https://releases.chatboxai.app";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NeedUpdatePayload {
+    pub version: String,
+    pub os: String,
+    pub uuid: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct NeedUpdateResponse {
+    #[serde(default)]
+    need_update: bool,
+}
+
+/// asks the release server, through the proxy of the app config
+pub async fn check_need_update(payload: NeedUpdatePayload) -> Result<bool> {
+    let response: NeedUpdateResponse = crate::http_client::client()?
+        .post(format!(
+            "{}/chatbox_need_update/{}",
+            RELEASE_HOST, payload.version
+        ))
+        .json(&serde_json::json!({
+            "uuid": payload.uuid,
+            "os": payload.os,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.need_update)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SponsorAd {
+    pub text: String,
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorAboutBanner {
+    /// `picture` or `picture-text`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+    pub picture_url: String,
+    pub link: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SponsorResponse<T> {
+    /// missing when nobody sponsors
+    data: Option<T>,
+}
+
+/// the `data` of a sponsor endpoint, through the proxy of the app config
+async fn get_sponsor<T: serde::de::DeserializeOwned>(path: &str) -> Result<Option<T>> {
+    let response: SponsorResponse<T> = crate::http_client::client()?
+        .get(format!("{}/{}", RELEASE_HOST, path))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.data)
+}
+
+pub async fn get_sponsor_ad() -> Result<Option<SponsorAd>> {
+    get_sponsor("sponsor_ad").await
+}
+
+pub async fn list_sponsor_about_banner() -> Result<Vec<SponsorAboutBanner>> {
+    Ok(get_sponsor("sponsor_about_banner")
+        .await?
+        .unwrap_or_default())
+}
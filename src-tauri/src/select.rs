@@ -1,6 +1,48 @@
 use anyhow::{anyhow, Result};
 use clipboard::ClipboardContext;
 use clipboard::ClipboardProvider;
+use serde::{Deserialize, Serialize};
+
+/// screen rectangle of a selection, in physical pixels
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SelectionRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl SelectionRect {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    /// how far the point is outside the rectangle, 0 when inside
+    fn distance(&self, x: f64, y: f64) -> f64 {
+        let dx = (self.x - x).max(x - (self.x + self.width)).max(0.0);
+        let dy = (self.y - y).max(y - (self.y + self.height)).max(0.0);
+        dx.hypot(dy)
+    }
+}
+
+/// the screen showing the point, or the nearest one when it is off screen
+pub fn screen_at(screens: &[SelectionRect], x: f64, y: f64) -> Option<&SelectionRect> {
+    screens
+        .iter()
+        .find(|screen| screen.contains(x, y))
+        .or_else(|| {
+            screens
+                .iter()
+                .min_by(|a, b| a.distance(x, y).total_cmp(&b.distance(x, y)))
+        })
+}
+
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub text: String,
+    /// only known when the selection was read through the accessibility api
+    pub bounds: Option<SelectionRect>,
+}
 
 #[cfg(target_os = "windows")]
 pub fn copy() {
@@ -70,6 +112,19 @@ pub fn get_selected_text() -> Result<String> {
     }
 }
 
+/// read the selection through the accessibility api, falling back to the
+/// clipboard round-trip for applications that aren't accessible
+#[cfg(not(target_os = "macos"))]
+pub fn get_selection() -> Result<Selection> {
+    match crate::easy_thing::accessibility::PlatformAccessibility::get_selection() {
+        Ok(selection) => Ok(selection),
+        Err(err) => {
+            tracing::debug!(accessibility_selection_error = ?err);
+            get_selected_text().map(|text| Selection { text, bounds: None })
+        }
+    }
+}
+
 #[cfg(target_os = "windows")]
 pub fn paste() {
     println!("select.rs paste");
@@ -98,17 +153,23 @@ pub fn paste() {
     enigo.key_up(Key::Control);
 }
 
+/// Paste `text` into the focused window through the clipboard, then put the
+/// user's clipboard back. Clipboard content that isn't text can't be restored
+/// and is left replaced by `text`.
 pub fn copy_and_paste(text: String) -> Result<()> {
     println!("select.rs copy_and_paste: {}", text);
     let mut cli_pboard: ClipboardContext =
         ClipboardProvider::new().map_err(|_err| anyhow!("get clipboard error"))?;
-    let old_text = cli_pboard
-        .get_contents()
-        .map_err(|_err| anyhow!("get clipboard content error"))?;
-    if let Ok(_) = cli_pboard.set_contents(text) {
-        std::thread::sleep(std::time::Duration::from_millis(30));
-        paste();
-        // 将文本粘贴到当前焦点窗口中
+    let old_text = cli_pboard.get_contents().ok();
+    cli_pboard
+        .set_contents(text)
+        .map_err(|err| anyhow!("set clipboard error {}", err))?;
+    std::thread::sleep(std::time::Duration::from_millis(30));
+    // 将文本粘贴到当前焦点窗口中
+    paste();
+    if let Some(old_text) = old_text {
+        // the app reads the clipboard after it handles the key press
+        std::thread::sleep(std::time::Duration::from_millis(200));
         cli_pboard
             .set_contents(old_text)
             .map_err(|_err| anyhow!("set old clipboard error"))?;
@@ -116,6 +177,27 @@ pub fn copy_and_paste(text: String) -> Result<()> {
     Ok(())
 }
 
+/// Paste `html` with `text` as its plain text flavor, apps pick the one they
+/// understand. The user's clipboard text is put back afterwards.
+pub fn copy_and_paste_html(html: String, text: String) -> Result<()> {
+    tracing::info!(copy_and_paste_html = html.len());
+    let mut clipboard =
+        arboard::Clipboard::new().map_err(|err| anyhow!("get clipboard error {}", err))?;
+    let old_text = clipboard.get_text().ok();
+    clipboard
+        .set_html(html, Some(text))
+        .map_err(|err| anyhow!("set clipboard html error {}", err))?;
+    std::thread::sleep(std::time::Duration::from_millis(30));
+    paste();
+    if let Some(old_text) = old_text {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        clipboard
+            .set_text(old_text)
+            .map_err(|_err| anyhow!("set old clipboard error"))?;
+    }
+    Ok(())
+}
+
 pub fn copy_content(content: String) -> Result<()> {
     print!("select.rs copy_content: {}", content);
     let mut cli_pboard: ClipboardContext =
@@ -147,3 +229,21 @@ pub fn press_enter() {
     let mut enigo = Enigo::new();
     enigo.key_click(Key::Return);
 }
+
+pub fn press_backspace() {
+    use enigo::*;
+    let mut enigo = Enigo::new();
+    enigo.key_click(Key::Backspace);
+}
+
+pub fn press_undo() {
+    use enigo::*;
+    #[cfg(target_os = "macos")]
+    let modifier = Key::Meta;
+    #[cfg(not(target_os = "macos"))]
+    let modifier = Key::Control;
+    let mut enigo = Enigo::new();
+    enigo.key_down(modifier);
+    enigo.key_click(Key::Layout('z'));
+    enigo.key_up(modifier);
+}
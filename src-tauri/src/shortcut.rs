@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, GlobalShortcutManager, Manager};
+
+use crate::app_config::AppConfig;
+use crate::tauri_windows::manager::{WindowKind, WindowManager};
+use crate::AppState;
+
+/// give the user time to let go of the hotkey before we press Ctrl+C ourselves
+const RELEASE_DELAY: Duration = Duration::from_millis(200);
+
+/// bind a hotkey to an action of the registry
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutBinding {
+    pub shortcut: String,
+    pub action_id: String,
+}
+
+#[derive(Debug, Clone)]
+enum ShortcutCommand {
+    QuickAsk,
+    Search,
+    Chat,
+    UndoInsertion,
+    Action(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutFailure {
+    /// config field or action the shortcut belongs to
+    pub name: String,
+    pub shortcut: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutReport {
+    pub registered: Vec<String>,
+    pub failures: Vec<ShortcutFailure>,
+}
+
+/// sorted modifiers and the key of an accelerator, `None` if it has no key
+/// or more than one
+fn parse_accelerator(accelerator: &str) -> Option<(Vec<&'static str>, String)> {
+    let mut modifiers = Vec::new();
+    let mut key = None;
+    for part in accelerator.split('+') {
+        let part = part.trim().to_lowercase();
+        let modifier = match part.as_str() {
+            "" => return None,
+            "commandorcontrol" | "commandorctrl" | "cmdorctrl" | "cmdorcontrol" => {
+                if cfg!(target_os = "macos") {
+                    "super"
+                } else {
+                    "ctrl"
+                }
+            }
+            "control" | "ctrl" => "ctrl",
+            "command" | "cmd" | "super" | "meta" => "super",
+            "option" | "alt" => "alt",
+            "shift" => "shift",
+            _ => {
+                if key.is_some() {
+                    return None;
+                }
+                key = Some(part);
+                continue;
+            }
+        };
+        if !modifiers.contains(&modifier) {
+            modifiers.push(modifier);
+        }
+    }
+    modifiers.sort_unstable();
+    Some((modifiers, key?))
+}
+
+/// A global shortcut takes its keys away from every other app, so a key that
+/// types text needs Ctrl, Alt or Cmd; with Shift alone no one could type a
+/// capital letter any more. Function keys may stand alone.
+fn takes_typing_keys(modifiers: &[&str], key: &str) -> bool {
+    let is_function_key = key
+        .strip_prefix('f')
+        .and_then(|number| number.parse::<u8>().ok())
+        .map(|number| (1..=24).contains(&number))
+        .unwrap_or(false);
+    !is_function_key && modifiers.iter().all(|modifier| *modifier == "shift")
+}
+
+/// Canonical form of an accelerator so `CmdOrCtrl+Shift+T` and `shift+ctrl+t`
+/// are seen as the same key. `None` if it has no key, only modifiers or would
+/// take a key used for typing, see `takes_typing_keys`.
+pub fn normalize_accelerator(accelerator: &str) -> Option<String> {
+    let (modifiers, key) = parse_accelerator(accelerator)?;
+    if takes_typing_keys(&modifiers, &key) {
+        return None;
+    }
+    let mut parts: Vec<String> = modifiers.into_iter().map(String::from).collect();
+    parts.push(key);
+    Some(parts.join("+"))
+}
+
+fn configured_shortcuts(
+    app_config: &AppConfig,
+    handle: &AppHandle,
+) -> (Vec<(String, String, ShortcutCommand)>, Vec<ShortcutFailure>) {
+    let mut shortcuts = Vec::new();
+    let mut failures = Vec::new();
+    let builtins = [
+        (
+            "quickAskShortcut",
+            &app_config.quick_ask_shortcut,
+            ShortcutCommand::QuickAsk,
+        ),
+        (
+            "searchShortcut",
+            &app_config.search_shortcut,
+            ShortcutCommand::Search,
+        ),
+        (
+            "chatShortcut",
+            &app_config.chat_shortcut,
+            ShortcutCommand::Chat,
+        ),
+        (
+            "undoInsertionShortcut",
+            &app_config.undo_insertion_shortcut,
+            ShortcutCommand::UndoInsertion,
+        ),
+    ];
+    for (name, shortcut, command) in builtins {
+        if let Some(shortcut) = shortcut
+            .as_ref()
+            .filter(|shortcut| !shortcut.trim().is_empty())
+        {
+            shortcuts.push((name.to_string(), shortcut.clone(), command));
+        }
+    }
+    for binding in app_config.shortcut_bindings.iter().flatten() {
+        let name = format!("action:{}", binding.action_id);
+        match crate::actions::find_action(handle, &binding.action_id) {
+            Some(action) if action.enabled => shortcuts.push((
+                name,
+                binding.shortcut.clone(),
+                ShortcutCommand::Action(action.id),
+            )),
+            Some(_) => {}
+            None => failures.push(ShortcutFailure {
+                name,
+                shortcut: binding.shortcut.clone(),
+                reason: format!("not found action {}", binding.action_id),
+            }),
+        }
+    }
+    (shortcuts, failures)
+}
+
+/// Drop shortcuts that are malformed or bound twice. When two entries use the
+/// same keys both are reported and neither is registered.
+fn check_conflicts(
+    shortcuts: Vec<(String, String, ShortcutCommand)>,
+    failures: &mut Vec<ShortcutFailure>,
+) -> Vec<(String, String, ShortcutCommand)> {
+    let mut by_keys: HashMap<String, Vec<(String, String, ShortcutCommand)>> = HashMap::new();
+    let mut order = Vec::new();
+    for (name, shortcut, command) in shortcuts {
+        match normalize_accelerator(&shortcut) {
+            Some(keys) => {
+                if !by_keys.contains_key(&keys) {
+                    order.push(keys.clone());
+                }
+                by_keys
+                    .entry(keys)
+                    .or_default()
+                    .push((name, shortcut, command));
+            }
+            None => {
+                let reason = match parse_accelerator(&shortcut) {
+                    Some(_) => "needs Ctrl, Alt or Cmd, it would take the key from every app",
+                    None => "invalid shortcut",
+                };
+                failures.push(ShortcutFailure {
+                    name,
+                    shortcut,
+                    reason: reason.to_string(),
+                })
+            }
+        }
+    }
+    let mut checked = Vec::new();
+    for keys in order {
+        let mut entries = by_keys.remove(&keys).unwrap_or_default();
+        if entries.len() == 1 {
+            checked.append(&mut entries);
+            continue;
+        }
+        let names: Vec<String> = entries.iter().map(|(name, ..)| name.clone()).collect();
+        for (name, shortcut, _) in entries {
+            failures.push(ShortcutFailure {
+                reason: format!("conflicts with {}", names.join(", ")),
+                name,
+                shortcut,
+            });
+        }
+    }
+    checked
+}
+
+fn run_command(handle: &AppHandle, command: &ShortcutCommand) -> anyhow::Result<()> {
+    tracing::info!(shortcut_command =? command);
+    match command {
+        ShortcutCommand::Search => WindowManager::toggle(handle, WindowKind::Search),
+        ShortcutCommand::Chat => WindowManager::toggle(handle, WindowKind::Chat),
+        ShortcutCommand::QuickAsk => {
+            let selection = capture_selection(handle);
+            if selection.is_empty() {
+                WindowManager::show(handle, WindowKind::Search).map(|_| ())
+            } else {
+                crate::tauri_windows::quick_answer::show_quick_answer_window(
+                    handle,
+                    Some(selection),
+                )
+            }
+        }
+        ShortcutCommand::UndoInsertion => {
+            // backspaces would arrive with the hotkey's modifiers still held
+            std::thread::sleep(RELEASE_DELAY);
+            crate::easy_thing::undo_last_insertion(handle).map(|_| ())
+        }
+        ShortcutCommand::Action(id) => run_action(handle, id),
+    }
+}
+
+/// selection in the app that had focus when the hotkey was pressed
+#[cfg(not(target_os = "macos"))]
+fn capture_selection(handle: &AppHandle) -> String {
+    std::thread::sleep(RELEASE_DELAY);
+    crate::tauri_windows::select::remember_foreground(handle);
+    let selection = crate::select::get_selection()
+        .map(|selection| selection.text.trim().to_string())
+        .unwrap_or_default();
+    let state: tauri::State<AppState> = handle.state();
+    if !selection.is_empty() {
+        *state.selected_content.write() = selection.clone();
+        crate::tray::remember_selection(handle, &selection);
+    }
+    selection
+}
+
+#[cfg(target_os = "macos")]
+fn capture_selection(_handle: &AppHandle) -> String {
+    std::thread::sleep(RELEASE_DELAY);
+    crate::select::selected_text()
+        .map(|text| text.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn run_action(handle: &AppHandle, id: &str) -> anyhow::Result<()> {
+    let selected = capture_selection(handle);
+    if selected.is_empty() {
+        return Err(anyhow::anyhow!("nothing selected for action {}", id));
+    }
+    crate::tauri_windows::select::click_select(
+        handle,
+        crate::tauri_windows::select::SelectPayload {
+            id: id.to_string(),
+            selected,
+        },
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn run_action(_handle: &AppHandle, id: &str) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!(
+        "action {} needs the select popup, which macOS doesn't have",
+        id
+    ))
+}
+
+pub struct ShortcutRegister;
+
+impl ShortcutRegister {
+    /// (Re)register every shortcut of the saved config. Shortcuts that can't be
+    /// registered are skipped and listed in the report.
+    pub fn register_shortcut(handle: &AppHandle) -> anyhow::Result<ShortcutReport> {
+        let app_config = crate::app_config::get_app_config()
+            .map_err(|err| anyhow::anyhow!("read app config error {}", err))?;
+        let mut manager = handle.global_shortcut_manager();
+        manager.unregister_all()?;
+
+        let (shortcuts, mut failures) = configured_shortcuts(&app_config, handle);
+        let shortcuts = check_conflicts(shortcuts, &mut failures);
+        let mut report = ShortcutReport::default();
+        for (name, shortcut, command) in shortcuts {
+            let command_handle = handle.clone();
+            let result = manager.register(&shortcut, move || {
+                let handle = command_handle.clone();
+                let command = command.clone();
+                let state: tauri::State<AppState> = command_handle.state();
+                // selecting presses keys and sleeps, keep it off the event loop
+                state.spawn_task(move || {
+                    if let Err(err) = run_command(&handle, &command) {
+                        tracing::warn!(shortcut_command_error = ?err);
+                    }
+                });
+            });
+            match result {
+                Ok(()) => report.registered.push(name),
+                Err(err) => failures.push(ShortcutFailure {
+                    name,
+                    shortcut,
+                    reason: err.to_string(),
+                }),
+            }
+        }
+        report.failures = failures;
+        tracing::info!(shortcut_report =? report);
+        if !report.failures.is_empty() {
+            notify_failures(handle, &report.failures);
+        }
+        Ok(report)
+    }
+}
+
+/// tell the user which hotkeys won't work and why, a log line goes unseen
+fn notify_failures(handle: &AppHandle, failures: &[ShortcutFailure]) {
+    let body = failures
+        .iter()
+        .map(|failure| {
+            format!(
+                "{} ({}): {}",
+                failure.shortcut, failure.name, failure.reason
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let identifier = handle.config().tauri.bundle.identifier.clone();
+    let result = tauri::api::notification::Notification::new(&identifier)
+        .title("快捷键未注册")
+        .body(body)
+        .show();
+    if let Err(err) = result {
+        tracing::warn!(show_notification_error = ?err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_modifier_order_and_aliases() {
+        assert_eq!(
+            normalize_accelerator("Shift+Alt+Q"),
+            normalize_accelerator("alt + shift + q")
+        );
+        assert_eq!(
+            normalize_accelerator("Control+Shift+Space").as_deref(),
+            Some("ctrl+shift+space")
+        );
+        assert_eq!(normalize_accelerator("F5").as_deref(), Some("f5"));
+    }
+
+    #[test]
+    fn rejects_keys_that_type_text() {
+        assert_eq!(normalize_accelerator("Shift+Q"), None);
+        assert_eq!(normalize_accelerator("shift+c"), None);
+        assert_eq!(normalize_accelerator("Q"), None);
+        assert_eq!(normalize_accelerator("Shift+F30"), None);
+        assert!(normalize_accelerator("Shift+F2").is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_accelerators() {
+        assert_eq!(normalize_accelerator("Ctrl+Shift"), None);
+        assert_eq!(normalize_accelerator("Ctrl+A+B"), None);
+        assert_eq!(normalize_accelerator("Ctrl++A"), None);
+    }
+
+    #[test]
+    fn defaults_are_accepted() {
+        let app_config = AppConfig::default();
+        for shortcut in [
+            app_config.quick_ask_shortcut,
+            app_config.search_shortcut,
+            app_config.chat_shortcut,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            assert!(normalize_accelerator(&shortcut).is_some(), "{}", shortcut);
+        }
+    }
+}
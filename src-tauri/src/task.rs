@@ -1,8 +1,11 @@
-use std::sync::atomic::Ordering;
 use std::time::Instant;
 
+use crate::select::{Selection, SelectionRect};
+use crate::tauri_windows::select::SelectAnchor;
 use crate::tauri_windows::select::SELECT_WINDOWS_HEIGHT;
+use crate::tauri_windows::select::SELECT_WINDOWS_OFFSET;
 use crate::tauri_windows::select::SELECT_WINDOWS_WIDTH;
+use crate::trace::{TraceEvent, TraceRecorder};
 use crate::AppState;
 use anyhow::{anyhow, Result};
 use rdev::{listen, Button, Event, EventType};
@@ -14,33 +17,20 @@ pub fn register_task(handle: &AppHandle) {
     let state: tauri::State<AppState> = handle.state();
     let _ = state.spawn_task(|| {
         if let Ok((mouse_position_x, mouse_position_y)) = get_mouse_position() {
-            let mut select_listen = SelectListener {
-                last_mouse_position_x: mouse_position_x,
-                last_mouse_position_y: mouse_position_y,
-                last_press_mouse_time: Instant::now(),
-            };
+            let driver = LiveSelectDriver::new(TraceRecorder::from_env());
+            let mut select_listen = SelectListener::new(driver, mouse_position_x, mouse_position_y);
             if let Err(error) = listen(move |event: Event| match event.event_type {
                 EventType::ButtonPress(Button::Left) => {
                     if let Ok((mouse_position_x, mouse_position_y)) = get_mouse_position() {
-                        select_listen.last_mouse_position_x = mouse_position_x;
-                        select_listen.last_mouse_position_y = mouse_position_y;
+                        select_listen.on_press(mouse_position_x, mouse_position_y);
                     }
                 }
                 EventType::ButtonRelease(Button::Left) => {
                     if let Ok((current_mouse_position_x, current_mouse_position_y)) =
                         get_mouse_position()
                     {
-                        if select_listen
-                            .should_select(current_mouse_position_x, current_mouse_position_y)
-                        {
-                            select_listen.select_content_and_show_tip(
-                                current_mouse_position_x as f64,
-                                current_mouse_position_y as f64,
-                            );
-                        }
-                        select_listen.last_press_mouse_time = Instant::now();
-                        select_listen.last_mouse_position_x = current_mouse_position_x;
-                        select_listen.last_mouse_position_y = current_mouse_position_y;
+                        select_listen
+                            .on_release(current_mouse_position_x, current_mouse_position_y);
                     }
                 }
                 _ => {}
@@ -53,9 +43,8 @@ pub fn register_task(handle: &AppHandle) {
     });
 }
 
-const MIN_DISTANCE_TIME: u128 = 100;
+const MIN_DISTANCE_TIME: u64 = 100;
 const MIN_DISTANCE_POSITION: f64 = 10.0;
-const WINDOWS_POSITION_OFFSET: f64 = 15.0;
 
 use mouse_position::mouse_position::Mouse;
 
@@ -67,66 +56,213 @@ fn get_mouse_position() -> Result<(f64, f64)> {
     }
 }
 
-struct SelectListener {
+/// Everything the selection logic needs from the outside world.
+///
+/// The live driver talks to the OS and the tauri windows, the replay driver in
+/// `crate::trace` answers from a recorded trace with a virtual clock.
+pub trait SelectDriver {
+    /// milliseconds since the driver started
+    fn now(&self) -> u64;
+    fn is_enable_select(&self) -> bool;
+    /// monitor bounds, see `SelectAnchor::window_position`
+    fn screens(&self) -> Vec<SelectionRect>;
+    fn capture_selection(&mut self) -> Result<Selection>;
+    fn show_tip(&mut self, content: &str, anchor: SelectAnchor);
+    /// called with every input the listener consumes, used for recording
+    fn observe(&mut self, _event: TraceEvent) {}
+}
+
+pub struct SelectListener<D: SelectDriver> {
+    driver: D,
     last_mouse_position_x: f64,
     last_mouse_position_y: f64,
-    last_press_mouse_time: Instant,
+    last_press_mouse_time: u64,
 }
 
-impl SelectListener {
-    fn should_select(&self, mouse_position_x: f64, mouse_position_y: f64) -> bool {
-        fn is_enable_select() -> bool {
-            if let Some(app_handle) = crate::APP.get() {
-                let state: State<AppState> = app_handle.state();
-                state.enable_select.load(Ordering::SeqCst)
-            } else {
-                false
-            }
+impl<D: SelectDriver> SelectListener<D> {
+    pub fn new(mut driver: D, mouse_position_x: f64, mouse_position_y: f64) -> Self {
+        let now = driver.now();
+        let screens = driver.screens();
+        driver.observe(TraceEvent::Start {
+            at_ms: now,
+            x: mouse_position_x,
+            y: mouse_position_y,
+            screens,
+        });
+        Self {
+            driver,
+            last_mouse_position_x: mouse_position_x,
+            last_mouse_position_y: mouse_position_y,
+            last_press_mouse_time: now,
         }
-        self.last_press_mouse_time.elapsed().as_millis() >= MIN_DISTANCE_TIME
+    }
+
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+
+    pub fn driver_mut(&mut self) -> &mut D {
+        &mut self.driver
+    }
+
+    pub fn on_press(&mut self, mouse_position_x: f64, mouse_position_y: f64) {
+        let now = self.driver.now();
+        self.driver.observe(TraceEvent::Press {
+            at_ms: now,
+            x: mouse_position_x,
+            y: mouse_position_y,
+        });
+        self.last_mouse_position_x = mouse_position_x;
+        self.last_mouse_position_y = mouse_position_y;
+    }
+
+    pub fn on_release(&mut self, mouse_position_x: f64, mouse_position_y: f64) {
+        let now = self.driver.now();
+        let enable_select = self.driver.is_enable_select();
+        self.driver.observe(TraceEvent::Release {
+            at_ms: now,
+            x: mouse_position_x,
+            y: mouse_position_y,
+            enable_select,
+        });
+        if enable_select && self.should_select(now, mouse_position_x, mouse_position_y) {
+            self.select_content_and_show_tip(now, mouse_position_x, mouse_position_y);
+        }
+        self.last_press_mouse_time = now;
+        self.last_mouse_position_x = mouse_position_x;
+        self.last_mouse_position_y = mouse_position_y;
+    }
+
+    fn should_select(&self, now: u64, mouse_position_x: f64, mouse_position_y: f64) -> bool {
+        now.saturating_sub(self.last_press_mouse_time) >= MIN_DISTANCE_TIME
             && ((mouse_position_x - self.last_mouse_position_x).abs() >= MIN_DISTANCE_POSITION
                 || (mouse_position_y - self.last_mouse_position_y).abs() >= MIN_DISTANCE_POSITION)
-            && is_enable_select()
     }
 
-    fn select_content_and_show_tip(&self, mouse_position_x: f64, mouse_position_y: f64) {
-        if let Ok(selected_content) = crate::select::get_selected_text() {
-            let trimed_selected_content = selected_content.trim();
+    fn select_content_and_show_tip(
+        &mut self,
+        released_at: u64,
+        mouse_position_x: f64,
+        mouse_position_y: f64,
+    ) {
+        let captured = self.driver.capture_selection();
+        let at_ms = self.driver.now();
+        self.driver.observe(TraceEvent::Capture {
+            at_ms,
+            text: captured
+                .as_ref()
+                .ok()
+                .map(|selection| selection.text.clone()),
+            bounds: captured
+                .as_ref()
+                .ok()
+                .and_then(|selection| selection.bounds),
+            error: captured.as_ref().err().map(|err| err.to_string()),
+        });
+        if let Ok(selection) = captured {
+            let trimed_selected_content = selection.text.trim();
             if !trimed_selected_content.is_empty() {
                 tracing::info!(selected_content = trimed_selected_content);
-                if let Some(handle) = crate::APP.get() {
-                    let state: State<AppState> = handle.state();
-                    *state.selected_content.write() = trimed_selected_content.to_string();
-                    let mut position_y = if self.last_mouse_position_y < mouse_position_y {
-                        self.last_mouse_position_y
-                    } else {
-                        mouse_position_y
-                    };
-                    position_y = position_y - SELECT_WINDOWS_HEIGHT - WINDOWS_POSITION_OFFSET;
-                    if position_y < SELECT_WINDOWS_HEIGHT {
-                        position_y = SELECT_WINDOWS_HEIGHT;
-                    }
-                    let mut position_x = (self.last_mouse_position_x + mouse_position_x) / 2.0
-                        - SELECT_WINDOWS_WIDTH / 2.0;
-                    let mut min_position_x = if self.last_mouse_position_x > mouse_position_x {
-                        mouse_position_x
-                    } else {
-                        self.last_mouse_position_x
-                    };
-                    if min_position_x < SELECT_WINDOWS_WIDTH / 2.0 {
-                        min_position_x = SELECT_WINDOWS_WIDTH / 2.0;
-                    }
-                    if position_x < min_position_x {
-                        position_x = min_position_x;
+                let anchor = match selection.bounds {
+                    Some(bounds) => SelectAnchor::Selection(bounds),
+                    None => {
+                        let (x, y) = self.tip_position(mouse_position_x, mouse_position_y);
+                        SelectAnchor::Mouse { x, y }
                     }
-                    crate::tauri_windows::select::build_select_windows(
-                        handle,
-                        trimed_selected_content,
-                        position_x,
-                        position_y,
-                    );
-                }
+                };
+                let (position_x, position_y) = anchor.window_position(&self.driver.screens());
+                self.driver.observe(TraceEvent::Tip {
+                    at_ms: released_at,
+                    x: position_x,
+                    y: position_y,
+                });
+                self.driver.show_tip(trimed_selected_content, anchor);
             }
         }
     }
+
+    fn tip_position(&self, mouse_position_x: f64, mouse_position_y: f64) -> (f64, f64) {
+        let mut position_y = if self.last_mouse_position_y < mouse_position_y {
+            self.last_mouse_position_y
+        } else {
+            mouse_position_y
+        };
+        position_y = position_y - SELECT_WINDOWS_HEIGHT - SELECT_WINDOWS_OFFSET;
+        if position_y < SELECT_WINDOWS_HEIGHT {
+            position_y = SELECT_WINDOWS_HEIGHT;
+        }
+        let mut position_x =
+            (self.last_mouse_position_x + mouse_position_x) / 2.0 - SELECT_WINDOWS_WIDTH / 2.0;
+        let mut min_position_x = if self.last_mouse_position_x > mouse_position_x {
+            mouse_position_x
+        } else {
+            self.last_mouse_position_x
+        };
+        if min_position_x < SELECT_WINDOWS_WIDTH / 2.0 {
+            min_position_x = SELECT_WINDOWS_WIDTH / 2.0;
+        }
+        if position_x < min_position_x {
+            position_x = min_position_x;
+        }
+        (position_x, position_y)
+    }
+}
+
+struct LiveSelectDriver {
+    started: Instant,
+    recorder: Option<TraceRecorder>,
+}
+
+impl LiveSelectDriver {
+    fn new(recorder: Option<TraceRecorder>) -> Self {
+        Self {
+            started: Instant::now(),
+            recorder,
+        }
+    }
+}
+
+impl SelectDriver for LiveSelectDriver {
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn is_enable_select(&self) -> bool {
+        if let Some(app_handle) = crate::APP.get() {
+            let state: State<AppState> = app_handle.state();
+            state.is_select_active()
+        } else {
+            false
+        }
+    }
+
+    fn screens(&self) -> Vec<SelectionRect> {
+        match crate::APP.get() {
+            Some(handle) => crate::tauri_windows::screens(handle),
+            None => Vec::new(),
+        }
+    }
+
+    fn capture_selection(&mut self) -> Result<Selection> {
+        crate::select::get_selection()
+    }
+
+    fn show_tip(&mut self, content: &str, anchor: SelectAnchor) {
+        if let Some(handle) = crate::APP.get() {
+            let state: State<AppState> = handle.state();
+            *state.selected_content.write() = content.to_string();
+            crate::tray::remember_selection(handle, content);
+            if let Err(err) =
+                crate::tauri_windows::select::build_select_windows(handle, content, anchor)
+            {
+                tracing::warn!(build_select_windows_error = ?err);
+            }
+        }
+    }
+
+    fn observe(&mut self, event: TraceEvent) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(&event);
+        }
+    }
 }
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Monitor, PhysicalPosition, PhysicalSize, Window};
+
+use super::manager::WindowKind;
+use crate::AppState;
+
+/// wait this long after the last move or resize before writing to disk
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// position and size of a window in physical pixels
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+    /// name of the monitor the window was on
+    pub monitor: Option<String>,
+}
+
+/// logical size limits of a window
+#[derive(Debug, Clone, Copy)]
+pub struct SizeLimits {
+    pub min_width: f64,
+    pub min_height: f64,
+    pub max_width: f64,
+    pub max_height: f64,
+}
+
+impl SizeLimits {
+    const fn new(min_width: f64, min_height: f64, max_width: f64, max_height: f64) -> Self {
+        Self {
+            min_width,
+            min_height,
+            max_width,
+            max_height,
+        }
+    }
+
+    pub fn check(&self, width: f64, height: f64) -> Result<()> {
+        if width < self.min_width
+            || width > self.max_width
+            || height < self.min_height
+            || height > self.max_height
+        {
+            Err(anyhow!(
+                "size {}x{} is outside {}x{} ~ {}x{}",
+                width,
+                height,
+                self.min_width,
+                self.min_height,
+                self.max_width,
+                self.max_height
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn size_limits(label: &str) -> Option<SizeLimits> {
+    if label == super::SELECT_WINDOWS {
+        return Some(SizeLimits::new(100.0, 40.0, 800.0, 300.0));
+    }
+    let limits = match WindowKind::from_label(label)? {
+        WindowKind::Chat => SizeLimits::new(400.0, 300.0, 7680.0, 4320.0),
+        WindowKind::Settings => SizeLimits::new(400.0, 300.0, 1600.0, 1600.0),
+        WindowKind::QuickAnswer => SizeLimits::new(300.0, 160.0, 1200.0, 1200.0),
+        WindowKind::Search => SizeLimits::new(300.0, 40.0, 1200.0, 600.0),
+    };
+    Some(limits)
+}
+
+/// the managed windows and the select popup
+fn is_remembered(label: &str) -> bool {
+    label == super::SELECT_WINDOWS || WindowKind::from_label(label).is_some()
+}
+
+/// the select popup and the quick answer window follow the selection, only
+/// their size is kept
+fn restores_position(label: &str) -> bool {
+    label != super::SELECT_WINDOWS && WindowKind::from_label(label) != Some(WindowKind::QuickAnswer)
+}
+
+fn geometry_path() -> Option<std::path::PathBuf> {
+    crate::app_config::app_config_dir().map(|dir| dir.join("window-state.json"))
+}
+
+pub fn load_geometry() -> HashMap<String, WindowGeometry> {
+    let content = match geometry_path() {
+        Some(path) if path.exists() => std::fs::read_to_string(path),
+        _ => return HashMap::new(),
+    };
+    match content.map(|content| serde_json::from_str(&content)) {
+        Ok(Ok(geometry)) => geometry,
+        Ok(Err(err)) => {
+            tracing::warn!(parse_window_state_error = ?err);
+            HashMap::new()
+        }
+        Err(err) => {
+            tracing::warn!(read_window_state_error = ?err);
+            HashMap::new()
+        }
+    }
+}
+
+fn save_geometry(geometry: &HashMap<String, WindowGeometry>) -> Result<()> {
+    let path = geometry_path().ok_or_else(|| anyhow!("not found app config directory"))?;
+    std::fs::write(path, serde_json::to_string_pretty(geometry)?)?;
+    Ok(())
+}
+
+fn current_geometry(window: &Window) -> Result<WindowGeometry> {
+    let position = window.outer_position()?;
+    let size = window.inner_size()?;
+    Ok(WindowGeometry {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+        maximized: window.is_maximized()?,
+        monitor: window
+            .current_monitor()?
+            .and_then(|monitor| monitor.name().cloned()),
+    })
+}
+
+/// Called on move and resize. Minimized windows report bogus positions and a
+/// maximized window keeps the size it had before, so neither is recorded.
+pub fn remember(handle: &AppHandle, label: &str) {
+    if !is_remembered(label) {
+        return;
+    }
+    let window = match handle.get_window(label) {
+        Some(window) => window,
+        None => return,
+    };
+    if window.is_minimized().unwrap_or(false) {
+        return;
+    }
+    let state: tauri::State<AppState> = handle.state();
+    {
+        let mut geometry = state.window_geometry.write();
+        if window.is_maximized().unwrap_or(false) {
+            if let Some(saved) = geometry.get_mut(label) {
+                saved.maximized = true;
+            }
+        } else {
+            match current_geometry(&window) {
+                Ok(current) => {
+                    geometry.insert(label.to_string(), current);
+                }
+                Err(err) => {
+                    tracing::warn!(window_geometry_error = ?err);
+                    return;
+                }
+            }
+        }
+    }
+    schedule_save(handle);
+}
+
+/// Write a save still waiting for `SAVE_DELAY`, called on exit when the
+/// delayed task won't run anymore.
+pub fn flush(handle: &AppHandle) {
+    let state: tauri::State<AppState> = handle.state();
+    // a scheduled save sees the new generation and skips
+    if state
+        .window_geometry_generation
+        .fetch_add(1, Ordering::SeqCst)
+        == 0
+    {
+        // nothing moved since start
+        return;
+    }
+    let geometry = state.window_geometry.read().clone();
+    if let Err(err) = save_geometry(&geometry) {
+        tracing::warn!(save_window_state_error = ?err);
+    }
+}
+
+fn schedule_save(handle: &AppHandle) {
+    let state: tauri::State<AppState> = handle.state();
+    let generation = state
+        .window_geometry_generation
+        .fetch_add(1, Ordering::SeqCst)
+        + 1;
+    let handle = handle.clone();
+    state.spawn_delay_task(
+        async move {
+            let state: tauri::State<AppState> = handle.state();
+            if state.window_geometry_generation.load(Ordering::SeqCst) != generation {
+                // moved again, the later save writes this one as well
+                return;
+            }
+            let geometry = state.window_geometry.read().clone();
+            if let Err(err) = save_geometry(&geometry) {
+                tracing::warn!(save_window_state_error = ?err);
+            }
+        },
+        SAVE_DELAY,
+    );
+}
+
+/// a monitor's name and area in physical pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MonitorArea {
+    name: Option<String>,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl From<&Monitor> for MonitorArea {
+    fn from(monitor: &Monitor) -> Self {
+        Self {
+            name: monitor.name().cloned(),
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+        }
+    }
+}
+
+impl MonitorArea {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i32
+            && y < self.y + self.height as i32
+    }
+}
+
+/// Keep a saved rectangle on a monitor that still exists: the one it was
+/// saved on, the one containing its corner, or the primary one.
+fn clamp_to_monitors(
+    geometry: &WindowGeometry,
+    monitors: &[MonitorArea],
+    primary: Option<&MonitorArea>,
+) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+    let monitor = monitors
+        .iter()
+        .find(|monitor| monitor.name.is_some() && monitor.name == geometry.monitor)
+        .or_else(|| {
+            monitors
+                .iter()
+                .find(|monitor| monitor.contains(geometry.x, geometry.y))
+        })
+        .or(primary)
+        .or_else(|| monitors.first())?;
+    let width = geometry.width.min(monitor.width);
+    let height = geometry.height.min(monitor.height);
+    let x = geometry
+        .x
+        .clamp(monitor.x, monitor.x + (monitor.width - width) as i32);
+    let y = geometry
+        .y
+        .clamp(monitor.y, monitor.y + (monitor.height - height) as i32);
+    Some((
+        PhysicalPosition::new(x, y),
+        PhysicalSize::new(width, height),
+    ))
+}
+
+/// put a window back where it was last time
+pub fn restore(handle: &AppHandle, window: &Window) -> Result<()> {
+    if !is_remembered(window.label()) {
+        return Ok(());
+    }
+    let state: tauri::State<AppState> = handle.state();
+    let geometry = match state.window_geometry.read().get(window.label()) {
+        Some(geometry) => geometry.clone(),
+        None => return Ok(()),
+    };
+    let monitors: Vec<MonitorArea> = window
+        .available_monitors()?
+        .iter()
+        .map(MonitorArea::from)
+        .collect();
+    let primary = window.primary_monitor()?.as_ref().map(MonitorArea::from);
+    let (position, size) = match clamp_to_monitors(&geometry, &monitors, primary.as_ref()) {
+        Some(clamped) => clamped,
+        None => return Ok(()),
+    };
+    tracing::info!(restore_window = window.label(), geometry =? geometry);
+    window.set_size(size)?;
+    if restores_position(window.label()) {
+        window.set_position(position)?;
+    }
+    if geometry.maximized {
+        window.maximize()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, y: i32, width: u32, height: u32) -> MonitorArea {
+        MonitorArea {
+            name: Some(name.to_string()),
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn saved(monitor: Option<&str>, x: i32, y: i32, width: u32, height: u32) -> WindowGeometry {
+        WindowGeometry {
+            x,
+            y,
+            width,
+            height,
+            maximized: false,
+            monitor: monitor.map(str::to_string),
+        }
+    }
+
+    fn clamped(
+        geometry: &WindowGeometry,
+        monitors: &[MonitorArea],
+        primary: Option<&MonitorArea>,
+    ) -> Option<(i32, i32, u32, u32)> {
+        clamp_to_monitors(geometry, monitors, primary)
+            .map(|(position, size)| (position.x, position.y, size.width, size.height))
+    }
+
+    #[test]
+    fn windows_on_a_monitor_stay_put() {
+        let monitors = [
+            monitor("left", 0, 0, 1920, 1080),
+            monitor("right", 1920, 0, 2560, 1440),
+        ];
+        assert_eq!(
+            clamped(&saved(Some("right"), 2000, 100, 800, 600), &monitors, None),
+            Some((2000, 100, 800, 600))
+        );
+    }
+
+    #[test]
+    fn windows_move_onto_the_monitor_they_were_saved_on() {
+        let monitors = [
+            monitor("left", 0, 0, 1920, 1080),
+            monitor("right", 1920, 0, 2560, 1440),
+        ];
+        // hanging off the right edge of the right monitor
+        assert_eq!(
+            clamped(&saved(Some("right"), 4000, 1200, 800, 600), &monitors, None),
+            Some((3680, 840, 800, 600))
+        );
+        // the name wins over the corner
+        assert_eq!(
+            clamped(&saved(Some("left"), 2000, 100, 800, 600), &monitors, None),
+            Some((1120, 100, 800, 600))
+        );
+    }
+
+    #[test]
+    fn unknown_monitors_fall_back_to_the_corner_then_the_primary() {
+        let monitors = [
+            monitor("left", -1920, 0, 1920, 1080),
+            monitor("main", 0, 0, 1920, 1080),
+        ];
+        let primary = monitors[1].clone();
+        assert_eq!(
+            clamped(
+                &saved(Some("gone"), -1000, 200, 800, 600),
+                &monitors,
+                Some(&primary)
+            ),
+            Some((-1000, 200, 800, 600))
+        );
+        // a monitor that was unplugged on the far right
+        assert_eq!(
+            clamped(
+                &saved(Some("gone"), 3000, 200, 800, 600),
+                &monitors,
+                Some(&primary)
+            ),
+            Some((1120, 200, 800, 600))
+        );
+        assert_eq!(
+            clamped(&saved(None, 3000, -500, 800, 600), &monitors, None),
+            Some((-800, 0, 800, 600))
+        );
+        assert_eq!(clamped(&saved(None, 0, 0, 800, 600), &[], None), None);
+    }
+
+    #[test]
+    fn windows_larger_than_the_monitor_shrink() {
+        let monitors = [monitor("laptop", 0, 0, 1366, 768)];
+        assert_eq!(
+            clamped(
+                &saved(Some("laptop"), 200, 100, 2560, 1440),
+                &monitors,
+                None
+            ),
+            Some((0, 0, 1366, 768))
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use tauri::{AppHandle, Manager, Window, WindowBuilder, WindowUrl};
+
+use super::quick_answer::{
+    QUICK_ANSWER_WINDOWS, QUICK_ANSWER_WINDOWS_HEIGHT, QUICK_ANSWER_WINDOWS_WIDTH,
+};
+
+pub const SETTINGS_WINDOWS: &str = "settings_windows";
+pub const SEARCH_WINDOWS: &str = "search_windows";
+/// sent to the chat window with a question to put in the message input
+pub const CHAT_QUESTION_EVENT: &str = "chat-question";
+
+/// the windows the manager creates on demand and reuses afterwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowKind {
+    Chat,
+    Settings,
+    QuickAnswer,
+    Search,
+}
+
+impl WindowKind {
+    pub const ALL: [WindowKind; 4] = [
+        WindowKind::Chat,
+        WindowKind::Settings,
+        WindowKind::QuickAnswer,
+        WindowKind::Search,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WindowKind::Chat => crate::CHATBOX_MAIN_WINDOWS,
+            WindowKind::Settings => SETTINGS_WINDOWS,
+            WindowKind::QuickAnswer => QUICK_ANSWER_WINDOWS,
+            WindowKind::Search => SEARCH_WINDOWS,
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.label() == label)
+    }
+
+    fn builder<'a>(&self, handle: &'a AppHandle) -> WindowBuilder<'a, tauri::Wry> {
+        match self {
+            WindowKind::Chat => WindowBuilder::new(
+                handle,
+                self.label(),
+                WindowUrl::App("src/index.html".into()),
+            )
+            .title("chatbox")
+            .inner_size(1000.0, 800.0)
+            .resizable(true),
+            WindowKind::Settings => WindowBuilder::new(
+                handle,
+                self.label(),
+                WindowUrl::App("src/settings.html".into()),
+            )
+            .title("settings")
+            .inner_size(600.0, 700.0)
+            .resizable(true)
+            .center(),
+            WindowKind::QuickAnswer => WindowBuilder::new(
+                handle,
+                self.label(),
+                WindowUrl::App("src/quick_answer.html".into()),
+            )
+            .title("quick answer")
+            .resizable(true)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .inner_size(QUICK_ANSWER_WINDOWS_WIDTH, QUICK_ANSWER_WINDOWS_HEIGHT)
+            .min_inner_size(300.0, 160.0),
+            WindowKind::Search => WindowBuilder::new(
+                handle,
+                self.label(),
+                WindowUrl::App("src/search.html".into()),
+            )
+            .title("search")
+            .resizable(false)
+            .decorations(false)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .inner_size(600.0, 64.0)
+            .center(),
+        }
+    }
+}
+
+pub struct WindowManager;
+
+impl WindowManager {
+    pub fn get(handle: &AppHandle, kind: WindowKind) -> Option<Window> {
+        handle.get_window(kind.label())
+    }
+
+    /// the existing window for `kind`, or a new hidden one
+    pub fn get_or_create(handle: &AppHandle, kind: WindowKind) -> Result<Window> {
+        if let Some(window) = Self::get(handle, kind) {
+            return Ok(window);
+        }
+        tracing::info!(create_window = kind.label());
+        let window = kind
+            .builder(handle)
+            .visible(false)
+            .build()
+            .map_err(|err| anyhow!("build {} window error: {}", kind.label(), err))?;
+        if let Err(err) = super::geometry::restore(handle, &window) {
+            tracing::warn!(restore_window_error = ?err);
+        }
+        Ok(window)
+    }
+
+    pub fn show(handle: &AppHandle, kind: WindowKind) -> Result<Window> {
+        let window = Self::get_or_create(handle, kind)?;
+        window.unminimize()?;
+        window.show()?;
+        window.set_focus()?;
+        Ok(window)
+    }
+
+    pub fn focus(handle: &AppHandle, kind: WindowKind) -> Result<()> {
+        let window =
+            Self::get(handle, kind).ok_or_else(|| anyhow!("not found {} window", kind.label()))?;
+        window.set_focus()?;
+        Ok(())
+    }
+
+    pub fn hide(handle: &AppHandle, kind: WindowKind) -> Result<()> {
+        if let Some(window) = Self::get(handle, kind) {
+            window.hide()?;
+        }
+        Ok(())
+    }
+
+    /// hide the window if it's in front of the user, otherwise bring it up
+    pub fn toggle(handle: &AppHandle, kind: WindowKind) -> Result<()> {
+        match Self::get(handle, kind) {
+            Some(window) if window.is_visible()? && !window.is_minimized()? => {
+                window.hide()?;
+            }
+            _ => {
+                Self::show(handle, kind)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// show the chat window and put `question` in its message input
+    pub fn show_chat(handle: &AppHandle, question: Option<String>) -> Result<()> {
+        let window = Self::show(handle, WindowKind::Chat)?;
+        if let Some(question) = question {
+            window.emit(CHAT_QUESTION_EVENT, question)?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,33 @@
+use crate::select::SelectionRect;
+use tauri::{AppHandle, Manager};
+
+pub mod geometry;
+pub mod manager;
+pub mod quick_answer;
 #[cfg(not(target_os = "macos"))]
 pub mod select;
 
 pub const SELECT_WINDOWS: &str = "select_windows";
+
+/// bounds of every monitor, in physical pixels
+pub fn screens(handle: &AppHandle) -> Vec<SelectionRect> {
+    let monitors = handle
+        .windows()
+        .into_values()
+        .next()
+        .and_then(|window| window.available_monitors().ok())
+        .unwrap_or_default();
+    monitors
+        .iter()
+        .map(|monitor| {
+            let position = monitor.position();
+            let size = monitor.size();
+            SelectionRect {
+                x: position.x as f64,
+                y: position.y as f64,
+                width: size.width as f64,
+                height: size.height as f64,
+            }
+        })
+        .collect()
+}
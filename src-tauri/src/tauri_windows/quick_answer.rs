@@ -0,0 +1,121 @@
+use super::manager::{WindowKind, WindowManager};
+use crate::select::screen_at;
+use crate::AppState;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, PhysicalPosition};
+
+pub const QUICK_ANSWER_WINDOWS: &str = "quick_answer_windows";
+pub const QUICK_ANSWER_WINDOWS_WIDTH: f64 = 420.0;
+pub const QUICK_ANSWER_WINDOWS_HEIGHT: f64 = 320.0;
+/// sent to an open quick answer window when it gets a new question
+pub const QUICK_ANSWER_QUESTION_EVENT: &str = "quick-answer-question";
+/// sent to the main window with a quick answer conversation to continue there
+pub const OPEN_IN_CHAT_EVENT: &str = "open-in-chat";
+
+/// what the quick answer window should ask, and what to do with the answer
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickAnswerQuestion {
+    pub question: String,
+    /// paste the answer over the selection once it is complete
+    pub replace: bool,
+    /// the selection action that asked, its answers may come from the cache
+    #[serde(default)]
+    pub action_id: Option<String>,
+}
+
+impl QuickAnswerQuestion {
+    pub fn answer(question: String) -> Self {
+        Self {
+            question,
+            replace: false,
+            action_id: None,
+        }
+    }
+
+    pub fn replace(question: String) -> Self {
+        Self {
+            question,
+            replace: true,
+            action_id: None,
+        }
+    }
+
+    pub fn for_action(self, action_id: &str) -> Self {
+        Self {
+            action_id: Some(action_id.to_string()),
+            ..self
+        }
+    }
+}
+
+pub use crate::llm::ChatMessage;
+
+/// top left corner for the quick answer window: where the select popup was,
+/// kept on the monitor showing the selection
+fn anchor_position(handle: &AppHandle) -> Option<(f64, f64)> {
+    let select_window = handle.get_window(super::SELECT_WINDOWS)?;
+    let position = select_window.outer_position().ok()?;
+    let scale_factor = select_window.scale_factor().unwrap_or(1.0);
+    let (position_x, position_y) = (position.x as f64, position.y as f64);
+    let screens = super::screens(handle);
+    let screen = match screen_at(&screens, position_x, position_y) {
+        Some(screen) => screen,
+        None => return Some((position_x, position_y)),
+    };
+    let width = QUICK_ANSWER_WINDOWS_WIDTH * scale_factor;
+    let height = QUICK_ANSWER_WINDOWS_HEIGHT * scale_factor;
+    Some((
+        position_x
+            .min(screen.x + screen.width - width)
+            .max(screen.x),
+        position_y
+            .min(screen.y + screen.height - height)
+            .max(screen.y),
+    ))
+}
+
+/// Show the quick answer window next to the selection and ask `question` in it.
+/// The window has its own label, the main chat window is left alone.
+pub fn show_quick_answer_window(handle: &AppHandle, question: Option<String>) -> Result<()> {
+    show_window(handle, question.map(QuickAnswerQuestion::answer))
+}
+
+/// ask `question` in the quick answer window, e.g. for a selection action
+pub fn show_question(handle: &AppHandle, question: QuickAnswerQuestion) -> Result<()> {
+    show_window(handle, Some(question))
+}
+
+fn show_window(handle: &AppHandle, question: Option<QuickAnswerQuestion>) -> Result<()> {
+    let state: tauri::State<AppState> = handle.state();
+    if let Some(question) = question.as_ref() {
+        *state.quick_answer_question.write() = question.clone();
+    }
+    let position = anchor_position(handle);
+    tracing::info!(quick_answer_position =? position);
+    let is_new = WindowManager::get(handle, WindowKind::QuickAnswer).is_none();
+    let window = WindowManager::get_or_create(handle, WindowKind::QuickAnswer)?;
+    if let Some((position_x, position_y)) = position {
+        window.set_position(PhysicalPosition::new(position_x, position_y))?;
+    } else if is_new {
+        window.center()?;
+    }
+    WindowManager::show(handle, WindowKind::QuickAnswer)?;
+    // a new window asks for its question once it has loaded
+    if let (false, Some(question)) = (is_new, question) {
+        window.emit(QUICK_ANSWER_QUESTION_EVENT, question)?;
+    }
+    Ok(())
+}
+
+pub fn hide_quick_answer_window(handle: &AppHandle) -> Result<()> {
+    WindowManager::hide(handle, WindowKind::QuickAnswer)
+}
+
+/// hand a quick answer conversation over to the main chat window
+pub fn open_in_chat(handle: &AppHandle, messages: Vec<ChatMessage>) -> Result<()> {
+    let window = WindowManager::show(handle, WindowKind::Chat)?;
+    window.emit(OPEN_IN_CHAT_EVENT, messages)?;
+    hide_quick_answer_window(handle)
+}
@@ -1,5 +1,9 @@
+use super::manager::{WindowKind, WindowManager};
 pub use super::SELECT_WINDOWS;
+use crate::actions::ActionTarget;
 use crate::easy_thing::foreground::PlatformForeground;
+use crate::select::{screen_at, SelectionRect};
+use crate::tauri_windows::quick_answer::QuickAnswerQuestion;
 use crate::AppState;
 use crate::APP;
 use serde::{Deserialize, Serialize};
@@ -7,28 +11,68 @@ use std::sync::atomic::Ordering;
 use tauri::{AppHandle, LogicalPosition, Manager, PhysicalPosition, WindowEvent};
 pub const SELECT_WINDOWS_WIDTH: f64 = 320.0;
 pub const SELECT_WINDOWS_HEIGHT: f64 = 100.0;
+pub const SELECT_WINDOWS_OFFSET: f64 = 15.0;
+
+/// where the select window should appear
+#[derive(Debug, Clone, Copy)]
+pub enum SelectAnchor {
+    /// window position worked out from the mouse drag
+    Mouse { x: f64, y: f64 },
+    /// screen bounds of the selected text
+    Selection(SelectionRect),
+}
 
+impl SelectAnchor {
+    /// Centered above the selection, or below it when there is no room above,
+    /// kept on the screen showing the selection. Screens may have negative
+    /// coordinates left of or above the primary one.
+    pub fn window_position(&self, screens: &[SelectionRect]) -> (f64, f64) {
+        match self {
+            SelectAnchor::Mouse { x, y } => (*x, *y),
+            SelectAnchor::Selection(rect) => {
+                let center_x = rect.x + rect.width / 2.0;
+                let mut position_x = center_x - SELECT_WINDOWS_WIDTH / 2.0;
+                let mut position_y = rect.y - SELECT_WINDOWS_HEIGHT - SELECT_WINDOWS_OFFSET;
+                if let Some(screen) = screen_at(screens, center_x, rect.y) {
+                    position_x = position_x
+                        .min(screen.x + screen.width - SELECT_WINDOWS_WIDTH)
+                        .max(screen.x);
+                    if position_y < screen.y {
+                        position_y = rect.y + rect.height + SELECT_WINDOWS_OFFSET;
+                    }
+                }
+                (position_x, position_y)
+            }
+        }
+    }
+}
 
-
-pub fn build_select_windows(
-    handle: &AppHandle,
-    content: &str,
-    window_position_x: f64,
-    window_position_y: f64,
-) {
+/// keep the window the user is selecting in, answers go back to it
+pub fn remember_foreground(handle: &AppHandle) {
     let foreground_handle = PlatformForeground::get_foreground_window();
     tracing::info!(foreground_handle = foreground_handle);
     let state: tauri::State<AppState> = handle.state();
-    let _selected = content.to_string();
     if foreground_handle != 0 {
         state
             .foreground_handle
             .store(foreground_handle, Ordering::SeqCst);
+        *state.foreground_window.write() = PlatformForeground::get_window_info(foreground_handle);
     }
+}
+
+pub fn build_select_windows(
+    handle: &AppHandle,
+    content: &str,
+    anchor: SelectAnchor,
+) -> anyhow::Result<()> {
+    let (window_position_x, window_position_y) = anchor.window_position(&super::screens(handle));
+    tracing::info!(select_anchor =? anchor);
+    remember_foreground(handle);
+    let _selected = content.to_string();
     match handle.get_window(SELECT_WINDOWS) {
         Some(window) => {
             tracing::info!("has select window");
-            window.unminimize().unwrap();
+            window.unminimize()?;
             if cfg!(target_os = "macos") {
                 let _ =
                     window.set_position(LogicalPosition::new(window_position_x, window_position_y));
@@ -36,8 +80,8 @@ pub fn build_select_windows(
                 let _ = window
                     .set_position(PhysicalPosition::new(window_position_x, window_position_y));
             }
-            window.show().unwrap();
-            window.set_focus().unwrap();
+            window.show()?;
+            window.set_focus()?;
         }
         None => {
             tracing::info!("not found select window");
@@ -57,20 +101,25 @@ pub fn build_select_windows(
             .position(window_position_x as f64, window_position_y as f64)
             .inner_size(SELECT_WINDOWS_WIDTH, SELECT_WINDOWS_HEIGHT)
             .focused(true)
-            .build()
-            .expect("build windows error not happened");
+            .build()?;
             windows.on_window_event(hide_window_when_lose_focused);
+            if let Err(err) = super::geometry::restore(handle, &windows) {
+                tracing::warn!(restore_window_error = ?err);
+            }
 
             //windows.set_always_on_top(true).unwrap();
         }
     }
+    Ok(())
 }
 
 fn hide_window_when_lose_focused(event: &WindowEvent) {
     if let WindowEvent::Focused(focused) = event {
         if !focused {
-            let handle = APP.get().unwrap();
-            if let Some(window) = handle.get_window(SELECT_WINDOWS) {
+            if let Some(window) = APP
+                .get()
+                .and_then(|handle| handle.get_window(SELECT_WINDOWS))
+            {
                 //window.set_always_on_top(false).unwrap();
                 let _ = window.hide();
             }
@@ -79,8 +128,10 @@ fn hide_window_when_lose_focused(event: &WindowEvent) {
 }
 
 pub fn hide_select_window() {
-    let handle = APP.get().unwrap();
-    if let Some(window) = handle.get_window(SELECT_WINDOWS) {
+    if let Some(window) = APP
+        .get()
+        .and_then(|handle| handle.get_window(SELECT_WINDOWS))
+    {
         //window.set_always_on_top(false).unwrap();
         let _ = window.hide();
     }
@@ -88,154 +139,102 @@ pub fn hide_select_window() {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SelectPayload {
-    pub label: String,
-    pub prompt: String,
+    /// id of the action in the registry
+    pub id: String,
     pub selected: String,
 }
 
-use tauri::Window;
-// fn insert_text_and_send(window: &Window, text: &str) {
-//     let script = format!(
-//         r#"
-//         (function() {{
-//             let input = document.querySelector('#message-input');
-//             input.value = '{}';  // 设置文本框的值为传入的文本
-//             input.focus();
-//             //let button = document.querySelector('#send-button');
-//             //button.click();
-//         }})();
-//         "#,
-//         text,
-//     );
-//     window.eval(&script).unwrap();
-// }
-
-
-fn insert_text_and_send(window: &Window, text: &str) {
-    let script = format!(
-        r#"
-      (function() {{
-        let input = document.querySelector('#message-input');
-        input.focus();
-        //let button = document.querySelector('#send-button');
-        //button.click();
-      }})();"#,
-    );
-    window.eval(&script).unwrap();
-}
-
-
-use enigo::{Enigo, Key, KeyboardControllable};
-use std::ffi::OsString;
-use std::os::windows::ffi::OsStringExt;
-use std::ptr;
-use std::thread;
-use std::time::Duration;
-use clipboard::{ClipboardContext, ClipboardProvider};
-
-
-
-use winapi::um::winuser::{
-    CloseClipboard, EmptyClipboard, OpenClipboard,
-    SetClipboardData, CF_UNICODETEXT,  GetClipboardData,
-};
-
+/// sent to the main window when a popup action goes to the chat
+pub const SELECT_ACTION_EVENT: &str = "select-action";
 
-
-fn set_clipboard_data(data: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut clipboard: ClipboardContext = ClipboardProvider::new()?;
-    clipboard.set_contents(data.to_owned())?;
-    Ok(())
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectActionEvent {
+    pub action_id: String,
+    pub label: String,
+    /// rendered prompt, already containing the selection
+    pub prompt: String,
+    pub selection: String,
 }
 
-
-fn simulate_paste() {
-    let mut enigo = Enigo::new();
-    enigo.key_down(Key::Control);
-    enigo.key_click(Key::Layout('v'));
-    enigo.key_up(Key::Control);
-
-    // 等待按键消息处理
-    thread::sleep(Duration::from_millis(100));
-    unsafe {
-        // 清空剪贴板并关闭
-        EmptyClipboard();
-        CloseClipboard();
+pub fn click_select(handle: &tauri::AppHandle, payload: SelectPayload) -> anyhow::Result<()> {
+    tracing::info!(click_select =? payload);
+    let action = crate::actions::find_action(handle, &payload.id)
+        .ok_or_else(|| anyhow::anyhow!("not found action {}", payload.id))?;
+    match action.target {
+        ActionTarget::Copy => return crate::select::copy_content(payload.selected),
+        ActionTarget::Chat | ActionTarget::InlineAnswer | ActionTarget::Replace => {}
     }
-}
 
-fn copy_selected_text() -> Option<String> {
-    // 模拟按键：Ctrl + C，复制选定的文本到剪贴板
-    let mut enigo = Enigo::new();
-    enigo.key_down(Key::Control);
-    enigo.key_click(Key::Layout('c'));
-    enigo.key_up(Key::Control);
-
-    // // 等待按键消息处理
-    thread::sleep(Duration::from_millis(100));
-
-    unsafe {
-        // 打开剪贴板
-        if OpenClipboard(ptr::null_mut()) == 0 {
-            println!("Failed to open the clipboard.");
-            return None;
+    let context = crate::prompt_template::TemplateContext::capture(handle, &payload.selected);
+    let prompt = crate::prompt_template::render_prompt(&action.prompt, &context)?;
+    match action.target {
+        ActionTarget::InlineAnswer => {
+            return crate::tauri_windows::quick_answer::show_question(
+                handle,
+                QuickAnswerQuestion::answer(prompt).for_action(&action.id),
+            )
         }
-
-        // 获取剪贴板中的文本
-        let clipboard_data = GetClipboardData(CF_UNICODETEXT);
-        if clipboard_data.is_null() {
-            println!("Failed to get clipboard data.");
-            CloseClipboard();
-            return None;
+        ActionTarget::Replace => {
+            return crate::tauri_windows::quick_answer::show_question(
+                handle,
+                QuickAnswerQuestion::replace(prompt).for_action(&action.id),
+            )
         }
+        ActionTarget::Chat | ActionTarget::Copy => {}
+    }
 
-        // 将剪贴板中的文本转换为 Rust 字符串
-        let text_ptr = clipboard_data as *const u16;
-        let mut text_length = 0;
-        while *text_ptr.offset(text_length) != 0 {
-            text_length += 1;
-        }
+    let window = WindowManager::show(handle, WindowKind::Chat)?;
+    window.emit(
+        SELECT_ACTION_EVENT,
+        SelectActionEvent {
+            action_id: action.id,
+            label: action.label,
+            prompt,
+            selection: payload.selected,
+        },
+    )?;
+    Ok(())
+}
 
-        let text_slice = std::slice::from_raw_parts(text_ptr, text_length as usize);
-        let selected_text = OsString::from_wide(text_slice)
-            .to_string_lossy()
-            .into_owned();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        println!("selected_text: {}", selected_text);
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> SelectionRect {
+        SelectionRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
 
-        Some(selected_text)
+    // a monitor left of the primary one
+    fn screens() -> Vec<SelectionRect> {
+        vec![
+            rect(-1920.0, 0.0, 1920.0, 1080.0),
+            rect(0.0, 0.0, 2560.0, 1440.0),
+        ]
     }
-}
 
-pub fn click_select(handle: &tauri::AppHandle, payload: SelectPayload) -> anyhow::Result<()> {
-    print!("tauri_windows.select.rs  click_select {:?}", payload);
-    let app_config = crate::app_config::get_app_config().unwrap_or_default();
-    let mode = app_config.mode.unwrap_or("快捷提问".to_string());
-    println!("mode: {}", mode);
-    tracing::info!(mode = mode);
-
-    let selected_text = copy_selected_text().unwrap_or_else(|| "".to_string());
-    println!("Selected text: {}", selected_text);
-    let combine_text = format!("{} {}", payload.prompt, selected_text);
-    println!("combine_text: {}", combine_text);
-    
-    set_clipboard_data(&combine_text).unwrap();
-
-    let window = handle.get_window("main").unwrap();
-    window.unminimize().unwrap();
-    // 显示窗口并将其置于最前端
-    window.show().unwrap();
-    window.set_focus().unwrap();  
-
-    println!("Window is shown and focused");
-
-    // 等待按键消息处理
-    thread::sleep(Duration::from_millis(100));
-    insert_text_and_send(&window, &selected_text);
-    
-    // 插入文本到输入框并模拟点击发送按钮
-    thread::sleep(Duration::from_millis(100));
-    simulate_paste();
+    #[test]
+    fn popup_stays_on_a_monitor_with_negative_coordinates() {
+        let anchor = SelectAnchor::Selection(rect(-1000.0, 500.0, 200.0, 20.0));
+        assert_eq!(anchor.window_position(&screens()), (-1060.0, 385.0));
+    }
 
-    Ok(())
+    #[test]
+    fn popup_is_clamped_to_the_monitor_of_the_selection() {
+        let left_edge = SelectAnchor::Selection(rect(-1900.0, 50.0, 40.0, 20.0));
+        assert_eq!(left_edge.window_position(&screens()), (-1920.0, 85.0));
+        let right_edge = SelectAnchor::Selection(rect(2500.0, 600.0, 50.0, 20.0));
+        assert_eq!(right_edge.window_position(&screens()), (2240.0, 485.0));
+    }
+
+    #[test]
+    fn popup_is_not_clamped_without_monitors() {
+        let anchor = SelectAnchor::Selection(rect(-1000.0, 50.0, 200.0, 20.0));
+        assert_eq!(anchor.window_position(&[]), (-1060.0, -65.0));
+    }
 }
@@ -0,0 +1,298 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::select::{Selection, SelectionRect};
+use crate::task::{SelectDriver, SelectListener};
+use crate::tauri_windows::select::SelectAnchor;
+
+/// set this to a file path to record the select listener input as jsonl
+pub const SELECT_TRACE_ENV: &str = "QUICK_AI_SELECT_TRACE";
+
+/// One line of a select trace. `at_ms` is relative to the start of the listener.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// listener started with the mouse at this position
+    Start {
+        at_ms: u64,
+        x: f64,
+        y: f64,
+        /// monitor bounds, the popup is kept on the one showing the selection
+        #[serde(default)]
+        screens: Vec<SelectionRect>,
+    },
+    /// left button pressed
+    Press { at_ms: u64, x: f64, y: f64 },
+    /// left button released
+    Release {
+        at_ms: u64,
+        x: f64,
+        y: f64,
+        enable_select: bool,
+    },
+    /// result of reading the selected text
+    Capture {
+        at_ms: u64,
+        text: Option<String>,
+        /// only present when read through the accessibility api
+        #[serde(default)]
+        bounds: Option<SelectionRect>,
+        error: Option<String>,
+    },
+    /// the select popup was shown at this position, stamped with its release
+    Tip { at_ms: u64, x: f64, y: f64 },
+}
+
+pub struct TraceRecorder {
+    writer: BufWriter<File>,
+}
+
+impl TraceRecorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path.as_ref())
+            .with_context(|| format!("create trace file {:?}", path.as_ref()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// recorder for the path in `QUICK_AI_SELECT_TRACE`, if set
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os(SELECT_TRACE_ENV)?;
+        match Self::create(&path) {
+            Ok(recorder) => {
+                tracing::info!(select_trace = ?path);
+                Some(recorder)
+            }
+            Err(err) => {
+                tracing::warn!(select_trace_error = ?err);
+                None
+            }
+        }
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) {
+        let result = serde_json::to_writer(&mut self.writer, event)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| writeln!(self.writer).map_err(anyhow::Error::from))
+            .and_then(|_| self.writer.flush().map_err(anyhow::Error::from));
+        if let Err(err) = result {
+            tracing::warn!(record_trace_error = ?err);
+        }
+    }
+}
+
+pub fn read_trace(path: impl AsRef<Path>) -> Result<Vec<TraceEvent>> {
+    let file = File::open(path.as_ref())
+        .with_context(|| format!("open trace file {:?}", path.as_ref()))?;
+    let mut events = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line)
+            .with_context(|| format!("parse trace line {}", index + 1))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TipPosition {
+    pub at_ms: u64,
+    pub x: f64,
+    pub y: f64,
+    pub content: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    /// popups recorded in the trace
+    pub expected: Vec<TipPosition>,
+    /// popups the current selection logic shows for the same input
+    pub actual: Vec<TipPosition>,
+    /// releases where the logic wanted a capture the trace doesn't have
+    pub missing_captures: Vec<u64>,
+}
+
+impl ReplayReport {
+    pub fn matches(&self) -> bool {
+        self.missing_captures.is_empty()
+            && self.expected.len() == self.actual.len()
+            && self
+                .expected
+                .iter()
+                .zip(self.actual.iter())
+                .all(|(expected, actual)| {
+                    expected.at_ms == actual.at_ms
+                        && expected.x == actual.x
+                        && expected.y == actual.y
+                })
+    }
+}
+
+struct ReplaySelectDriver {
+    now: u64,
+    screens: Vec<SelectionRect>,
+    enable_select: bool,
+    capture: Option<std::result::Result<Selection, String>>,
+    tips: Vec<TipPosition>,
+    missing_captures: Vec<u64>,
+}
+
+impl SelectDriver for ReplaySelectDriver {
+    fn now(&self) -> u64 {
+        self.now
+    }
+
+    fn is_enable_select(&self) -> bool {
+        self.enable_select
+    }
+
+    fn screens(&self) -> Vec<SelectionRect> {
+        self.screens.clone()
+    }
+
+    fn capture_selection(&mut self) -> Result<Selection> {
+        match self.capture.take() {
+            Some(Ok(selection)) => Ok(selection),
+            Some(Err(error)) => Err(anyhow!(error)),
+            None => {
+                self.missing_captures.push(self.now);
+                Err(anyhow!("no capture recorded"))
+            }
+        }
+    }
+
+    fn show_tip(&mut self, content: &str, anchor: SelectAnchor) {
+        let (position_x, position_y) = anchor.window_position(&self.screens);
+        self.tips.push(TipPosition {
+            at_ms: self.now,
+            x: position_x,
+            y: position_y,
+            content: Some(content.to_string()),
+        });
+    }
+}
+
+/// Feed a trace through the selection logic. Time only advances with the
+/// timestamps in the trace, and captures are answered from the trace, so the
+/// result is the same on every machine.
+pub fn replay_trace(events: &[TraceEvent]) -> Result<ReplayReport> {
+    let (start_x, start_y, screens) = match events.first() {
+        Some(TraceEvent::Start { x, y, screens, .. }) => (*x, *y, screens.clone()),
+        _ => return Err(anyhow!("trace must begin with a start event")),
+    };
+    let driver = ReplaySelectDriver {
+        now: 0,
+        screens,
+        enable_select: true,
+        capture: None,
+        tips: Vec::new(),
+        missing_captures: Vec::new(),
+    };
+    let mut listener = SelectListener::new(driver, start_x, start_y);
+    let mut report = ReplayReport::default();
+    let mut captured_text = None;
+
+    for (index, event) in events.iter().enumerate().skip(1) {
+        match event {
+            TraceEvent::Start { .. } => return Err(anyhow!("unexpected start event")),
+            TraceEvent::Press { at_ms, x, y } => {
+                listener.driver_mut().now = *at_ms;
+                listener.on_press(*x, *y);
+            }
+            TraceEvent::Release {
+                at_ms,
+                x,
+                y,
+                enable_select,
+            } => {
+                // the capture, if any, is recorded right after its release
+                let capture = match events.get(index + 1) {
+                    Some(TraceEvent::Capture {
+                        text,
+                        bounds,
+                        error,
+                        ..
+                    }) => Some(match text {
+                        Some(text) => Ok(Selection {
+                            text: text.clone(),
+                            bounds: *bounds,
+                        }),
+                        None => Err(error.clone().unwrap_or_default()),
+                    }),
+                    _ => None,
+                };
+                let driver = listener.driver_mut();
+                driver.now = *at_ms;
+                driver.enable_select = *enable_select;
+                driver.capture = capture;
+                listener.on_release(*x, *y);
+            }
+            TraceEvent::Capture { text, .. } => {
+                captured_text = text.as_ref().map(|text| text.trim().to_string());
+            }
+            TraceEvent::Tip { at_ms, x, y } => report.expected.push(TipPosition {
+                at_ms: *at_ms,
+                x: *x,
+                y: *y,
+                content: captured_text.clone(),
+            }),
+        }
+    }
+
+    let driver = listener.driver();
+    report.actual = driver.tips.clone();
+    report.missing_captures = driver.missing_captures.clone();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Vec<TraceEvent> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/select_trace.jsonl");
+        read_trace(path).unwrap()
+    }
+
+    #[test]
+    fn replay_shows_popup_only_for_the_drag() {
+        let report = replay_trace(&fixture()).unwrap();
+        assert!(report.matches(), "{:?}", report);
+        assert_eq!(
+            report.actual,
+            vec![TipPosition {
+                at_ms: 1400,
+                x: 600.0,
+                y: 185.0,
+                content: Some("hello world".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn replay_reports_a_missing_capture() {
+        let events: Vec<_> = fixture()
+            .into_iter()
+            .filter(|event| !matches!(event, TraceEvent::Capture { at_ms: 1460, .. }))
+            .collect();
+        let report = replay_trace(&events).unwrap();
+        assert!(!report.matches());
+        assert_eq!(report.missing_captures, vec![1400]);
+        assert!(report.actual.is_empty());
+    }
+
+    #[test]
+    fn replay_rejects_a_trace_without_start() {
+        assert!(replay_trace(&fixture()[1..]).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use tauri::{
+    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem, SystemTraySubmenu,
+};
+
+use crate::tauri_windows::manager::{WindowKind, WindowManager};
+use crate::AppState;
+
+const TOGGLE_SELECT: &str = "toggle_select";
+const PAUSE_15_MINUTES: &str = "pause_15_minutes";
+const PAUSE_1_HOUR: &str = "pause_1_hour";
+const RESUME_SELECT: &str = "resume_select";
+const OPEN_CHAT: &str = "open_chat";
+const OPEN_SETTINGS: &str = "open_settings";
+const RECENT_SELECTION: &str = "recent_selection_";
+const COPY_LAST_ANSWER: &str = "copy_last_answer";
+const RESUME_AUTO_INPUT: &str = "resume_auto_input";
+const CANCEL_AUTO_INPUT: &str = "cancel_auto_input";
+const QUIT: &str = "quit";
+
+/// how many selections the tray remembers
+const MAX_RECENT_SELECTIONS: usize = 5;
+/// longest menu title for a recent selection, in characters
+const MAX_TITLE_LENGTH: usize = 30;
+
+/// what the tray menu shows
+#[derive(Debug, Default)]
+struct TrayMenuState {
+    enable_select: bool,
+    paused_until: Option<DateTime<Local>>,
+    recent_selections: Vec<String>,
+    has_last_answer: bool,
+    auto_input_paused: bool,
+}
+
+impl TrayMenuState {
+    fn current(state: &AppState) -> Self {
+        Self {
+            enable_select: state.enable_select.load(Ordering::SeqCst),
+            paused_until: *state.select_paused_until.read(),
+            recent_selections: state.recent_selections.read().iter().cloned().collect(),
+            has_last_answer: !state.last_answer.read().is_empty(),
+            auto_input_paused: state
+                .auto_input
+                .get()
+                .map(|queue| queue.has_paused_jobs())
+                .unwrap_or(false),
+        }
+    }
+}
+
+fn menu_title(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > MAX_TITLE_LENGTH {
+        format!(
+            "{}…",
+            line.chars().take(MAX_TITLE_LENGTH).collect::<String>()
+        )
+    } else {
+        line
+    }
+}
+
+fn build_menu(menu_state: &TrayMenuState) -> SystemTrayMenu {
+    let title = match menu_state.paused_until {
+        Some(until) => format!("划词弹窗（暂停至 {}）", until.format("%H:%M")),
+        None => "划词弹窗".to_string(),
+    };
+    let mut toggle_select = CustomMenuItem::new(TOGGLE_SELECT, title);
+    if menu_state.enable_select {
+        toggle_select = toggle_select.selected();
+    }
+    let mut menu = SystemTrayMenu::new().add_item(toggle_select);
+    if menu_state.paused_until.is_some() {
+        menu = menu.add_item(CustomMenuItem::new(RESUME_SELECT, "恢复划词弹窗"));
+    } else {
+        let mut pause_15_minutes = CustomMenuItem::new(PAUSE_15_MINUTES, "暂停 15 分钟");
+        let mut pause_1_hour = CustomMenuItem::new(PAUSE_1_HOUR, "暂停 1 小时");
+        if !menu_state.enable_select {
+            pause_15_minutes = pause_15_minutes.disabled();
+            pause_1_hour = pause_1_hour.disabled();
+        }
+        menu = menu.add_item(pause_15_minutes).add_item(pause_1_hour);
+    }
+
+    let mut recent = SystemTrayMenu::new();
+    for (index, selection) in menu_state.recent_selections.iter().enumerate() {
+        recent = recent.add_item(CustomMenuItem::new(
+            format!("{}{}", RECENT_SELECTION, index),
+            menu_title(selection),
+        ));
+    }
+    if menu_state.recent_selections.is_empty() {
+        recent = recent.add_item(CustomMenuItem::new("recent_selection_empty", "无").disabled());
+    }
+    let mut copy_last_answer = CustomMenuItem::new(COPY_LAST_ANSWER, "复制上次回答");
+    if !menu_state.has_last_answer {
+        copy_last_answer = copy_last_answer.disabled();
+    }
+
+    if menu_state.auto_input_paused {
+        menu = menu
+            .add_native_item(SystemTrayMenuItem::Separator)
+            .add_item(CustomMenuItem::new(RESUME_AUTO_INPUT, "继续自动输入"))
+            .add_item(CustomMenuItem::new(CANCEL_AUTO_INPUT, "取消自动输入"));
+    }
+    menu.add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(OPEN_CHAT, "打开聊天"))
+        .add_item(CustomMenuItem::new(OPEN_SETTINGS, "设置"))
+        .add_submenu(SystemTraySubmenu::new("最近划词", recent))
+        .add_item(copy_last_answer)
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(QUIT, "退出"))
+}
+
+/// the tray as it looks at startup, before `AppState` exists
+pub fn system_tray() -> SystemTray {
+    let app_config = crate::app_config::get_app_config().unwrap_or_default();
+    SystemTray::new().with_menu(build_menu(&TrayMenuState {
+        enable_select: app_config.enable_select.unwrap_or(true),
+        ..Default::default()
+    }))
+}
+
+/// rebuild the menu from `AppState`, call it after changing anything it shows
+pub fn refresh_tray_menu(handle: &AppHandle) {
+    let state: tauri::State<AppState> = handle.state();
+    let menu = build_menu(&TrayMenuState::current(&state));
+    if let Err(err) = handle.tray_handle().set_menu(menu) {
+        tracing::warn!(refresh_tray_menu_error = ?err);
+    }
+}
+
+pub fn remember_selection(handle: &AppHandle, selection: &str) {
+    let selection = selection.trim();
+    if selection.is_empty() {
+        return;
+    }
+    let state: tauri::State<AppState> = handle.state();
+    {
+        let mut recent = state.recent_selections.write();
+        recent.retain(|text| text != selection);
+        recent.push_front(selection.to_string());
+        recent.truncate(MAX_RECENT_SELECTIONS);
+    }
+    refresh_tray_menu(handle);
+}
+
+pub fn remember_answer(handle: &AppHandle, answer: String) {
+    if answer.trim().is_empty() {
+        return;
+    }
+    let state: tauri::State<AppState> = handle.state();
+    let had_answer = !state.last_answer.read().is_empty();
+    *state.last_answer.write() = answer;
+    if !had_answer {
+        refresh_tray_menu(handle);
+    }
+}
+
+/// switch the popup on or off, which also ends a pause
+pub fn set_enable_select(handle: &AppHandle, enable_select: bool) {
+    let state: tauri::State<AppState> = handle.state();
+    if state.enable_select.swap(enable_select, Ordering::SeqCst) != enable_select {
+        resume_select(handle);
+    }
+}
+
+fn toggle_select(handle: &AppHandle) -> anyhow::Result<()> {
+    let state: tauri::State<AppState> = handle.state();
+    let enable_select = !state.enable_select.load(Ordering::SeqCst);
+    set_enable_select(handle, enable_select);
+    let mut app_config = crate::app_config::get_app_config().unwrap_or_default();
+    app_config.enable_select = Some(enable_select);
+    crate::app_config::save_app_config(&app_config).map_err(|err| anyhow::anyhow!(err))
+}
+
+/// turn the popup off for `duration`, a later pause or resume replaces this one
+fn pause_select(handle: &AppHandle, duration: Duration) {
+    let state: tauri::State<AppState> = handle.state();
+    let until = Local::now() + chrono::Duration::from_std(duration).unwrap_or_default();
+    *state.select_paused_until.write() = Some(until);
+    let generation = state.select_pause_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    refresh_tray_menu(handle);
+    let handle = handle.clone();
+    state.spawn_delay_task(
+        async move {
+            let state: tauri::State<AppState> = handle.state();
+            if state.select_pause_generation.load(Ordering::SeqCst) == generation {
+                resume_select(&handle);
+            }
+        },
+        duration,
+    );
+}
+
+fn resume_select(handle: &AppHandle) {
+    let state: tauri::State<AppState> = handle.state();
+    state.select_pause_generation.fetch_add(1, Ordering::SeqCst);
+    *state.select_paused_until.write() = None;
+    refresh_tray_menu(handle);
+}
+
+fn click_menu_item(handle: &AppHandle, id: &str) -> anyhow::Result<()> {
+    let state: tauri::State<AppState> = handle.state();
+    match id {
+        TOGGLE_SELECT => toggle_select(handle)?,
+        PAUSE_15_MINUTES => pause_select(handle, Duration::from_secs(15 * 60)),
+        PAUSE_1_HOUR => pause_select(handle, Duration::from_secs(60 * 60)),
+        RESUME_SELECT => resume_select(handle),
+        OPEN_CHAT => {
+            WindowManager::show(handle, WindowKind::Chat)?;
+        }
+        OPEN_SETTINGS => {
+            WindowManager::show(handle, WindowKind::Settings)?;
+        }
+        COPY_LAST_ANSWER => {
+            let answer = state.last_answer.read().clone();
+            crate::select::copy_content(answer)?;
+        }
+        RESUME_AUTO_INPUT => crate::easy_thing::resume_auto_input(handle),
+        CANCEL_AUTO_INPUT => crate::easy_thing::cancel_auto_input(handle),
+        QUIT => handle.exit(0),
+        _ => {
+            if let Some(index) = id
+                .strip_prefix(RECENT_SELECTION)
+                .and_then(|index| index.parse::<usize>().ok())
+            {
+                let selection = state.recent_selections.read().get(index).cloned();
+                if let Some(selection) = selection {
+                    WindowManager::show_chat(handle, Some(selection))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_click_system_tray(handle: &AppHandle, event: SystemTrayEvent) {
+    let result = match event {
+        SystemTrayEvent::LeftClick { .. } if !cfg!(target_os = "macos") => {
+            WindowManager::show(handle, WindowKind::Chat).map(|_| ())
+        }
+        SystemTrayEvent::MenuItemClick { id, .. } => {
+            tracing::info!(tray_menu_click = id);
+            click_menu_item(handle, &id)
+        }
+        _ => Ok(()),
+    };
+    if let Err(err) = result {
+        tracing::warn!(system_tray_error = ?err);
+    }
+}
@@ -8,7 +8,7 @@ import {
     IconButton, Button, ButtonGroup, Stack, Grid, MenuItem, ListItemIcon, Typography, Divider,
     TextField, useTheme, useMediaQuery, debounce,
 } from '@mui/material';
-import { Session, createSession, Message, createMessage } from './types'
+import { Session, createSession, Message, createMessage, SelectActionEvent, OpenAIRoleEnumType } from './types'
 import useStore from './store'
 import SettingWindow from './SettingWindow'
 import ChatConfigWindow from './ChatConfigWindow'
@@ -25,6 +25,8 @@ import { useTranslation } from "react-i18next";
 import icon from './icon.png'
 import { save } from '@tauri-apps/api/dialog';
 import { writeTextFile } from '@tauri-apps/api/fs';
+import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api';
 import ArrowCircleUpIcon from '@mui/icons-material/ArrowCircleUp';
 import ArrowCircleDownIcon from '@mui/icons-material/ArrowCircleDown';
 import SponsorChip from './SponsorChip'
@@ -238,6 +240,7 @@ function Main() {
         client.replay(
             store.settings.openaiKey,
             store.settings.apiHost,
+            store.settings.llmProfile,
             store.settings.maxContextSize,
             store.settings.maxTokens,
             store.settings.model,
@@ -268,17 +271,21 @@ function Main() {
         }
     }
 
-    const generate = async (session: Session, promptMsgs: Message[], targetMsg: Message) => {
+    // actionId: the selection action that asked, its answer may come from the cache
+    const generate = async (session: Session, promptMsgs: Message[], targetMsg: Message, actionId?: string) => {
         messageScrollRef.current = { msgId: targetMsg.id, smooth: false }
+        let answer = ''
         await client.replay(
             store.settings.openaiKey,
             store.settings.apiHost,
+            store.settings.llmProfile,
             store.settings.maxContextSize,
             store.settings.maxTokens,
             store.settings.model,
             store.settings.temperature,
             promptMsgs,
             ({ text, cancel }) => {
+                answer = text
                 for (let i = 0; i < session.messages.length; i++) {
                     if (session.messages[i].id === targetMsg.id) {
                         session.messages[i] = {
@@ -294,6 +301,7 @@ function Main() {
                 store.updateChatSession(session)
             },
             (err) => {
+                answer = ''
                 for (let i = 0; i < session.messages.length; i++) {
                     if (session.messages[i].id === targetMsg.id) {
                         session.messages[i] = {
@@ -306,7 +314,8 @@ function Main() {
                     }
                 }
                 store.updateChatSession(session)
-            }
+            },
+            actionId,
         )
         for (let i = 0; i < session.messages.length; i++) {
             if (session.messages[i].id === targetMsg.id) {
@@ -318,11 +327,57 @@ function Main() {
             }
         }
         store.updateChatSession(session)
+        if (answer !== '') {
+            invoke('remember_answer', { payload: answer })
+        }
 
         messageScrollRef.current = null
     }
 
     const [quoteCache, setQuoteCache] = useState('')
+    // 划词弹窗填入的提问, 原样发送时带上它的操作, 回答才能走缓存
+    const selectActionRef = useRef<SelectActionEvent | null>(null)
+
+    // 划词弹窗的操作, 把拼好的提问填入输入框
+    useEffect(() => {
+        const unlisten = listen<SelectActionEvent>('select-action', (event) => {
+            selectActionRef.current = event.payload
+            setQuoteCache(event.payload.prompt)
+        })
+        return () => {
+            unlisten.then((f) => f())
+        }
+    }, [])
+
+    // 快捷键或搜索框发来的提问, 填入输入框
+    useEffect(() => {
+        const unlisten = listen<string>('chat-question', (event) => {
+            selectActionRef.current = null
+            setQuoteCache(event.payload)
+        })
+        return () => {
+            unlisten.then((f) => f())
+        }
+    }, [])
+
+    // 监听只注册一次, 通过 ref 拿到最新的会话列表
+    const storeRef = useRef(store)
+    storeRef.current = store
+
+    // 快捷回答窗口里的对话, 在新会话中继续
+    useEffect(() => {
+        const unlisten = listen<{ role: OpenAIRoleEnumType, content: string }[]>('open-in-chat', (event) => {
+            const session = createSession()
+            session.messages = [
+                ...session.messages,
+                ...event.payload.map((msg) => createMessage(msg.role, msg.content)),
+            ]
+            storeRef.current.createChatSession(session)
+        })
+        return () => {
+            unlisten.then((f) => f())
+        }
+    }, [])
 
     const sessionListRef = useRef<HTMLDivElement>(null)
     const handleCreateNewSession = () => {
@@ -624,12 +679,15 @@ function Main() {
                                 quoteCache={quoteCache}
                                 setQuotaCache={setQuoteCache}
                                 onSubmit={async (newUserMsg: Message, needGenerating = true) => {
+                                    const selectAction = selectActionRef.current
+                                    selectActionRef.current = null
+                                    const actionId = selectAction?.prompt === newUserMsg.content ? selectAction.actionId : undefined
                                     if (needGenerating) {
                                         const promptsMsgs = [...store.currentSession.messages, newUserMsg]
                                         const newAssistantMsg = createMessage('assistant', '....')
                                         store.currentSession.messages = [...store.currentSession.messages, newUserMsg, newAssistantMsg]
                                         store.updateChatSession(store.currentSession)
-                                        generate(store.currentSession, promptsMsgs, newAssistantMsg)
+                                        generate(store.currentSession, promptsMsgs, newAssistantMsg, actionId)
                                         messageScrollRef.current = { msgId: newAssistantMsg.id, smooth: true }
                                     } else {
                                         store.currentSession.messages = [...store.currentSession.messages, newUserMsg]
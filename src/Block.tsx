@@ -1,4 +1,4 @@
-import { useState, useMemo, useCallback } from 'react';
+import { useState, useMemo, useCallback, useEffect } from 'react';
 import Avatar from '@mui/material/Avatar';
 import MenuItem from '@mui/material/MenuItem';
 import {
@@ -20,6 +20,7 @@ import { styled, alpha } from '@mui/material/styles';
 import StopIcon from '@mui/icons-material/Stop';
 import MoreVertIcon from '@mui/icons-material/MoreVert';
 import * as wordCount from './utils'
+import { invoke } from '@tauri-apps/api'
 import FormatQuoteIcon from '@mui/icons-material/FormatQuote';
 import 'github-markdown-css/github-markdown-light.css'
 import mila from 'markdown-it-link-attributes';
@@ -101,6 +102,25 @@ function _Block(props: Props) {
         props.refreshMsg();
     }, [onStop, props.refreshMsg]);
 
+    // counted by the backend with the model's own tokenizer, once the answer is complete
+    const [tokenCount, setTokenCount] = useState<number | null>(null)
+    useEffect(() => {
+        if (!props.showTokenCount || msg.generating) {
+            return
+        }
+        let stale = false
+        invoke<number>('count_tokens', { payload: { text: msg.content, model: msg.model } })
+            .then((count) => {
+                if (!stale) {
+                    setTokenCount(count)
+                }
+            })
+            .catch((err) => console.error('count tokens', err))
+        return () => {
+            stale = true
+        }
+    }, [props.showTokenCount, msg.content, msg.model, msg.generating])
+
     const tips: string[] = []
     if (props.showModelName) {
         tips.push(`model: ${props.msg.model || 'unknown'}`)
@@ -109,7 +129,7 @@ function _Block(props: Props) {
         tips.push(`word count: ${wordCount.countWord(msg.content)}`)
     }
     if (props.showTokenCount) {
-        tips.push(`token estimate: ${wordCount.estimateTokens(msg.content)}`)
+        tips.push(`token count: ${tokenCount ?? '...'}`)
     }
     return (
         <ListItem
@@ -5,6 +5,8 @@ import {
     FormGroup, FormControlLabel, Switch, Select, MenuItem, FormControl, InputLabel, Slider, Typography, Box,
 } from '@mui/material';
 import { Settings } from './types'
+import { invoke } from '@tauri-apps/api'
+import { LlmProfileSummary } from './client'
 import { getDefaultSettings } from './store'
 import ThemeChangeButton from './theme/ThemeChangeIcon';
 import { ThemeMode } from './theme/index';
@@ -94,6 +96,14 @@ export default function SettingWindow(props: Props) {
         setSettingsEdit(props.settings)
     }, [props.settings])
 
+    // profiles of the app config, requests use the api host when there are none
+    const [profiles, setProfiles] = React.useState<LlmProfileSummary[]>([]);
+    useEffect(() => {
+        if (props.open) {
+            invoke<LlmProfileSummary[]>('list_llm_profiles').then(setProfiles)
+        }
+    }, [props.open])
+
     const onCancel = () => {
         props.close()
         setSettingsEdit(props.settings)
@@ -124,6 +134,24 @@ export default function SettingWindow(props: Props) {
                     value={settingsEdit.openaiKey}
                     onChange={(e) => setSettingsEdit({ ...settingsEdit, openaiKey: e.target.value.trim() })}
                 />
+                {
+                    profiles.length > 0 && (
+                        <FormControl fullWidth variant="outlined" margin="dense">
+                            <InputLabel htmlFor="llm-profile-select">{t('llm profile')}</InputLabel>
+                            <Select
+                                label={t('llm profile')}
+                                id="llm-profile-select"
+                                value={settingsEdit.llmProfile || profiles.find((p) => p.isDefault)?.name || ''}
+                                onChange={(e) => setSettingsEdit({ ...settingsEdit, llmProfile: e.target.value })}>
+                                {profiles.map((profile) => (
+                                    <MenuItem key={profile.name} value={profile.name}>
+                                        {profile.name} ({profile.provider}{profile.model ? `, ${profile.model}` : ''})
+                                    </MenuItem>
+                                ))}
+                            </Select>
+                        </FormControl>
+                    )
+                }
                 <FormControl fullWidth variant="outlined" margin="dense">
                     <InputLabel htmlFor="language-select">{t('language')}</InputLabel>
                     <Select
@@ -1,17 +1,73 @@
 import { Message } from './types';
-import * as wordCount from './utils';
-import { createParser } from 'eventsource-parser'
+import { invoke } from '@tauri-apps/api'
+import { listen } from '@tauri-apps/api/event'
+import { v4 as uuidv4 } from 'uuid'
 
 export interface OnTextCallbackResult {
     // response content
     text: string;
-    // cancel for fetch
+    // cancel the request
     cancel: () => void;
 }
 
+// payload of the `llm-token` event
+interface ChatToken {
+    requestId: string;
+    delta: string;
+    content: string;
+    // replayed from the response cache
+    cached: boolean;
+}
+
+// error of the `chat_completion` command
+interface LlmError {
+    kind: string;
+    // auth, quota, rate_limit, context_too_long, network, timeout, server, budget_exceeded, ...
+    code: string;
+    // sending the same request again may succeed
+    retryable: boolean;
+    message?: string;
+    status?: number;
+    retryAfterMs?: number;
+}
+
+// an `LlmError` thrown by `replay`, `code` says what the user can do about it
+export class LlmRequestError extends Error {
+    code: string;
+    retryable: boolean;
+    retryAfterMs?: number;
+
+    constructor(error: LlmError) {
+        super(error.message ? `${error.code}: ${error.message}` : error.code)
+        this.code = error.code
+        this.retryable = error.retryable
+        this.retryAfterMs = error.retryAfterMs
+    }
+}
+
+// a profile of the app config as listed by `list_llm_profiles`
+export interface LlmProfileSummary {
+    name: string;
+    provider: string;
+    model: string;
+    isDefault: boolean;
+}
+
+// the chosen profile, else the default one, with the model of the profile;
+// the api host and model of the settings only when the app config has no profiles
+async function backendOf(apiKey: string, host: string, model: string, profile?: string) {
+    const profiles = await invoke<LlmProfileSummary[]>('list_llm_profiles')
+    if (profiles.length === 0) {
+        return { host, apiKey, model }
+    }
+    const chosen = profiles.find((p) => p.name === profile) ?? profiles.find((p) => p.isDefault)
+    return { profile: chosen?.name, model: chosen?.model ?? '' }
+}
+
 export async function replay(
     apiKey: string,
     host: string,
+    profile: string | undefined,
     maxContextSize: string,
     maxTokens: string,
     modelName: string,
@@ -19,125 +75,63 @@ export async function replay(
     msgs: Message[],
     onText?: (option: OnTextCallbackResult) => void,
     onError?: (error: Error) => void,
+    // answers of selection actions may come from the response cache
+    actionId?: string,
 ) {
     if (msgs.length === 0) {
         throw new Error('No messages to replay')
     }
-    const head = msgs[0].role === 'system' ? msgs[0] : undefined
-    if (head) {
-        msgs = msgs.slice(1)
-    }
-
     const maxTokensNumber = Number(maxTokens)
+    // the backend counts tokens with the model's tokenizer and drops the
+    // oldest messages that don't fit, keeping the system prompt
     const maxLen = Number(maxContextSize)
-    let totalLen = head ? wordCount.estimateTokens(head.content) : 0
-
-    let prompts: Message[] = []
-    for (let i = msgs.length - 1; i >= 0; i--) {
-        const msg = msgs[i]
-        const msgTokenSize: number = wordCount.estimateTokens(msg.content) + 200 // 200 作为预估的误差补偿
-        if (msgTokenSize + totalLen > maxLen) {
-            break
-        }
-        prompts = [msg, ...prompts]
-        totalLen += msgTokenSize
-    }
-    if (head) {
-        prompts = [head, ...prompts]
-    }
+    const maxContextTokens = maxLen > 0 ? maxLen : undefined
 
-    // fetch has been canceled
+    // request has been canceled
     let hasCancel = false;
-    // abort signal for fetch
-    const controller = new AbortController();
+    // the backend streams the answer, so the proxy of the app config applies
+    const requestId = uuidv4()
     const cancel = () => {
         hasCancel = true;
-        controller.abort();
+        invoke('cancel_chat_completion', { payload: requestId })
     };
 
-    let fullText = '';
+    const unlisten = await listen<ChatToken>('llm-token', (event) => {
+        if (event.payload.requestId !== requestId || hasCancel) {
+            return
+        }
+        if (onText) {
+            onText({ text: event.payload.content, cancel })
+        }
+    })
     try {
-        const messages = prompts.map(msg => ({ role: msg.role, content: msg.content }))
-        const response = await fetch(`${host}/v1/chat/completions`, {
-            method: 'POST',
-            headers: {
-                'Authorization': `Bearer ${apiKey}`,
-                'Content-Type': 'application/json',
-            },
-            body: JSON.stringify({
+        const messages = msgs.map(msg => ({ role: msg.role, content: msg.content }))
+        const backend = await backendOf(apiKey, host, modelName, profile)
+        return await invoke<string>('chat_completion', {
+            payload: {
+                requestId,
+                ...backend,
                 messages,
-                model: modelName,
-                max_tokens: maxTokensNumber,
+                maxTokens: maxTokensNumber,
+                maxContextTokens,
+                actionId,
                 temperature,
-                stream: true,
-            }),
-            signal: controller.signal,
-        });
-        await handleSSE(response, (message) => {
-            if (message === '[DONE]') {
-                return;
-            }
-            const data = JSON.parse(message)
-            if (data.error) {
-                throw new Error(`Error from OpenAI: ${JSON.stringify(data)}`)
-            }
-            const text = data.choices[0]?.delta?.content
-            if (text !== undefined) {
-                fullText += text
-                if (onText) {
-                    onText({ text: fullText, cancel })
-                }
-            }
+            },
         })
-    } catch (error) {
+    } catch (e) {
         // if a cancellation is performed
         // do not throw an exception
         // otherwise the content will be overwritten.
         if (hasCancel) {
             return;
         }
+        const llmError = e as LlmError
+        const error = llmError.code ? new LlmRequestError(llmError) : new Error(String(e))
         if (onError) {
-            onError(error as any)
+            onError(error)
         }
         throw error
-    }
-    return fullText
-}
-
-export async function handleSSE(response: Response, onMessage: (message: string) => void) {
-    if (!response.ok) {
-        const error = await response.json().catch(() => null)
-        throw new Error(error ? JSON.stringify(error) : `${response.status} ${response.statusText}`)
-    }
-    if (response.status !== 200) {
-        throw new Error(`Error from OpenAI: ${response.status} ${response.statusText}`)
-    }
-    if (!response.body) {
-        throw new Error('No response body')
-    }
-    const parser = createParser((event) => {
-        if (event.type === 'event') {
-            onMessage(event.data)
-        }
-    })
-    for await (const chunk of iterableStreamAsync(response.body)) {
-        const str = new TextDecoder().decode(chunk)
-        parser.feed(str)
-    }
-}
-
-export async function* iterableStreamAsync(stream: ReadableStream): AsyncIterableIterator<Uint8Array> {
-    const reader = stream.getReader();
-    try {
-        while (true) {
-            const { value, done } = await reader.read()
-            if (done) {
-                return
-            } else {
-                yield value
-            }
-        }
     } finally {
-        reader.releaseLock()
+        unlisten()
     }
 }
@@ -0,0 +1,192 @@
+import { useEffect, useRef, useState } from "react";
+import { createRoot } from "react-dom/client";
+import { Button, TextField } from "@mui/material";
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+import MarkdownIt from "markdown-it";
+import "github-markdown-css/github-markdown-light.css";
+import * as client from "./client";
+import { readSettings } from "./store";
+import { Message, Settings, createMessage } from "./types";
+import "./quick_answer.css";
+
+const md = new MarkdownIt({ linkify: true, breaks: true });
+
+interface QuickAnswerQuestion {
+  question: string;
+  // paste the answer over the selection once it is complete
+  replace: boolean;
+  // the selection action that asked, its answer may come from the cache
+  actionId?: string;
+}
+
+export function QuickAnswer() {
+  const [settings, setSettings] = useState<Settings>();
+  const [messages, setMessages] = useState<Message[]>([]);
+  const [followUp, setFollowUp] = useState("");
+  const [error, setError] = useState("");
+  const [generating, setGenerating] = useState(false);
+  const [replace, setReplace] = useState(false);
+  const cancelRef = useRef<() => void>();
+
+  const ask = async (
+    settings: Settings,
+    question: string,
+    history: Message[],
+    actionId?: string
+  ): Promise<string | undefined> => {
+    cancelRef.current?.();
+    const userMsg = createMessage("user", question);
+    const promptMsgs = [...history, userMsg];
+    const answerMsg = createMessage("assistant", "");
+    setMessages([...promptMsgs, answerMsg]);
+    setError("");
+    setGenerating(true);
+    let answer = "";
+    try {
+      await client.replay(
+        settings.openaiKey,
+        settings.apiHost,
+        settings.llmProfile,
+        settings.maxContextSize,
+        settings.maxTokens,
+        settings.model,
+        settings.temperature,
+        promptMsgs,
+        ({ text, cancel }) => {
+          cancelRef.current = cancel;
+          answer = text;
+          setMessages([...promptMsgs, { ...answerMsg, content: text }]);
+        },
+        undefined,
+        actionId,
+      );
+    } catch (e) {
+      setError(String(e));
+      return undefined;
+    } finally {
+      setGenerating(false);
+    }
+    invoke("remember_answer", { payload: answer });
+    return answer;
+  };
+
+  const replaceSelection = (answer: string) => {
+    invoke("replace_selection", { payload: answer }).catch((e) => setError(String(e)));
+  };
+
+  const start = async (settings: Settings, question: QuickAnswerQuestion) => {
+    setReplace(question.replace);
+    const answer = await ask(settings, question.question, [], question.actionId);
+    if (question.replace && answer && answer.trim() !== "") {
+      replaceSelection(answer);
+    }
+  };
+
+  useEffect(() => {
+    let currentSettings: Settings | undefined;
+    readSettings().then(async (settings) => {
+      currentSettings = settings;
+      setSettings(settings);
+      const question = await invoke<QuickAnswerQuestion>("get_quick_answer_question");
+      if (question.question.trim() !== "") {
+        start(settings, question);
+      }
+    });
+    const unlisten = listen<QuickAnswerQuestion>("quick-answer-question", (event) => {
+      if (currentSettings) {
+        start(currentSettings, event.payload);
+      }
+    });
+    return () => {
+      cancelRef.current?.();
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const lastAnswer = (): string => {
+    const last = messages[messages.length - 1];
+    return last && last.role === "assistant" ? last.content : "";
+  };
+
+  const copyAnswer = () => {
+    invoke("copy_select_content", { payload: lastAnswer() });
+  };
+
+  const insertAnswer = () => {
+    invoke("insert_quick_answer", { payload: { response: lastAnswer() } });
+  };
+
+  const sendFollowUp = () => {
+    if (!settings || followUp.trim() === "" || generating) {
+      return;
+    }
+    ask(settings, followUp, messages);
+    setFollowUp("");
+  };
+
+  const openInChat = () => {
+    cancelRef.current?.();
+    const payload = messages.map((msg) => ({ role: msg.role, content: msg.content }));
+    invoke("open_quick_answer_in_chat", { payload: payload });
+  };
+
+  return (
+    <div className="quick-answer">
+      <div className="quick-answer-messages">
+        {messages.map((msg) =>
+          msg.role === "user" ? (
+            <div className="quick-answer-question" key={msg.id}>
+              {msg.content}
+            </div>
+          ) : (
+            <div
+              className="markdown-body"
+              key={msg.id}
+              dangerouslySetInnerHTML={{ __html: md.render(msg.content) }}
+            />
+          )
+        )}
+        {error && <div className="quick-answer-error">{error}</div>}
+      </div>
+      <div className="quick-answer-follow-up">
+        <TextField
+          size="small"
+          fullWidth
+          placeholder="追问"
+          value={followUp}
+          onChange={(event) => setFollowUp(event.target.value)}
+          onKeyDown={(event) => {
+            if (event.key === "Enter") {
+              event.preventDefault();
+              sendFollowUp();
+            }
+          }}
+        />
+      </div>
+      <div className="quick-answer-actions">
+        <Button size="small" disabled={generating} onClick={copyAnswer}>
+          复制
+        </Button>
+        <Button size="small" disabled={generating} onClick={insertAnswer}>
+          插入
+        </Button>
+        {replace && (
+          <Button size="small" disabled={generating} onClick={() => replaceSelection(lastAnswer())}>
+            替换
+          </Button>
+        )}
+        <Button size="small" onClick={openInChat}>
+          在聊天中打开
+        </Button>
+        <Button size="small" onClick={() => invoke("close_window")}>
+          关闭
+        </Button>
+      </div>
+    </div>
+  );
+}
+
+const root = createRoot(document.getElementById("quick-answer")!);
+
+root.render(<QuickAnswer />);
@@ -0,0 +1,55 @@
+import { useEffect, useRef, useState } from "react";
+import { createRoot } from "react-dom/client";
+import { TextField } from "@mui/material";
+import { invoke } from "@tauri-apps/api";
+import { appWindow } from "@tauri-apps/api/window";
+
+// 搜索框: 输入问题后回车, 在快捷回答窗口中提问
+export function Search() {
+  const [question, setQuestion] = useState("");
+  const inputRef = useRef<HTMLInputElement>(null);
+
+  useEffect(() => {
+    const unlisten = appWindow.onFocusChanged(({ payload: focused }) => {
+      if (focused) {
+        inputRef.current?.focus();
+      } else {
+        invoke("close_window");
+      }
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const submit = () => {
+    if (question.trim() === "") {
+      return;
+    }
+    invoke("run_quick_answer", { payload: { question: question } });
+    setQuestion("");
+  };
+
+  return (
+    <TextField
+      autoFocus
+      fullWidth
+      inputRef={inputRef}
+      placeholder="提问"
+      value={question}
+      onChange={(event) => setQuestion(event.target.value)}
+      onKeyDown={(event) => {
+        if (event.key === "Enter") {
+          event.preventDefault();
+          submit();
+        } else if (event.key === "Escape") {
+          invoke("close_window");
+        }
+      }}
+    />
+  );
+}
+
+const root = createRoot(document.getElementById("search")!);
+
+root.render(<Search />);
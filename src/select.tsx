@@ -18,61 +18,48 @@ import "./select.css";
 import { createRoot } from "react-dom/client";
 import SelectInput from "@mui/material/Select/SelectInput";
 
-interface Mode {
+interface SelectAction {
+  id: string;
   label: string;
+  icon: string;
   prompt: string;
-  //icon: React.ReactElement;
-  icon: React.ReactNode;
-  click: (mode: Mode) => void;
+  target: "chat" | "inline_answer" | "replace" | "copy";
+  enabled: boolean;
 }
 
+const icons: { [name: string]: React.ReactNode } = {
+  EmojiPeople: <EmojiPeople className="icon" />,
+  Comment: <Comment className="icon" />,
+  Compare: <Compare className="icon" />,
+  Brush: <Brush className="icon" />,
+  FileCopy: <FileCopy className="icon" />,
+};
+
+const renderIcon = (name: string) => icons[name] || <More className="icon" />;
+
 
  export function Select() {
   const [selectedContent, setselectedContent] = useState<string>("");
   const [messageApi, contextHolder] = message.useMessage();
-  // <span className="mui-icon mui-icon-paperplane"></span>
-  const [modes, setModes] = useState<Mode[]>([
-    {
-      label: "提问",
-      prompt: "",
-      icon: <EmojiPeople className="icon" />,
-      click: (mode: Mode) => {
-        triggerSelectClick(mode.label, mode.prompt);
-      },
-    },
-    {
-      label: "解释",
-      prompt: "请帮我解释这段文字：",
-      icon: <Comment className="icon" />,
-      click: (mode: Mode) => {
-        triggerSelectClick(mode.label, mode.prompt);
-      },
-    },
-    {
-      label: "翻译",
-      prompt: "请帮我翻译这段文字：",
-      icon: <Compare className="icon" />,
-      click: (mode: Mode) => {
-        triggerSelectClick(mode.label, mode.prompt);
-      },
-    },
-    {
-      label: "润色",
-      prompt: "帮我美化或者优化这段文字：",
-      icon: <Brush className="icon" />,
-      click: (mode: Mode) => {
-        triggerSelectClick(mode.label, mode.prompt);
-      },
-    },
-    {
-      label: "复制",
-      prompt: "",
-      icon: <FileCopy className="icon" />,
-      click: (mode: Mode) => {
-        copySelectContent();
-      },
-    },
-  ]);
+  const [actions, setActions] = useState<SelectAction[]>([]);
+
+  useEffect(() => {
+    invoke<SelectAction[]>("list_actions").then(setActions);
+    const unlisten = listen<SelectAction[]>("actions-changed", (event) => {
+      setActions(event.payload);
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const clickAction = (action: SelectAction) => {
+    if (action.target === "copy") {
+      copySelectContent();
+    } else {
+      triggerSelectClick(action.id);
+    }
+  };
 
   const getSelectedContent = async (): Promise<string> => {
     try {
@@ -90,12 +77,11 @@ interface Mode {
     getSelectedContent();
   }, []);
 
-  const triggerSelectClick = async (label: string, prompt: string) => {
+  const triggerSelectClick = async (id: string) => {
     const selectedContent = await getSelectedContent();
     console.log("trigger select click", selectedContent);
     const payload = {
-      label: label,
-      prompt: prompt,
+      id: id,
       selected: selectedContent,
     };
     invoke("trigger_select_click", { payload: payload });
@@ -134,14 +120,14 @@ interface Mode {
   return (
     <div className="container">
       <div className="select-container">
-      {modes.map((item) => {
+      {actions.filter((item) => item.enabled).map((item) => {
         return (
           <div
             className="select-item"
-            key={item.label}
-            onClick={() => item.click(item)}
+            key={item.id}
+            onClick={() => clickAction(item)}
           >
-            {item.icon}
+            {renderIcon(item.icon)}
             <span className="select-text">{item.label}</span>
           </div>
         );
@@ -0,0 +1,43 @@
+import { useEffect, useState } from "react";
+import { createRoot } from "react-dom/client";
+import { invoke } from "@tauri-apps/api";
+import SettingWindow from "./SettingWindow";
+import { ThemeSwitcherProvider } from "./theme/ThemeSwitcher";
+import { readSettings, writeSettings } from "./store";
+import { Settings } from "./types";
+import "./i18n.js";
+
+// 独立的设置窗口, 复用主窗口的设置对话框
+export function Setting() {
+  const [settings, setSettings] = useState<Settings>();
+
+  useEffect(() => {
+    readSettings().then(setSettings);
+  }, []);
+
+  const close = () => {
+    invoke("close_window");
+  };
+
+  if (!settings) {
+    return null;
+  }
+  return (
+    <ThemeSwitcherProvider>
+      <SettingWindow
+        open={true}
+        settings={settings}
+        save={async (settings) => {
+          await writeSettings(settings);
+          setSettings(settings);
+          close();
+        }}
+        close={close}
+      />
+    </ThemeSwitcherProvider>
+  );
+}
+
+const root = createRoot(document.getElementById("settings")!);
+
+root.render(<Setting />);
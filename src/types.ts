@@ -37,9 +37,19 @@ export function createSession(name: string = "Untitled"): Session {
     }
 }
 
+// payload of the `select-action` event sent by the select popup
+export interface SelectActionEvent {
+    actionId: string
+    label: string
+    prompt: string
+    selection: string
+}
+
 export interface Settings {
     openaiKey: string
     apiHost: string
+    // profile of the app config, its default one when empty
+    llmProfile?: string
     model: string
     maxContextSize: string
     temperature: number
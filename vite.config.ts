@@ -27,7 +27,7 @@ export default defineConfig({
         // produce sourcemaps for debug builds
         sourcemap: !!process.env.TAURI_DEBUG,
         rollupOptions: {
-            input: ['src/index.html', 'src/select.html', 'src/*.*'],
+            input: ['src/index.html', 'src/select.html', 'src/quick_answer.html', 'src/settings.html', 'src/search.html', 'src/*.*'],
             output: {
                 dir: 'dist',
             },